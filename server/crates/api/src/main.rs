@@ -10,12 +10,13 @@ use endpoints::state::AppState;
 use h3_mapper::h3_client::H3Client;
 use houses::house_client::HouseClient;
 use hyper::Request;
+use maps::maps_provider::maps_provider_from_env;
 use std::env;
 use std::error::Error;
+use std::path::Path;
 use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
-use tracing_subscriber;
 use utoipa_swagger_ui::SwaggerUi;
 
 #[tokio::main]
@@ -28,13 +29,24 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         .init();
 
     let db_client = Box::new(DynamoDbClient::new().await?);
-    let h3_client = H3Client::new();
+    let h3_client = match env::var("CITY_BOUNDARIES_DIR") {
+        Ok(path) => H3Client::from_directory(Path::new(&path))?,
+        Err(_) => H3Client::new()?,
+    };
     let mut house_client = HouseClient::new();
     house_client.load_data()?;
+    let maps_client = match maps_provider_from_env() {
+        Ok(maps_client) => Some(maps_client),
+        Err(error) => {
+            tracing::warn!(error = %error, "Exact routes are disabled");
+            None
+        }
+    };
     let app_state = Arc::new(AppState {
         db_client,
         house_client,
         h3_client,
+        maps_client,
     });
 
     let trace_layer =
@@ -53,24 +53,47 @@ impl AttributeValueParser for i32 {
     }
 }
 
-impl AttributeValueParser for f64 {
+impl AttributeValueParser for Option<i32> {
+    fn parse(value: Option<&AttributeValue>) -> Result<Self, Error> {
+        match value {
+            None => Ok(None),
+            Some(attr_value) => Ok(Some(i32::parse(Some(attr_value))?)),
+        }
+    }
+}
+
+impl AttributeValueParser for i64 {
     fn parse(value: Option<&AttributeValue>) -> Result<Self, Error> {
         let value = value.ok_or(anyhow::anyhow!("Key not found"))?;
         let result = value
-            .as_s()
+            .as_n()
             .map_err(|_| anyhow::anyhow!("Expected number"))?
-            .parse::<f64>()
+            .parse::<i64>()
             .map_err(|_| anyhow::anyhow!("Could not parse number"))?;
         Ok(result)
     }
 }
+
+// Coordinates used to be written as strings, so those are still read
+impl AttributeValueParser for f64 {
+    fn parse(value: Option<&AttributeValue>) -> Result<Self, Error> {
+        let value = value.ok_or(anyhow::anyhow!("Key not found"))?;
+        let result = match value {
+            AttributeValue::N(number) | AttributeValue::S(number) => number,
+            _ => return Err(anyhow::anyhow!("Expected number")),
+        }
+        .parse::<f64>()
+        .map_err(|_| anyhow::anyhow!("Could not parse number"))?;
+        Ok(result)
+    }
+}
+
 impl AttributeValueParser for bool {
     fn parse(value: Option<&AttributeValue>) -> Result<Self, Error> {
         let value = value.ok_or(anyhow::anyhow!("Key not found"))?;
-        let result = value
+        let result = *value
             .as_bool()
-            .map_err(|_| anyhow::anyhow!("Expected bool"))?
-            .clone();
+            .map_err(|_| anyhow::anyhow!("Expected bool"))?;
         Ok(result)
     }
 }
@@ -100,6 +123,65 @@ impl AttributeValueParser for Uuid {
     }
 }
 
+// The inverse of AttributeValueParser. None leaves the attribute out of the item.
+pub trait AttributeValueSerialiser {
+    fn serialise(&self) -> Option<AttributeValue>;
+}
+
+impl AttributeValueSerialiser for str {
+    fn serialise(&self) -> Option<AttributeValue> {
+        Some(AttributeValue::S(self.to_string()))
+    }
+}
+
+impl AttributeValueSerialiser for String {
+    fn serialise(&self) -> Option<AttributeValue> {
+        self.as_str().serialise()
+    }
+}
+
+impl<T: AttributeValueSerialiser> AttributeValueSerialiser for Option<T> {
+    fn serialise(&self) -> Option<AttributeValue> {
+        self.as_ref().and_then(T::serialise)
+    }
+}
+
+impl AttributeValueSerialiser for i32 {
+    fn serialise(&self) -> Option<AttributeValue> {
+        Some(AttributeValue::N(self.to_string()))
+    }
+}
+
+impl AttributeValueSerialiser for i64 {
+    fn serialise(&self) -> Option<AttributeValue> {
+        Some(AttributeValue::N(self.to_string()))
+    }
+}
+
+impl AttributeValueSerialiser for f64 {
+    fn serialise(&self) -> Option<AttributeValue> {
+        Some(AttributeValue::N(self.to_string()))
+    }
+}
+
+impl AttributeValueSerialiser for bool {
+    fn serialise(&self) -> Option<AttributeValue> {
+        Some(AttributeValue::Bool(*self))
+    }
+}
+
+impl AttributeValueSerialiser for DateTime<Utc> {
+    fn serialise(&self) -> Option<AttributeValue> {
+        Some(AttributeValue::S(self.format(DATETIME_FORMAT).to_string()))
+    }
+}
+
+impl AttributeValueSerialiser for Uuid {
+    fn serialise(&self) -> Option<AttributeValue> {
+        Some(AttributeValue::S(self.to_string()))
+    }
+}
+
 pub fn single<T>(vec: Vec<T>) -> Result<T, Error> {
     if vec.len() == 1 {
         Ok(vec.into_iter().next().unwrap())
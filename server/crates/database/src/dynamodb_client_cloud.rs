@@ -5,9 +5,21 @@ use async_trait::async_trait;
 use aws_config::meta::region::RegionProviderChain;
 use aws_config::{self, BehaviorVersion};
 use aws_sdk_dynamodb::operation::query::{QueryInput, QueryOutput};
-use aws_sdk_dynamodb::types::{ItemResponse, TransactGetItem, TransactWriteItem};
+use aws_sdk_dynamodb::operation::scan::{ScanInput, ScanOutput};
+use aws_sdk_dynamodb::types::{
+    AttributeValue, DeleteRequest, ItemResponse, KeysAndAttributes, PutRequest, TransactGetItem,
+    TransactWriteItem, WriteRequest,
+};
 use aws_sdk_dynamodb::{config::Region, Client};
+use std::collections::HashMap;
 use std::env;
+use std::time::Duration;
+
+// DynamoDB's limits on a single BatchGetItem and BatchWriteItem
+const BATCH_GET_SIZE: usize = 100;
+const BATCH_WRITE_SIZE: usize = 25;
+const MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(50);
 
 pub struct DynamoDbClient {
     client: Client,
@@ -18,13 +30,40 @@ impl DynamoDbClient {
         let region_name = env::var("AWS_REGION")?;
         let region_provider =
             RegionProviderChain::first_try(Region::new(region_name)).or_default_provider();
-        let config = aws_config::defaults(BehaviorVersion::latest())
-            .region(region_provider)
-            .load()
-            .await;
+        let mut loader = aws_config::defaults(BehaviorVersion::latest()).region(region_provider);
+        // For DynamoDB Local, e.g. http://localhost:8000
+        if let Ok(endpoint_url) = env::var("DYNAMODB_ENDPOINT_URL") {
+            loader = loader.endpoint_url(endpoint_url);
+        }
+        let config = loader.load().await;
         let client = Client::new(&config);
         Ok(DynamoDbClient { client })
     }
+
+    // Batch writes can't be conditional, so conditions are rejected rather than dropped
+    fn write_request(item: &TransactWriteItem) -> Result<(String, WriteRequest), Error> {
+        if let Some(put) = &item.put {
+            if put.condition_expression.is_some() {
+                return Err(anyhow::anyhow!("Batch writes can't have conditions"));
+            }
+            let request = PutRequest::builder()
+                .set_item(Some(put.item.clone()))
+                .build()?;
+            let request = WriteRequest::builder().put_request(request).build();
+            Ok((put.table_name.clone(), request))
+        } else if let Some(delete) = &item.delete {
+            if delete.condition_expression.is_some() {
+                return Err(anyhow::anyhow!("Batch writes can't have conditions"));
+            }
+            let request = DeleteRequest::builder()
+                .set_key(Some(delete.key.clone()))
+                .build()?;
+            let request = WriteRequest::builder().delete_request(request).build();
+            Ok((delete.table_name.clone(), request))
+        } else {
+            Err(anyhow::anyhow!("Only Put/Delete is supported"))
+        }
+    }
 }
 
 #[async_trait]
@@ -86,4 +125,119 @@ impl IDynamoDbClient for DynamoDbClient {
 
         Ok(query_output)
     }
+    // Chunked to DynamoDB's limit, and unprocessed keys are retried with exponential backoff
+    async fn batch_get(
+        &self,
+        items: Vec<TransactGetItem>,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+        let mut results = vec![];
+        for chunk in items.chunks(BATCH_GET_SIZE) {
+            let mut keys: HashMap<String, Vec<HashMap<String, AttributeValue>>> = HashMap::new();
+            for item in chunk {
+                let get = item
+                    .get
+                    .as_ref()
+                    .ok_or(anyhow::anyhow!("Only Gets are supported"))?;
+                if get.projection_expression.is_some() {
+                    return Err(anyhow::anyhow!("Batch gets can't have projections"));
+                }
+                keys.entry(get.table_name.clone())
+                    .or_default()
+                    .push(get.key.clone());
+            }
+            let mut request_items = keys
+                .into_iter()
+                .map(|(table_name, keys)| {
+                    let keys = KeysAndAttributes::builder().set_keys(Some(keys)).build()?;
+                    Ok((table_name, keys))
+                })
+                .collect::<Result<HashMap<_, _>, Error>>()?;
+            let mut backoff = INITIAL_BACKOFF;
+            let mut attempt = 0;
+            loop {
+                let output = self
+                    .client
+                    .batch_get_item()
+                    .set_request_items(Some(request_items))
+                    .send()
+                    .await?;
+                results.extend(output.responses.unwrap_or_default().into_values().flatten());
+                request_items = output.unprocessed_keys.unwrap_or_default();
+                if request_items.is_empty() {
+                    break;
+                }
+                if attempt >= MAX_RETRIES {
+                    return Err(anyhow::anyhow!(
+                        "Keys were still unprocessed after {} retries",
+                        MAX_RETRIES
+                    ));
+                }
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+        Ok(results)
+    }
+
+    // Chunked to DynamoDB's limit, and unprocessed items are retried with exponential backoff
+    async fn batch_write(&self, items: Vec<TransactWriteItem>) -> Result<(), Error> {
+        for chunk in items.chunks(BATCH_WRITE_SIZE) {
+            let mut request_items: HashMap<String, Vec<WriteRequest>> = HashMap::new();
+            for item in chunk {
+                let (table_name, request) = Self::write_request(item)?;
+                request_items.entry(table_name).or_default().push(request);
+            }
+            let mut backoff = INITIAL_BACKOFF;
+            let mut attempt = 0;
+            loop {
+                let output = self
+                    .client
+                    .batch_write_item()
+                    .set_request_items(Some(request_items))
+                    .send()
+                    .await?;
+                request_items = output.unprocessed_items.unwrap_or_default();
+                if request_items.is_empty() {
+                    break;
+                }
+                if attempt >= MAX_RETRIES {
+                    return Err(anyhow::anyhow!(
+                        "Items were still unprocessed after {} retries",
+                        MAX_RETRIES
+                    ));
+                }
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+        Ok(())
+    }
+
+    async fn scan(&self, scan: ScanInput) -> Result<ScanOutput, Error> {
+        let scan_output = self
+            .client
+            .scan()
+            .set_table_name(scan.table_name)
+            .set_index_name(scan.index_name)
+            .set_attributes_to_get(scan.attributes_to_get)
+            .set_limit(scan.limit)
+            .set_select(scan.select)
+            .set_scan_filter(scan.scan_filter)
+            .set_conditional_operator(scan.conditional_operator)
+            .set_exclusive_start_key(scan.exclusive_start_key)
+            .set_return_consumed_capacity(scan.return_consumed_capacity)
+            .set_total_segments(scan.total_segments)
+            .set_segment(scan.segment)
+            .set_projection_expression(scan.projection_expression)
+            .set_filter_expression(scan.filter_expression)
+            .set_expression_attribute_names(scan.expression_attribute_names)
+            .set_expression_attribute_values(scan.expression_attribute_values)
+            .set_consistent_read(scan.consistent_read)
+            .send()
+            .await?;
+
+        Ok(scan_output)
+    }
 }
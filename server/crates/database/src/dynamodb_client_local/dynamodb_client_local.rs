@@ -1,33 +1,48 @@
-use super::super::attribute_value_parser::{self, parse_attribute_value};
+use super::super::attribute_value_parser;
 use super::super::dynamodb_client_trait::IDynamoDbClient;
+use super::expression::{parse_projection, Comparator, Expression, Operand};
+use super::table::{
+    FakeItem, FakeTable, KeySchema, KeyValue, PrimaryKey, GEOCODE_CACHE_SCHEMA, HOUSES_SCHEMA,
+    REQUIREMENTS_SCHEMA, SPATIAL_DISTANCES_SCHEMA,
+};
 use anyhow::Error;
 use async_trait::async_trait;
 use aws_sdk_dynamodb::operation::query::{QueryInput, QueryOutput};
+use aws_sdk_dynamodb::operation::scan::{ScanInput, ScanOutput};
 use aws_sdk_dynamodb::operation::transact_get_items::builders::TransactGetItemsOutputBuilder;
-use aws_sdk_dynamodb::types::{
-    AttributeValue, Delete, ItemResponse, Put, TransactGetItem, TransactWriteItem,
-};
+use aws_sdk_dynamodb::types::{AttributeValue, ItemResponse, TransactGetItem, TransactWriteItem};
 use csv::ReaderBuilder;
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Mutex, RwLock};
 
-const KEY_JOIN_STR: &str = ":";
+// An item's place in an index, as its index key then its table key
+type Position = (PrimaryKey, PrimaryKey);
 
-pub struct FakeItem {
-    pub hash_map: HashMap<String, AttributeValue>,
+// The parts of a Query or Scan applied after choosing which items to read
+struct ReadOptions {
+    filter: Option<Expression>,
+    projection: Option<Vec<String>>,
+    limit: usize,
+    forward: bool,
 }
 
-type FakeTable = HashMap<String, FakeItem>;
+struct Page {
+    items: Vec<HashMap<String, AttributeValue>>,
+    scanned_count: usize,
+    last_evaluated_key: Option<HashMap<String, AttributeValue>>,
+}
 
 pub struct DynamoDbClient {
     requirements_table: RwLock<FakeTable>,
     spatial_distances_table: RwLock<FakeTable>,
     houses_table: RwLock<FakeTable>,
+    geocode_cache_table: RwLock<FakeTable>,
+    transaction_lock: Mutex<()>,
 }
 
 impl DynamoDbClient {
     pub async fn new() -> Result<Self, Error> {
-        let requirements_table = RwLock::new(HashMap::new());
+        let requirements_table = RwLock::new(FakeTable::new(REQUIREMENTS_SCHEMA));
         let spatial_distances_items = DynamoDbClient::load_spatial_distances_data()?;
         let spatial_distances_table = RwLock::new(spatial_distances_items);
         let houses_items = DynamoDbClient::load_houses_data()?;
@@ -36,13 +51,15 @@ impl DynamoDbClient {
             requirements_table,
             spatial_distances_table,
             houses_table,
+            geocode_cache_table: RwLock::new(FakeTable::new(GEOCODE_CACHE_SCHEMA)),
+            transaction_lock: Mutex::new(()),
         })
     }
 
     fn load_spatial_distances_data() -> Result<FakeTable, Error> {
         let csv_data = include_str!("spatial_distances.csv");
         let mut reader = ReaderBuilder::new().from_reader(csv_data.as_bytes());
-        let mut items = HashMap::new();
+        let mut table = FakeTable::new(SPATIAL_DISTANCES_SCHEMA);
         for result in reader.records() {
             let record = result?;
             let source_index = record[0].to_string();
@@ -52,42 +69,35 @@ impl DynamoDbClient {
             let duration_drive = (record[4].parse::<f64>()?.round() as i32).to_string();
             let duration_transit = (record[5].parse::<f64>()?.round() as i32).to_string();
             let duration_walk = (record[6].parse::<f64>()?.round() as i32).to_string();
-            let item = FakeItem {
-                hash_map: HashMap::from([
-                    (
-                        "SourceIndex".to_string(),
-                        AttributeValue::S(source_index.clone()),
-                    ),
-                    (
-                        "DestinationIndex".to_string(),
-                        AttributeValue::S(destination_index.clone()),
-                    ),
-                    ("CityCode".to_string(), AttributeValue::S(city_code)),
-                    (
-                        "DurationCycle".to_string(),
-                        AttributeValue::N(duration_cycle),
-                    ),
-                    (
-                        "DurationDrive".to_string(),
-                        AttributeValue::N(duration_drive),
-                    ),
-                    (
-                        "DurationTransit".to_string(),
-                        AttributeValue::N(duration_transit),
-                    ),
-                    ("DurationWalk".to_string(), AttributeValue::N(duration_walk)),
-                ]),
-            };
-            let key = format!("{}{}{}", source_index, KEY_JOIN_STR, destination_index);
-            items.insert(key, item);
+            table.insert(HashMap::from([
+                ("SourceIndex".to_string(), AttributeValue::S(source_index)),
+                (
+                    "DestinationIndex".to_string(),
+                    AttributeValue::S(destination_index),
+                ),
+                ("CityCode".to_string(), AttributeValue::S(city_code)),
+                (
+                    "DurationCycle".to_string(),
+                    AttributeValue::N(duration_cycle),
+                ),
+                (
+                    "DurationDrive".to_string(),
+                    AttributeValue::N(duration_drive),
+                ),
+                (
+                    "DurationTransit".to_string(),
+                    AttributeValue::N(duration_transit),
+                ),
+                ("DurationWalk".to_string(), AttributeValue::N(duration_walk)),
+            ]))?;
         }
-        Ok(items)
+        Ok(table)
     }
 
     fn load_houses_data() -> Result<FakeTable, Error> {
         let csv_data = include_str!("houses.csv");
         let mut reader = ReaderBuilder::new().from_reader(csv_data.as_bytes());
-        let mut items = HashMap::new();
+        let mut table = FakeTable::new(HOUSES_SCHEMA);
         for result in reader.records() {
             let record = result?;
             let address = record[0].to_string();
@@ -103,154 +113,219 @@ impl DynamoDbClient {
             let lng = (record[10].parse::<f64>()?).to_string();
             let h3_index = record[11].to_string();
 
-            let item = FakeItem {
-                hash_map: HashMap::from([
-                    ("H3Index".to_string(), AttributeValue::S(h3_index.clone())),
-                    ("Address".to_string(), AttributeValue::S(address.clone())),
-                    ("CityCode".to_string(), AttributeValue::S(city_code)),
-                    ("Url".to_string(), AttributeValue::S(url)),
-                    ("Lat".to_string(), AttributeValue::S(lat)),
-                    ("Lng".to_string(), AttributeValue::S(lng)),
-                    ("PriceLower".to_string(), AttributeValue::N(price_lower)),
-                    ("PriceUpper".to_string(), AttributeValue::N(price_upper)),
-                    ("PropertyType".to_string(), AttributeValue::S(property_type)),
-                    ("NumBathrooms".to_string(), AttributeValue::N(num_bathrooms)),
-                    ("NumBedrooms".to_string(), AttributeValue::N(num_bedrooms)),
-                    ("NumCarSpaces".to_string(), AttributeValue::N(num_carspaces)),
-                ]),
-            };
-            let key = format!("{}{}{}", h3_index, KEY_JOIN_STR, address);
-            items.insert(key, item);
+            table.insert(HashMap::from([
+                ("H3Index".to_string(), AttributeValue::S(h3_index)),
+                ("Address".to_string(), AttributeValue::S(address)),
+                ("CityCode".to_string(), AttributeValue::S(city_code)),
+                ("Url".to_string(), AttributeValue::S(url)),
+                ("Lat".to_string(), AttributeValue::N(lat)),
+                ("Lng".to_string(), AttributeValue::N(lng)),
+                ("PriceLower".to_string(), AttributeValue::N(price_lower)),
+                ("PriceUpper".to_string(), AttributeValue::N(price_upper)),
+                ("PropertyType".to_string(), AttributeValue::S(property_type)),
+                ("NumBathrooms".to_string(), AttributeValue::N(num_bathrooms)),
+                ("NumBedrooms".to_string(), AttributeValue::N(num_bedrooms)),
+                ("NumCarSpaces".to_string(), AttributeValue::N(num_carspaces)),
+            ]))?;
         }
-        Ok(items)
+        Ok(table)
     }
 
-    fn get_table(&self, table_name: &str) -> &RwLock<HashMap<String, FakeItem>> {
+    fn get_table(&self, table_name: &str) -> Result<&RwLock<FakeTable>, Error> {
         if table_name.ends_with("Requirements") {
-            &self.requirements_table
+            Ok(&self.requirements_table)
         } else if table_name.ends_with("SpatialDistances") {
-            &self.spatial_distances_table
+            Ok(&self.spatial_distances_table)
         } else if table_name.ends_with("Houses") {
-            &self.houses_table
+            Ok(&self.houses_table)
+        } else if table_name.ends_with("GeocodeCache") {
+            Ok(&self.geocode_cache_table)
         } else {
-            panic!("Unrecognised table {:?}", table_name);
+            Err(anyhow::anyhow!("Unrecognised table {:?}", table_name))
         }
     }
 
-    fn get_primary_key_columns(&self, table_name: &str) -> (&str, Option<&str>) {
-        if table_name.ends_with("Requirements") {
-            ("RequirementId", None)
-        } else if table_name.ends_with("SpatialDistances") {
-            ("SourceIndex", Some("DestinationIndex"))
-        } else {
-            panic!("Unrecognised table {:?}", table_name);
+    // Conditions are evaluated against the existing item, or an empty one if there is none
+    fn check_condition(
+        condition: Option<&str>,
+        names: Option<&HashMap<String, String>>,
+        values: Option<&HashMap<String, AttributeValue>>,
+        existing_item: Option<&FakeItem>,
+    ) -> Result<(), Error> {
+        let condition = match condition {
+            Some(condition) => Expression::parse(condition, names, values)?,
+            None => return Ok(()),
+        };
+        let empty_item = HashMap::new();
+        let existing_item = existing_item
+            .map(|item| &item.hash_map)
+            .unwrap_or(&empty_item);
+        if !condition.evaluate(existing_item)? {
+            return Err(anyhow::anyhow!("The conditional request failed"));
         }
+        Ok(())
     }
 
-    fn get_primary_key(partition_key: &str, sort_key: Option<&str>) -> String {
-        let primary_key = match sort_key {
-            None => partition_key,
-            Some(sort_key) => &format!("{}{}{}", partition_key, KEY_JOIN_STR, sort_key),
+    // Checks a write's key and condition without applying it
+    fn check_write(
+        &self,
+        item: &TransactWriteItem,
+    ) -> Result<(&RwLock<FakeTable>, PrimaryKey), Error> {
+        let (table_name, key, condition, names, values) = match (&item.put, &item.delete) {
+            (Some(put), _) => (
+                &put.table_name,
+                &put.item,
+                put.condition_expression.as_deref(),
+                put.expression_attribute_names.as_ref(),
+                put.expression_attribute_values.as_ref(),
+            ),
+            (None, Some(delete)) => (
+                &delete.table_name,
+                &delete.key,
+                delete.condition_expression.as_deref(),
+                delete.expression_attribute_names.as_ref(),
+                delete.expression_attribute_values.as_ref(),
+            ),
+            _ => return Err(anyhow::anyhow!("Only Put/Delete is supported")),
         };
-        primary_key.to_string()
+        let table_lock = self.get_table(table_name)?;
+        let table = table_lock.read().unwrap();
+        let primary_key = table.schema.key.primary_key(key)?;
+        Self::check_condition(condition, names, values, table.items.get(&primary_key))?;
+        Ok((table_lock, primary_key))
     }
 
-    fn write_put(&self, put: Put) -> Result<(), Error> {
-        let table = self.get_table(&put.table_name);
-        let (partition_column, sort_column) = self.get_primary_key_columns(&put.table_name);
-        let partition_key = parse_attribute_value::<String>(put.item.get(partition_column))?;
-        let sort_key = match sort_column {
-            None => None,
-            Some(col) => Some(parse_attribute_value::<String>(put.item.get(col))?),
-        };
-        let primary_key = Self::get_primary_key(&partition_key, sort_key.as_deref());
-        let item = FakeItem {
-            hash_map: put.item.clone(),
-        };
-        let mut hash_map = table.write().unwrap();
-        if put.condition_expression.is_some() {
-            let existing_item = hash_map.get(&primary_key);
-            self.check_put_condition(put, &existing_item)?;
+    // Like DynamoDB, an equality on the partition key and at most one condition on the sort key
+    fn check_key_condition(
+        key_condition: &Expression,
+        index: &KeySchema,
+    ) -> Result<KeyValue, Error> {
+        let mut partition_key = None;
+        let mut has_sort_key = false;
+        for condition in key_condition.conditions() {
+            let (path, equal_to) = match condition {
+                Expression::Compare(Operand::Path(path), Comparator::Eq, Operand::Value(value)) => {
+                    (path, Some(value))
+                }
+                Expression::Compare(Operand::Path(path), comparator, Operand::Value(_))
+                    if *comparator != Comparator::Ne =>
+                {
+                    (path, None)
+                }
+                Expression::Between(Operand::Path(path), Operand::Value(_), Operand::Value(_))
+                | Expression::BeginsWith(Operand::Path(path), Operand::Value(_)) => (path, None),
+                _ => return Err(anyhow::anyhow!("Unsupported key condition {:?}", condition)),
+            };
+            if let (true, Some(value), None) =
+                (path == index.partition_key, equal_to, &partition_key)
+            {
+                partition_key = Some(KeyValue::from_attribute(value)?);
+            } else if index.sort_key == Some(path.as_str()) && !has_sort_key {
+                has_sort_key = true;
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Query key condition not supported on {}",
+                    path
+                ));
+            }
         }
-        hash_map.insert(primary_key.to_string(), item);
-        Ok(())
+        partition_key.ok_or(anyhow::anyhow!(
+            "Query condition missed key schema element: {}",
+            index.partition_key
+        ))
     }
 
-    fn write_delete(&self, delete: Delete) -> Result<(), Error> {
-        let table = self.get_table(&delete.table_name);
-        let (partition_column, sort_column) = self.get_primary_key_columns(&delete.table_name);
-        let partition_key = parse_attribute_value::<String>(delete.key.get(partition_column))?;
-        let sort_key = match sort_column {
+    fn read_options(
+        filter_expression: Option<&str>,
+        projection_expression: Option<&str>,
+        names: Option<&HashMap<String, String>>,
+        values: Option<&HashMap<String, AttributeValue>>,
+        limit: Option<i32>,
+        forward: bool,
+    ) -> Result<ReadOptions, Error> {
+        let filter = match filter_expression {
+            Some(filter) => Some(Expression::parse(filter, names, values)?),
             None => None,
-            Some(col) => Some(parse_attribute_value::<String>(delete.key.get(col))?),
         };
-        let primary_key = Self::get_primary_key(&partition_key, sort_key.as_deref());
-        let mut hash_map = table.write().unwrap();
-        if delete.condition_expression.is_some() {
-            let existing_item = hash_map.get(&primary_key);
-            self.check_delete_condition(delete, &existing_item)?;
-        }
-        hash_map.remove(&primary_key.to_string());
-        Ok(())
+        let projection = projection_expression
+            .map(|projection| parse_projection(projection, names))
+            .transpose()?;
+        let limit = match limit {
+            Some(limit) if limit < 1 => {
+                return Err(anyhow::anyhow!("Limit must be greater than or equal to 1"))
+            }
+            Some(limit) => limit as usize,
+            None => usize::MAX,
+        };
+        Ok(ReadOptions {
+            filter,
+            projection,
+            limit,
+            forward,
+        })
     }
 
-    fn check_put_condition(
-        &self,
-        put: Put,
-        existing_item: &Option<&FakeItem>,
-    ) -> Result<(), Error> {
-        let expression = put
-            .condition_expression
-            .ok_or(anyhow::anyhow!("No condition"))?;
-
-        let must_be_new = expression.starts_with("attribute_not_exists");
-        match (existing_item, must_be_new) {
-            (Some(_), true) => {
-                return Err(anyhow::anyhow!("Item already exists"));
-            }
-            (Some(existing_item), false) => {
-                let actual_version =
-                    parse_attribute_value::<i32>(existing_item.hash_map.get("version"))?;
-                let new_version = parse_attribute_value::<i32>(put.item.get("version"))?;
-                if new_version != actual_version + 1 {
-                    return Err(anyhow::anyhow!("Version mismatch"));
-                }
-            }
-            (None, false) => {
-                return Err(anyhow::anyhow!("Item does not exist"));
-            }
-            _ => {}
+    // Reads matches in index order from the exclusive start key. Like DynamoDB, the limit
+    // counts items read before the filter is applied.
+    fn read_page(
+        table: &FakeTable,
+        index: &KeySchema,
+        mut matches: Vec<(Position, &HashMap<String, AttributeValue>)>,
+        exclusive_start_key: Option<&HashMap<String, AttributeValue>>,
+        options: ReadOptions,
+    ) -> Result<Page, Error> {
+        matches.sort_by(|(left, _), (right, _)| left.cmp(right));
+        if !options.forward {
+            matches.reverse();
+        }
+        if let Some(start_key) = exclusive_start_key {
+            let start = (
+                index.primary_key(start_key)?,
+                table.schema.key.primary_key(start_key)?,
+            );
+            matches.retain(|(position, _)| match options.forward {
+                true => *position > start,
+                false => *position < start,
+            });
         }
-        Ok(())
-    }
 
-    fn check_delete_condition(
-        &self,
-        delete: Delete,
-        existing_item: &Option<&FakeItem>,
-    ) -> Result<(), Error> {
-        let _ = delete
-            .condition_expression
-            .ok_or(anyhow::anyhow!("No condition"))?;
-        let attributes = delete
-            .expression_attribute_values
-            .ok_or(anyhow::anyhow!("No expression values"))?;
-        let expected_version =
-            parse_attribute_value::<i32>(attributes.get(&":old_version".to_string()))?;
-
-        match existing_item {
-            Some(existing_item) => {
-                let actual_version =
-                    parse_attribute_value::<i32>(existing_item.hash_map.get("version"))?;
-                if expected_version != actual_version {
-                    return Err(anyhow::anyhow!("Version mismatch"));
+        let hit_limit = matches.len() >= options.limit;
+        let read: Vec<_> = matches.into_iter().take(options.limit).collect();
+        let mut items = vec![];
+        for (_, item) in &read {
+            if let Some(filter) = &options.filter {
+                if !filter.evaluate(item)? {
+                    continue;
                 }
             }
-            None => {
-                return Err(anyhow::anyhow!("Item does not exist"));
+            items.push(Self::project(item, options.projection.as_deref()));
+        }
+        let last_evaluated_key = match read.last() {
+            Some((_, item)) if hit_limit => {
+                let mut key = table.schema.key.key_attributes(item);
+                key.extend(index.key_attributes(item));
+                Some(key)
             }
+            _ => None,
+        };
+        Ok(Page {
+            items,
+            scanned_count: read.len(),
+            last_evaluated_key,
+        })
+    }
+
+    fn project(
+        item: &HashMap<String, AttributeValue>,
+        projection: Option<&[String]>,
+    ) -> HashMap<String, AttributeValue> {
+        match projection {
+            None => item.clone(),
+            Some(projection) => projection
+                .iter()
+                .filter_map(|name| Some((name.clone(), item.get(name)?.clone())))
+                .collect(),
         }
-        Ok(())
     }
 }
 
@@ -258,21 +333,19 @@ impl DynamoDbClient {
 impl IDynamoDbClient for DynamoDbClient {
     async fn read_single(&self, item: TransactGetItem) -> Result<Option<ItemResponse>, Error> {
         let get = item.get.ok_or(anyhow::anyhow!("Only Gets are supported"))?;
-        let table = self.get_table(&get.table_name);
-        let hash_map = table.read().unwrap();
-        let (partition_column, sort_column) = self.get_primary_key_columns(&get.table_name);
-        let partition_key = parse_attribute_value::<String>(get.key.get(partition_column))?;
-        let sort_key = match sort_column {
-            None => None,
-            Some(col) => Some(parse_attribute_value::<String>(get.key.get(col))?),
-        };
-        let primary_key = Self::get_primary_key(&partition_key, sort_key.as_deref());
-        let item = match hash_map.get(&primary_key) {
+        let table = self.get_table(&get.table_name)?.read().unwrap();
+        let primary_key = table.schema.key.primary_key(&get.key)?;
+        let item = match table.items.get(&primary_key) {
             Some(item) => item,
             None => return Ok(None),
         };
+        let projection = get
+            .projection_expression
+            .as_deref()
+            .map(|projection| parse_projection(projection, get.expression_attribute_names.as_ref()))
+            .transpose()?;
         let item_response = ItemResponse::builder()
-            .set_item(Some(item.hash_map.clone()))
+            .set_item(Some(Self::project(&item.hash_map, projection.as_deref())))
             .build();
         let output = TransactGetItemsOutputBuilder::default()
             .responses(item_response)
@@ -282,94 +355,343 @@ impl IDynamoDbClient for DynamoDbClient {
         Ok(Some(item))
     }
 
+    // Like a transaction, every condition is checked before anything is written
     async fn write(&self, items: Vec<TransactWriteItem>) -> Result<(), Error> {
-        for item in items {
-            self.write_single(item).await?;
+        let _transaction = self.transaction_lock.lock().unwrap();
+        let mut keys: Vec<(&RwLock<FakeTable>, PrimaryKey)> = vec![];
+        for item in &items {
+            let (table, primary_key) = self.check_write(item)?;
+            if keys
+                .iter()
+                .any(|(other, key)| std::ptr::eq(*other, table) && *key == primary_key)
+            {
+                return Err(anyhow::anyhow!(
+                    "Transaction request cannot include multiple operations on one item"
+                ));
+            }
+            keys.push((table, primary_key));
+        }
+        for ((table, primary_key), item) in keys.into_iter().zip(items) {
+            let mut table = table.write().unwrap();
+            match item.put {
+                Some(put) => {
+                    table
+                        .items
+                        .insert(primary_key, FakeItem { hash_map: put.item });
+                }
+                None => {
+                    table.items.remove(&primary_key);
+                }
+            }
         }
         Ok(())
     }
 
     async fn write_single(&self, item: TransactWriteItem) -> Result<(), Error> {
-        if let Some(put) = item.put {
-            self.write_put(put)?;
-        } else if let Some(delete) = item.delete {
-            self.write_delete(delete)?;
-        } else {
-            return Err(anyhow::anyhow!("Only Put/Delete is supported"));
-        }
-        Ok(())
+        self.write(vec![item]).await
     }
 
     async fn query(&self, query: QueryInput) -> Result<QueryOutput, Error> {
-        let table_name = query.table_name.ok_or(anyhow::anyhow!("No table name"))?;
-        let table = self.get_table(&table_name);
-        let table_data = table.read().unwrap();
+        let table_name = query
+            .table_name
+            .as_deref()
+            .ok_or(anyhow::anyhow!("No table name"))?;
+        let table = self.get_table(table_name)?.read().unwrap();
+        let index = table.schema.index(query.index_name.as_deref())?;
+        let names = query.expression_attribute_names.as_ref();
+        let values = query.expression_attribute_values.as_ref();
         let key_condition = query
             .key_condition_expression
             .as_deref()
             .ok_or(anyhow::anyhow!("No key condition expression"))?;
-        let attr_names = query
-            .expression_attribute_names
-            .as_ref()
-            .ok_or(anyhow::anyhow!("No expression attribute names"))?;
-        let attr_values = query
-            .expression_attribute_values
-            .as_ref()
-            .ok_or(anyhow::anyhow!("No expression attribute values"))?;
-        let parts: Vec<&str> = key_condition.split('=').collect();
-        if parts.len() != 2 {
-            return Err(anyhow::anyhow!("Unsupported key condition format"));
+        let key_condition = Expression::parse(key_condition, names, values)?;
+        let partition_key = Self::check_key_condition(&key_condition, &index)?;
+        let options = Self::read_options(
+            query.filter_expression.as_deref(),
+            query.projection_expression.as_deref(),
+            names,
+            values,
+            query.limit,
+            query.scan_index_forward.unwrap_or(true),
+        )?;
+        if let Some(filter) = &options.filter {
+            let index_columns = index.columns();
+            if let Some(path) = filter
+                .paths()
+                .into_iter()
+                .find(|path| index_columns.contains(path))
+            {
+                return Err(anyhow::anyhow!(
+                    "Filter Expression can only contain non-primary key attributes: {}",
+                    path
+                ));
+            }
         }
-        let field_key = parts[0].trim();
-        let value_key = parts[1].trim();
-        let field_name = attr_names.get(field_key).ok_or(anyhow::anyhow!(
-            "Field name not found in expression attributes"
-        ))?;
-        let value = attr_values
-            .get(value_key)
-            .ok_or(anyhow::anyhow!("Value not found in expression attributes"))?;
-        let value = parse_attribute_value::<String>(Some(value))?;
-        let start_key = query.exclusive_start_key;
-        println!("Start key: {:?}", start_key);
-        let mut items = Vec::new();
-        let mut item_count = 0;
-        let mut found_start = start_key.is_none();
-        let mut last_evaluated_key = None;
-        let mut hit_limit = false;
-        for (_key, item) in table_data.iter() {
-            let code = match item.hash_map.get(field_name) {
-                Some(AttributeValue::S(code)) => code,
-                _ => continue,
+
+        let mut matches = vec![];
+        let candidates: Box<dyn Iterator<Item = _>> = match query.index_name {
+            None => Box::new(table.partition(&partition_key)),
+            Some(_) => Box::new(table.items.iter()),
+        };
+        for (primary_key, item) in candidates {
+            let index_key = match index.index_key(&item.hash_map) {
+                Some(index_key) => index_key,
+                None => continue,
             };
-            if code != &value {
-                continue;
+            if key_condition.evaluate(&item.hash_map)? {
+                matches.push(((index_key, primary_key.clone()), &item.hash_map));
             }
-            if !found_start {
-                let start = start_key.as_ref().unwrap();
-                println!("Checking match: {:?}", item.hash_map);
-                let start_matches = start
-                    .iter()
-                    .all(|(k, v)| item.hash_map.get(k).map_or(false, |item_v| item_v == v));
-                if start_matches {
-                    found_start = true;
-                }
-                continue;
+        }
+        let page = Self::read_page(
+            &table,
+            &index,
+            matches,
+            query.exclusive_start_key.as_ref(),
+            options,
+        )?;
+        Ok(QueryOutput::builder()
+            .count(page.items.len() as i32)
+            .scanned_count(page.scanned_count as i32)
+            .set_items(Some(page.items))
+            .set_last_evaluated_key(page.last_evaluated_key)
+            .build())
+    }
+
+    async fn batch_get(
+        &self,
+        items: Vec<TransactGetItem>,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+        let mut keys = BTreeSet::new();
+        let mut results = vec![];
+        for item in items {
+            let get = item.get.ok_or(anyhow::anyhow!("Only Gets are supported"))?;
+            if get.projection_expression.is_some() {
+                return Err(anyhow::anyhow!("Batch gets can't have projections"));
+            }
+            let table = self.get_table(&get.table_name)?.read().unwrap();
+            let primary_key = table.schema.key.primary_key(&get.key)?;
+            if !keys.insert((get.table_name.clone(), primary_key.clone())) {
+                return Err(anyhow::anyhow!(
+                    "Provided list of item keys contains duplicates"
+                ));
+            }
+            if let Some(item) = table.items.get(&primary_key) {
+                results.push(item.hash_map.clone());
+            }
+        }
+        Ok(results)
+    }
+
+    // Every write is checked before any are applied, but other writers can interleave
+    async fn batch_write(&self, items: Vec<TransactWriteItem>) -> Result<(), Error> {
+        let mut keys = BTreeSet::new();
+        let mut writes = vec![];
+        for item in items {
+            let (table_name, key_item, has_condition) = match (&item.put, &item.delete) {
+                (Some(put), _) => (
+                    &put.table_name,
+                    &put.item,
+                    put.condition_expression.is_some(),
+                ),
+                (None, Some(delete)) => (
+                    &delete.table_name,
+                    &delete.key,
+                    delete.condition_expression.is_some(),
+                ),
+                _ => return Err(anyhow::anyhow!("Only Put/Delete is supported")),
+            };
+            if has_condition {
+                return Err(anyhow::anyhow!("Batch writes can't have conditions"));
             }
-            last_evaluated_key = Some(item.hash_map.clone());
-            items.push(item.hash_map.clone());
-            item_count += 1;
-            if let Some(limit) = query.limit {
-                if item_count >= limit {
-                    hit_limit = true;
-                    break;
+            let table = self.get_table(table_name)?;
+            let primary_key = table.read().unwrap().schema.key.primary_key(key_item)?;
+            if !keys.insert((table_name.clone(), primary_key.clone())) {
+                return Err(anyhow::anyhow!(
+                    "Provided list of item keys contains duplicates"
+                ));
+            }
+            writes.push((table, primary_key, item));
+        }
+        for (table, primary_key, item) in writes {
+            let mut table = table.write().unwrap();
+            match item.put {
+                Some(put) => {
+                    table
+                        .items
+                        .insert(primary_key, FakeItem { hash_map: put.item });
+                }
+                None => {
+                    table.items.remove(&primary_key);
                 }
             }
         }
-        println!("Last evaluated key: {:?}", last_evaluated_key);
-        let mut builder = QueryOutput::builder().set_items(Some(items));
-        if hit_limit && item_count > 0 {
-            builder = builder.set_last_evaluated_key(last_evaluated_key);
+        Ok(())
+    }
+
+    // Items come back in index key order, rather than DynamoDB's hash order
+    async fn scan(&self, scan: ScanInput) -> Result<ScanOutput, Error> {
+        let table_name = scan
+            .table_name
+            .as_deref()
+            .ok_or(anyhow::anyhow!("No table name"))?;
+        if scan.segment.is_some() || scan.total_segments.is_some() {
+            return Err(anyhow::anyhow!("Parallel scans are not supported"));
         }
-        Ok(builder.build())
+        let table = self.get_table(table_name)?.read().unwrap();
+        let index = table.schema.index(scan.index_name.as_deref())?;
+        let options = Self::read_options(
+            scan.filter_expression.as_deref(),
+            scan.projection_expression.as_deref(),
+            scan.expression_attribute_names.as_ref(),
+            scan.expression_attribute_values.as_ref(),
+            scan.limit,
+            true,
+        )?;
+        let matches = table
+            .items
+            .iter()
+            .filter_map(|(primary_key, item)| {
+                let index_key = index.index_key(&item.hash_map)?;
+                Some(((index_key, primary_key.clone()), &item.hash_map))
+            })
+            .collect();
+        let page = Self::read_page(
+            &table,
+            &index,
+            matches,
+            scan.exclusive_start_key.as_ref(),
+            options,
+        )?;
+        Ok(ScanOutput::builder()
+            .count(page.items.len() as i32)
+            .scanned_count(page.scanned_count as i32)
+            .set_items(Some(page.items))
+            .set_last_evaluated_key(page.last_evaluated_key)
+            .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::OnceCell;
+
+    const SOURCE_INDEX: &str = "87b916972ffffff";
+
+    // Loading the spatial distances takes a while, so the read only tests share a client
+    static CLIENT: OnceCell<DynamoDbClient> = OnceCell::const_new();
+
+    async fn client() -> &'static DynamoDbClient {
+        CLIENT
+            .get_or_init(|| async { DynamoDbClient::new().await.unwrap() })
+            .await
+    }
+
+    fn query_by_source_index() -> aws_sdk_dynamodb::operation::query::builders::QueryInputBuilder {
+        QueryInput::builder()
+            .table_name("Test-SpatialDistances")
+            .expression_attribute_names("#source_index", "SourceIndex")
+            .expression_attribute_values(
+                ":source_index",
+                AttributeValue::S(SOURCE_INDEX.to_string()),
+            )
+    }
+
+    fn destinations(output: &QueryOutput) -> Vec<String> {
+        output
+            .items()
+            .iter()
+            .map(|item| parse_attribute(item, "DestinationIndex"))
+            .collect()
+    }
+
+    fn parse_attribute(item: &HashMap<String, AttributeValue>, column: &str) -> String {
+        attribute_value_parser::parse_attribute_value::<String>(item.get(column)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sort_key_condition_and_order() {
+        let client = client().await;
+        let query = query_by_source_index()
+            .key_condition_expression(
+                "#source_index = :source_index AND #destination BETWEEN :low AND :high",
+            )
+            .expression_attribute_names("#destination", "DestinationIndex")
+            .expression_attribute_values(":low", AttributeValue::S("87b914582ffffff".to_string()))
+            .expression_attribute_values(":high", AttributeValue::S("87b914586ffffff".to_string()))
+            .scan_index_forward(false)
+            .build()
+            .unwrap();
+        let output = client.query(query).await.unwrap();
+        assert_eq!(
+            destinations(&output),
+            vec!["87b914586ffffff", "87b914584ffffff", "87b914582ffffff"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pages_have_key_only_last_evaluated_keys() {
+        let client = client().await;
+        let query = query_by_source_index()
+            .key_condition_expression("#source_index = :source_index")
+            .limit(2)
+            .build()
+            .unwrap();
+        let first_page = client.query(query.clone()).await.unwrap();
+        let last_evaluated_key = first_page.last_evaluated_key.clone().unwrap();
+        let mut key_columns: Vec<&str> = last_evaluated_key.keys().map(String::as_str).collect();
+        key_columns.sort();
+        assert_eq!(key_columns, vec!["DestinationIndex", "SourceIndex"]);
+        let mut next_query = query;
+        next_query.exclusive_start_key = Some(last_evaluated_key);
+        let second_page = client.query(next_query).await.unwrap();
+        assert_eq!(
+            destinations(&first_page),
+            vec!["87b914582ffffff", "87b914584ffffff"]
+        );
+        assert_eq!(
+            destinations(&second_page),
+            vec!["87b914586ffffff", "87b914590ffffff"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filter_and_projection_on_index() {
+        let client = client().await;
+        let query = QueryInput::builder()
+            .table_name("Test-Houses")
+            .index_name("CityCodeIndex")
+            .key_condition_expression("#city_code = :city_code")
+            .filter_expression("begins_with(#address, :prefix) AND #bedrooms >= :bedrooms")
+            .projection_expression("#address, #bedrooms")
+            .expression_attribute_names("#city_code", "CityCode")
+            .expression_attribute_names("#address", "Address")
+            .expression_attribute_names("#bedrooms", "NumBedrooms")
+            .expression_attribute_values(":city_code", AttributeValue::S("Adelaide".to_string()))
+            .expression_attribute_values(":prefix", AttributeValue::S("2002/".to_string()))
+            .expression_attribute_values(":bedrooms", AttributeValue::N("1".to_string()))
+            .build()
+            .unwrap();
+        let output = client.query(query).await.unwrap();
+        assert_eq!(output.count, 1);
+        assert_eq!(output.scanned_count as usize, 132);
+        let item = &output.items()[0];
+        assert_eq!(item.len(), 2);
+        assert_eq!(
+            parse_attribute(item, "Address"),
+            "2002/29 Angas Street, Adelaide"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_key_condition_on_non_key_attribute() {
+        let client = client().await;
+        let query = query_by_source_index()
+            .key_condition_expression("#source_index = :source_index AND #city_code = :city_code")
+            .expression_attribute_names("#city_code", "CityCode")
+            .expression_attribute_values(":city_code", AttributeValue::S("Adelaide".to_string()))
+            .build()
+            .unwrap();
+        assert!(client.query(query).await.is_err());
     }
 }
@@ -0,0 +1,633 @@
+use anyhow::{Error, Result};
+use aws_sdk_dynamodb::types::AttributeValue;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// Condition, filter and key condition expressions, following
+// https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/Expressions.OperatorsAndFunctions.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Compare(Operand, Comparator, Operand),
+    Between(Operand, Operand, Operand),
+    In(Operand, Vec<Operand>),
+    AttributeExists(String),
+    AttributeNotExists(String),
+    BeginsWith(Operand, Operand),
+    Contains(Operand, Operand),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    // Names and values are substituted while parsing
+    Path(String),
+    Value(AttributeValue),
+    Size(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Value(String),
+    Comparator(Comparator),
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+fn tokenise(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' | '\n' | '\r' => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LeftParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RightParen);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '=' => {
+                chars.next();
+                tokens.push(Token::Comparator(Comparator::Eq));
+            }
+            '<' | '>' => {
+                chars.next();
+                let comparator = match (c, chars.peek()) {
+                    ('<', Some('=')) => Some(Comparator::Le),
+                    ('<', Some('>')) => Some(Comparator::Ne),
+                    ('>', Some('=')) => Some(Comparator::Ge),
+                    _ => None,
+                };
+                let comparator = match comparator {
+                    Some(comparator) => {
+                        chars.next();
+                        comparator
+                    }
+                    None if c == '<' => Comparator::Lt,
+                    None => Comparator::Gt,
+                };
+                tokens.push(Token::Comparator(comparator));
+            }
+            ':' | '#' | '_' | 'a'..='z' | 'A'..='Z' => {
+                let mut word = String::new();
+                word.push(c);
+                chars.next();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '-') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                match word.strip_prefix(':') {
+                    Some(_) => tokens.push(Token::Value(word)),
+                    None => tokens.push(Token::Name(word)),
+                }
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unexpected {:?} in expression {:?}",
+                    c,
+                    expression
+                ))
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    names: Option<&'a HashMap<String, String>>,
+    values: Option<&'a HashMap<String, AttributeValue>>,
+}
+
+impl Expression {
+    pub fn parse(
+        expression: &str,
+        names: Option<&HashMap<String, String>>,
+        values: Option<&HashMap<String, AttributeValue>>,
+    ) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenise(expression)?,
+            position: 0,
+            names,
+            values,
+        };
+        let parsed = parser.parse_or()?;
+        if parser.position < parser.tokens.len() {
+            return Err(anyhow::anyhow!(
+                "Unexpected {:?} in expression {:?}",
+                parser.tokens[parser.position],
+                expression
+            ));
+        }
+        Ok(parsed)
+    }
+
+    pub fn evaluate(&self, item: &HashMap<String, AttributeValue>) -> Result<bool> {
+        let result = match self {
+            Expression::Compare(left, comparator, right) => {
+                match (left.resolve(item)?, right.resolve(item)?) {
+                    (Some(left), Some(right)) => compare(&left, *comparator, &right)?,
+                    _ => false,
+                }
+            }
+            Expression::Between(operand, lower, upper) => {
+                match (
+                    operand.resolve(item)?,
+                    lower.resolve(item)?,
+                    upper.resolve(item)?,
+                ) {
+                    (Some(value), Some(lower), Some(upper)) => {
+                        compare(&value, Comparator::Ge, &lower)?
+                            && compare(&value, Comparator::Le, &upper)?
+                    }
+                    _ => false,
+                }
+            }
+            Expression::In(operand, candidates) => {
+                let value = match operand.resolve(item)? {
+                    Some(value) => value,
+                    None => return Ok(false),
+                };
+                let mut matched = false;
+                for candidate in candidates {
+                    if let Some(candidate) = candidate.resolve(item)? {
+                        if compare(&value, Comparator::Eq, &candidate)? {
+                            matched = true;
+                            break;
+                        }
+                    }
+                }
+                matched
+            }
+            Expression::AttributeExists(name) => item.contains_key(name),
+            Expression::AttributeNotExists(name) => !item.contains_key(name),
+            Expression::BeginsWith(operand, prefix) => {
+                match (operand.resolve(item)?, prefix.resolve(item)?) {
+                    (Some(AttributeValue::S(value)), Some(AttributeValue::S(prefix))) => {
+                        value.starts_with(&prefix)
+                    }
+                    (Some(AttributeValue::B(value)), Some(AttributeValue::B(prefix))) => {
+                        value.as_ref().starts_with(prefix.as_ref())
+                    }
+                    _ => false,
+                }
+            }
+            Expression::Contains(operand, needle) => {
+                match (operand.resolve(item)?, needle.resolve(item)?) {
+                    (Some(AttributeValue::S(value)), Some(AttributeValue::S(needle))) => {
+                        value.contains(&needle)
+                    }
+                    (Some(AttributeValue::Ss(values)), Some(AttributeValue::S(needle))) => {
+                        values.contains(&needle)
+                    }
+                    (Some(AttributeValue::Ns(values)), Some(AttributeValue::N(needle))) => {
+                        values.contains(&needle)
+                    }
+                    (Some(AttributeValue::L(values)), Some(needle)) => values.contains(&needle),
+                    _ => false,
+                }
+            }
+            Expression::And(left, right) => left.evaluate(item)? && right.evaluate(item)?,
+            Expression::Or(left, right) => left.evaluate(item)? || right.evaluate(item)?,
+            Expression::Not(expression) => !expression.evaluate(item)?,
+        };
+        Ok(result)
+    }
+
+    // The conditions joined by top level ANDs
+    pub fn conditions(&self) -> Vec<&Expression> {
+        match self {
+            Expression::And(left, right) => {
+                let mut conditions = left.conditions();
+                conditions.extend(right.conditions());
+                conditions
+            }
+            _ => vec![self],
+        }
+    }
+
+    // Every attribute the expression reads
+    pub fn paths<'a>(&'a self) -> Vec<&'a str> {
+        let operand_paths =
+            |operands: Vec<&'a Operand>| operands.into_iter().filter_map(Operand::path).collect();
+        match self {
+            Expression::Compare(left, _, right)
+            | Expression::BeginsWith(left, right)
+            | Expression::Contains(left, right) => operand_paths(vec![left, right]),
+            Expression::Between(operand, lower, upper) => {
+                operand_paths(vec![operand, lower, upper])
+            }
+            Expression::In(operand, candidates) => {
+                operand_paths(std::iter::once(operand).chain(candidates).collect())
+            }
+            Expression::AttributeExists(path) | Expression::AttributeNotExists(path) => {
+                vec![path.as_str()]
+            }
+            Expression::And(left, right) | Expression::Or(left, right) => {
+                let mut paths = left.paths();
+                paths.extend(right.paths());
+                paths
+            }
+            Expression::Not(expression) => expression.paths(),
+        }
+    }
+}
+
+impl Operand {
+    fn path(&self) -> Option<&str> {
+        match self {
+            Operand::Path(path) | Operand::Size(path) => Some(path),
+            Operand::Value(_) => None,
+        }
+    }
+
+    fn resolve(&self, item: &HashMap<String, AttributeValue>) -> Result<Option<AttributeValue>> {
+        let value = match self {
+            Operand::Path(path) => item.get(path).cloned(),
+            Operand::Value(value) => Some(value.clone()),
+            Operand::Size(path) => match item.get(path) {
+                Some(value) => Some(AttributeValue::N(size(value)?.to_string())),
+                None => None,
+            },
+        };
+        Ok(value)
+    }
+}
+
+fn size(value: &AttributeValue) -> Result<usize> {
+    let size = match value {
+        AttributeValue::S(value) => value.len(),
+        AttributeValue::B(value) => value.as_ref().len(),
+        AttributeValue::Ss(values) => values.len(),
+        AttributeValue::Ns(values) => values.len(),
+        AttributeValue::Bs(values) => values.len(),
+        AttributeValue::L(values) => values.len(),
+        AttributeValue::M(values) => values.len(),
+        _ => return Err(anyhow::anyhow!("size() is not supported for {:?}", value)),
+    };
+    Ok(size)
+}
+
+// Ordering for scalar values of the same type, or None if they can't be ordered
+pub fn order(left: &AttributeValue, right: &AttributeValue) -> Result<Option<Ordering>> {
+    let ordering = match (left, right) {
+        (AttributeValue::N(left), AttributeValue::N(right)) => {
+            let left = left.parse::<f64>()?;
+            let right = right.parse::<f64>()?;
+            left.partial_cmp(&right)
+        }
+        (AttributeValue::S(left), AttributeValue::S(right)) => Some(left.cmp(right)),
+        (AttributeValue::B(left), AttributeValue::B(right)) => {
+            Some(left.as_ref().cmp(right.as_ref()))
+        }
+        _ => None,
+    };
+    Ok(ordering)
+}
+
+fn compare(left: &AttributeValue, comparator: Comparator, right: &AttributeValue) -> Result<bool> {
+    let ordering = match order(left, right)? {
+        Some(ordering) => ordering,
+        // Values of different types, or non-scalar values, are only ever equal to themselves
+        None => {
+            return Ok(match comparator {
+                Comparator::Eq => left == right,
+                Comparator::Ne => left != right,
+                _ => false,
+            })
+        }
+    };
+    let result = match comparator {
+        Comparator::Eq => ordering.is_eq(),
+        Comparator::Ne => ordering.is_ne(),
+        Comparator::Lt => ordering.is_lt(),
+        Comparator::Le => ordering.is_le(),
+        Comparator::Gt => ordering.is_gt(),
+        Comparator::Ge => ordering.is_ge(),
+    };
+    Ok(result)
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or(anyhow::anyhow!("Unexpected end of expression"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(anyhow::anyhow!("Expected {:?}, got {:?}", expected, token));
+        }
+        Ok(())
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(name)) if name.eq_ignore_ascii_case(keyword))
+    }
+
+    fn parse_or(&mut self) -> Result<Expression> {
+        let mut expression = self.parse_and()?;
+        while self.next_is_keyword("OR") {
+            self.position += 1;
+            expression = Expression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression> {
+        let mut expression = self.parse_not()?;
+        while self.next_is_keyword("AND") {
+            self.position += 1;
+            expression = Expression::And(Box::new(expression), Box::new(self.parse_not()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression> {
+        if self.next_is_keyword("NOT") {
+            self.position += 1;
+            return Ok(Expression::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_condition()
+    }
+
+    fn parse_condition(&mut self) -> Result<Expression> {
+        if self.peek() == Some(&Token::LeftParen) {
+            self.position += 1;
+            let expression = self.parse_or()?;
+            self.expect(Token::RightParen)?;
+            return Ok(expression);
+        }
+        if let Some(expression) = self.parse_function()? {
+            return Ok(expression);
+        }
+        let operand = self.parse_operand()?;
+        if self.next_is_keyword("BETWEEN") {
+            self.position += 1;
+            let lower = self.parse_operand()?;
+            if !self.next_is_keyword("AND") {
+                return Err(anyhow::anyhow!("Expected AND in BETWEEN"));
+            }
+            self.position += 1;
+            let upper = self.parse_operand()?;
+            return Ok(Expression::Between(operand, lower, upper));
+        }
+        if self.next_is_keyword("IN") {
+            self.position += 1;
+            self.expect(Token::LeftParen)?;
+            let mut candidates = vec![self.parse_operand()?];
+            while self.peek() == Some(&Token::Comma) {
+                self.position += 1;
+                candidates.push(self.parse_operand()?);
+            }
+            self.expect(Token::RightParen)?;
+            return Ok(Expression::In(operand, candidates));
+        }
+        match self.next()? {
+            Token::Comparator(comparator) => {
+                let right = self.parse_operand()?;
+                Ok(Expression::Compare(operand, comparator, right))
+            }
+            token => Err(anyhow::anyhow!("Expected a comparator, got {:?}", token)),
+        }
+    }
+
+    // Functions that are conditions by themselves. size() is an operand instead.
+    fn parse_function(&mut self) -> Result<Option<Expression>> {
+        let name = match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(Token::Name(name)), Some(Token::LeftParen)) => name.clone(),
+            _ => return Ok(None),
+        };
+        let expression = match name.as_str() {
+            "attribute_exists" | "attribute_not_exists" => {
+                self.position += 2;
+                let path = self.parse_path()?;
+                self.expect(Token::RightParen)?;
+                match name.as_str() {
+                    "attribute_exists" => Expression::AttributeExists(path),
+                    _ => Expression::AttributeNotExists(path),
+                }
+            }
+            "begins_with" | "contains" => {
+                self.position += 2;
+                let operand = self.parse_operand()?;
+                self.expect(Token::Comma)?;
+                let argument = self.parse_operand()?;
+                self.expect(Token::RightParen)?;
+                match name.as_str() {
+                    "begins_with" => Expression::BeginsWith(operand, argument),
+                    _ => Expression::Contains(operand, argument),
+                }
+            }
+            "size" => return Ok(None),
+            _ => return Err(anyhow::anyhow!("Unsupported function {}", name)),
+        };
+        Ok(Some(expression))
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        match self.peek() {
+            Some(Token::Value(_)) => {
+                let placeholder = match self.next()? {
+                    Token::Value(placeholder) => placeholder,
+                    _ => unreachable!(),
+                };
+                let value = self
+                    .values
+                    .and_then(|values| values.get(&placeholder))
+                    .ok_or(anyhow::anyhow!(
+                        "Value {} not found in expression attributes",
+                        placeholder
+                    ))?;
+                Ok(Operand::Value(value.clone()))
+            }
+            Some(Token::Name(name))
+                if name == "size"
+                    && self.tokens.get(self.position + 1) == Some(&Token::LeftParen) =>
+            {
+                self.position += 2;
+                let path = self.parse_path()?;
+                self.expect(Token::RightParen)?;
+                Ok(Operand::Size(path))
+            }
+            _ => Ok(Operand::Path(self.parse_path()?)),
+        }
+    }
+
+    fn parse_path(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Name(name) if name.starts_with('#') => self
+                .names
+                .and_then(|names| names.get(&name))
+                .cloned()
+                .ok_or(anyhow::anyhow!(
+                    "Name {} not found in expression attributes",
+                    name
+                )),
+            Token::Name(name) => Ok(name),
+            token => Err(anyhow::anyhow!(
+                "Expected an attribute name, got {:?}",
+                token
+            )),
+        }
+    }
+}
+
+// Top level attributes named in a projection expression
+pub fn parse_projection(
+    expression: &str,
+    names: Option<&HashMap<String, String>>,
+) -> Result<Vec<String>, Error> {
+    expression
+        .split(',')
+        .map(str::trim)
+        .map(|name| match name.strip_prefix('#') {
+            Some(_) => names
+                .and_then(|names| names.get(name))
+                .cloned()
+                .ok_or(anyhow::anyhow!(
+                    "Name {} not found in expression attributes",
+                    name
+                )),
+            None if !name.is_empty() && !name.contains(['.', '[']) => Ok(name.to_string()),
+            None => Err(anyhow::anyhow!("Unsupported projection {:?}", name)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item() -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (
+                "Address".to_string(),
+                AttributeValue::S("1 King William St".to_string()),
+            ),
+            ("Price".to_string(), AttributeValue::N("500000".to_string())),
+            ("Bedrooms".to_string(), AttributeValue::N("3".to_string())),
+        ])
+    }
+
+    fn evaluate(expression: &str, values: &[(&str, AttributeValue)]) -> bool {
+        let names = HashMap::from([("#price".to_string(), "Price".to_string())]);
+        let values = values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        Expression::parse(expression, Some(&names), Some(&values))
+            .unwrap()
+            .evaluate(&item())
+            .unwrap()
+    }
+
+    fn n(value: &str) -> AttributeValue {
+        AttributeValue::N(value.to_string())
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert!(evaluate("#price >= :min", &[(":min", n("500000"))]));
+        assert!(evaluate("#price <> :other", &[(":other", n("1"))]));
+        // Numbers compare numerically rather than as strings
+        assert!(evaluate("Bedrooms < :max", &[(":max", n("10"))]));
+        assert!(!evaluate(
+            "Bedrooms = :three",
+            &[(":three", AttributeValue::S("3".to_string()))]
+        ));
+    }
+
+    #[test]
+    fn test_between_and_in() {
+        let values = [
+            (":low", n("400000")),
+            (":high", n("600000")),
+            (":a", n("2")),
+            (":b", n("3")),
+        ];
+        assert!(evaluate(
+            "#price BETWEEN :low AND :high AND Bedrooms IN (:a, :b)",
+            &values
+        ));
+        assert!(!evaluate("#price BETWEEN :high AND :low", &values));
+    }
+
+    #[test]
+    fn test_functions() {
+        let prefix = AttributeValue::S("1 King".to_string());
+        assert!(evaluate(
+            "begins_with(Address, :prefix)",
+            &[(":prefix", prefix)]
+        ));
+        assert!(evaluate(
+            "attribute_exists(#price) AND attribute_not_exists(Url)",
+            &[]
+        ));
+        assert!(evaluate("size(Address) > :length", &[(":length", n("5"))]));
+    }
+
+    #[test]
+    fn test_precedence() {
+        // AND binds tighter than OR, and NOT tighter than AND
+        assert!(evaluate(
+            "attribute_exists(Url) AND Bedrooms = :three OR #price = :price",
+            &[(":three", n("3")), (":price", n("500000"))]
+        ));
+        assert!(!evaluate(
+            "attribute_exists(Url) AND (Bedrooms = :three OR #price = :price)",
+            &[(":three", n("3")), (":price", n("500000"))]
+        ));
+        assert!(evaluate(
+            "NOT attribute_exists(Url) AND attribute_exists(Address)",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn test_missing_placeholder() {
+        assert!(Expression::parse("#missing = :value", None, None).is_err());
+    }
+
+    #[test]
+    fn test_projection() {
+        let names = HashMap::from([("#address".to_string(), "Address".to_string())]);
+        let projection = parse_projection("#address, Price", Some(&names)).unwrap();
+        assert_eq!(projection, vec!["Address", "Price"]);
+    }
+}
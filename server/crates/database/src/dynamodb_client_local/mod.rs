@@ -1,3 +1,6 @@
+#[allow(clippy::module_inception)]
 pub mod dynamodb_client_local;
+mod expression;
+mod table;
 
 pub use dynamodb_client_local::*;
@@ -0,0 +1,212 @@
+use anyhow::{Error, Result};
+use aws_sdk_dynamodb::types::AttributeValue;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+// Mirrors the tables in terraform/server/foundation/db.tf
+#[derive(Debug, Clone, Copy)]
+pub struct KeySchema {
+    pub partition_key: &'static str,
+    pub sort_key: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TableSchema {
+    pub key: KeySchema,
+    pub indexes: &'static [(&'static str, KeySchema)],
+}
+
+pub const REQUIREMENTS_SCHEMA: TableSchema = TableSchema {
+    key: KeySchema {
+        partition_key: "RequirementId",
+        sort_key: None,
+    },
+    indexes: &[(
+        "CityCodeIndex",
+        KeySchema {
+            partition_key: "CityCode",
+            sort_key: None,
+        },
+    )],
+};
+
+pub const SPATIAL_DISTANCES_SCHEMA: TableSchema = TableSchema {
+    key: KeySchema {
+        partition_key: "SourceIndex",
+        sort_key: Some("DestinationIndex"),
+    },
+    indexes: &[(
+        "CityCodeIndex",
+        KeySchema {
+            partition_key: "CityCode",
+            sort_key: Some("SourceIndex"),
+        },
+    )],
+};
+
+pub const HOUSES_SCHEMA: TableSchema = TableSchema {
+    key: KeySchema {
+        partition_key: "H3Index",
+        sort_key: Some("Address"),
+    },
+    indexes: &[(
+        "CityCodeIndex",
+        KeySchema {
+            partition_key: "CityCode",
+            sort_key: Some("H3Index"),
+        },
+    )],
+};
+
+pub const GEOCODE_CACHE_SCHEMA: TableSchema = TableSchema {
+    key: KeySchema {
+        partition_key: "AddressKey",
+        sort_key: None,
+    },
+    indexes: &[],
+};
+
+// A scalar key attribute, ordered the way DynamoDB orders sort keys
+#[derive(Debug, Clone)]
+pub enum KeyValue {
+    S(String),
+    N(f64),
+    B(Vec<u8>),
+}
+
+impl KeyValue {
+    pub fn from_attribute(value: &AttributeValue) -> Result<Self, Error> {
+        match value {
+            AttributeValue::S(value) => Ok(KeyValue::S(value.clone())),
+            AttributeValue::N(value) => Ok(KeyValue::N(value.parse::<f64>()?)),
+            AttributeValue::B(value) => Ok(KeyValue::B(value.as_ref().to_vec())),
+            _ => Err(anyhow::anyhow!("Key attributes must be S, N or B")),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            KeyValue::S(_) => 0,
+            KeyValue::N(_) => 1,
+            KeyValue::B(_) => 2,
+        }
+    }
+}
+
+impl Ord for KeyValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (KeyValue::S(left), KeyValue::S(right)) => left.cmp(right),
+            (KeyValue::N(left), KeyValue::N(right)) => left.total_cmp(right),
+            (KeyValue::B(left), KeyValue::B(right)) => left.cmp(right),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for KeyValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for KeyValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for KeyValue {}
+
+pub type PrimaryKey = (KeyValue, Option<KeyValue>);
+
+impl KeySchema {
+    pub fn columns(&self) -> Vec<&'static str> {
+        let mut columns = vec![self.partition_key];
+        columns.extend(self.sort_key);
+        columns
+    }
+
+    // Errors like DynamoDB when a key attribute is missing from a key or a new item
+    pub fn primary_key(&self, item: &HashMap<String, AttributeValue>) -> Result<PrimaryKey, Error> {
+        let key_value = |column: &str| {
+            item.get(column)
+                .ok_or(anyhow::anyhow!("Missing the key {} in the item", column))
+                .and_then(KeyValue::from_attribute)
+        };
+        let partition_key = key_value(self.partition_key)?;
+        let sort_key = match self.sort_key {
+            None => None,
+            Some(column) => Some(key_value(column)?),
+        };
+        Ok((partition_key, sort_key))
+    }
+
+    // The key of an item in this index, or None if the item is not in it. GSIs are sparse.
+    pub fn index_key(&self, item: &HashMap<String, AttributeValue>) -> Option<PrimaryKey> {
+        self.primary_key(item).ok()
+    }
+
+    // Only the key attributes, as returned in LastEvaluatedKey
+    pub fn key_attributes(
+        &self,
+        item: &HashMap<String, AttributeValue>,
+    ) -> HashMap<String, AttributeValue> {
+        self.columns()
+            .into_iter()
+            .filter_map(|column| Some((column.to_string(), item.get(column)?.clone())))
+            .collect()
+    }
+}
+
+impl TableSchema {
+    pub fn index(&self, index_name: Option<&str>) -> Result<KeySchema, Error> {
+        match index_name {
+            None => Ok(self.key),
+            Some(index_name) => self
+                .indexes
+                .iter()
+                .find(|(name, _)| *name == index_name)
+                .map(|(_, key)| *key)
+                .ok_or(anyhow::anyhow!(
+                    "The table does not have the specified index: {}",
+                    index_name
+                )),
+        }
+    }
+}
+
+pub struct FakeItem {
+    pub hash_map: HashMap<String, AttributeValue>,
+}
+
+// Items are kept in primary key order, so iteration is deterministic
+pub struct FakeTable {
+    pub schema: TableSchema,
+    pub items: BTreeMap<PrimaryKey, FakeItem>,
+}
+
+impl FakeTable {
+    pub fn new(schema: TableSchema) -> Self {
+        FakeTable {
+            schema,
+            items: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, hash_map: HashMap<String, AttributeValue>) -> Result<(), Error> {
+        let primary_key = self.schema.key.primary_key(&hash_map)?;
+        self.items.insert(primary_key, FakeItem { hash_map });
+        Ok(())
+    }
+
+    // Items sharing a partition key are adjacent, because None sorts before any sort key
+    pub fn partition<'a>(
+        &'a self,
+        partition_key: &'a KeyValue,
+    ) -> impl Iterator<Item = (&'a PrimaryKey, &'a FakeItem)> {
+        self.items
+            .range((partition_key.clone(), None)..)
+            .take_while(move |((key, _), _)| key == partition_key)
+    }
+}
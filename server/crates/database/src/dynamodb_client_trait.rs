@@ -2,8 +2,12 @@ use anyhow::Error;
 use async_trait::async_trait;
 use aws_sdk_dynamodb::{
     operation::query::{QueryInput, QueryOutput},
-    types::{ItemResponse, TransactGetItem, TransactWriteItem},
+    operation::scan::{ScanInput, ScanOutput},
+    types::{AttributeValue, ItemResponse, TransactGetItem, TransactWriteItem},
 };
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use std::collections::HashMap;
+use std::future::Future;
 
 #[async_trait]
 pub trait IDynamoDbClient: Sync + Send {
@@ -11,4 +15,137 @@ pub trait IDynamoDbClient: Sync + Send {
     async fn write(&self, items: Vec<TransactWriteItem>) -> Result<(), Error>;
     async fn write_single(&self, item: TransactWriteItem) -> Result<(), Error>;
     async fn query(&self, query: QueryInput) -> Result<QueryOutput, Error>;
+    // Any number of gets. Missing items are left out, and the order is not kept.
+    async fn batch_get(
+        &self,
+        items: Vec<TransactGetItem>,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, Error>;
+    // Any number of unconditional puts and deletes. Unlike write, this is not a transaction.
+    async fn batch_write(&self, items: Vec<TransactWriteItem>) -> Result<(), Error>;
+    async fn scan(&self, scan: ScanInput) -> Result<ScanOutput, Error>;
+}
+
+// Caps on how much of a query or scan to read. The default reads every page.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PageLimits {
+    pub max_items: Option<usize>,
+    pub max_pages: Option<usize>,
+}
+
+// A page of items, and the key to start the next page from
+type Page = (
+    Vec<HashMap<String, AttributeValue>>,
+    Option<HashMap<String, AttributeValue>>,
+);
+
+// Follows LastEvaluatedKey from the given start key until the pages run out or a limit is
+// reached. Pages are only fetched as the stream is polled, and are told how many items are
+// still wanted so they don't read more than that.
+fn paginate<'a, F, Fut>(
+    exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+    fetch_page: F,
+    limits: PageLimits,
+) -> impl Stream<Item = Result<HashMap<String, AttributeValue>, Error>> + 'a
+where
+    F: Fn(Option<HashMap<String, AttributeValue>>, Option<usize>) -> Fut + 'a,
+    Fut: Future<Output = Result<Page, Error>> + 'a,
+{
+    // None once there are no more pages, otherwise the key to start the next page from
+    let first_page = Some(exclusive_start_key);
+    let state = (first_page, 0, 0);
+    let pages = stream::try_unfold(state, move |(next_page, num_pages, num_items)| {
+        let remaining = limits
+            .max_items
+            .map(|max_items| max_items.saturating_sub(num_items));
+        let under_limit = limits
+            .max_pages
+            .is_none_or(|max_pages| num_pages < max_pages)
+            && remaining != Some(0);
+        let page = next_page
+            .filter(|_| under_limit)
+            .map(|start_key| fetch_page(start_key, remaining));
+        async move {
+            let (items, last_evaluated_key) = match page {
+                Some(page) => page.await?,
+                None => return Ok::<_, Error>(None),
+            };
+            let num_items = num_items + items.len();
+            let items = stream::iter(items.into_iter().map(Ok));
+            let state = (last_evaluated_key.map(Some), num_pages + 1, num_items);
+            Ok(Some((items, state)))
+        }
+    });
+    pages
+        .try_flatten()
+        .take(limits.max_items.unwrap_or(usize::MAX))
+}
+
+// A page's own limit, lowered to the number of items still wanted
+fn page_limit(limit: Option<i32>, remaining: Option<usize>) -> Option<i32> {
+    let remaining = remaining.map(|remaining| i32::try_from(remaining).unwrap_or(i32::MAX));
+    match (limit, remaining) {
+        (Some(limit), Some(remaining)) => Some(limit.min(remaining)),
+        (limit, remaining) => limit.or(remaining),
+    }
+}
+
+// Every item matching a query, across as many pages as it takes
+pub fn query_stream(
+    db: &dyn IDynamoDbClient,
+    query: QueryInput,
+    limits: PageLimits,
+) -> impl Stream<Item = Result<HashMap<String, AttributeValue>, Error>> + '_ {
+    let exclusive_start_key = query.exclusive_start_key.clone();
+    let fetch_page = move |start_key, remaining| {
+        let mut query = query.clone();
+        query.exclusive_start_key = start_key;
+        query.limit = page_limit(query.limit, remaining);
+        async move {
+            let output = db.query(query).await?;
+            Ok((output.items.unwrap_or_default(), output.last_evaluated_key))
+        }
+    };
+    paginate(exclusive_start_key, fetch_page, limits)
+}
+
+pub async fn query_all(
+    db: &dyn IDynamoDbClient,
+    query: QueryInput,
+    limits: PageLimits,
+) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+    query_stream(db, query, limits).try_collect().await
+}
+
+// Every item in a scan, across as many pages as it takes
+pub fn scan_stream(
+    db: &dyn IDynamoDbClient,
+    scan: ScanInput,
+    limits: PageLimits,
+) -> impl Stream<Item = Result<HashMap<String, AttributeValue>, Error>> + '_ {
+    let exclusive_start_key = scan.exclusive_start_key.clone();
+    let fetch_page = move |start_key, remaining| {
+        let mut scan = scan.clone();
+        scan.exclusive_start_key = start_key;
+        scan.limit = page_limit(scan.limit, remaining);
+        async move {
+            let output = db.scan(scan).await?;
+            Ok((output.items.unwrap_or_default(), output.last_evaluated_key))
+        }
+    };
+    paginate(exclusive_start_key, fetch_page, limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_limit() {
+        assert_eq!(page_limit(None, None), None);
+        assert_eq!(page_limit(Some(25), None), Some(25));
+        assert_eq!(page_limit(None, Some(10)), Some(10));
+        assert_eq!(page_limit(Some(25), Some(10)), Some(10));
+        assert_eq!(page_limit(Some(5), Some(10)), Some(5));
+        assert_eq!(page_limit(None, Some(usize::MAX)), Some(i32::MAX));
+    }
 }
@@ -0,0 +1,69 @@
+use super::dynamodb_client_trait::IDynamoDbClient;
+use anyhow::Error;
+use database_derive::DynamoItem;
+
+#[derive(Debug, PartialEq, DynamoItem)]
+#[dynamo(table_env = "GEOCODE_CACHE_TABLE_NAME")]
+pub struct GeocodeItem {
+    #[dynamo(partition_key)]
+    pub address_key: String,
+    pub lat: f64,
+    pub lng: f64,
+    #[dynamo(ttl)]
+    pub time_to_live: i64,
+}
+
+impl GeocodeItem {
+    pub async fn from_db(
+        address_key: &str,
+        db: &dyn IDynamoDbClient,
+    ) -> Result<Option<Self>, Error> {
+        let transaction = Self::get(address_key)?;
+        let output = match db.read_single(transaction).await? {
+            Some(output) => output,
+            None => return Ok(None),
+        };
+        let attribute = output.item.ok_or(anyhow::anyhow!("No item"))?;
+        let item = Self::from_map(&attribute)?;
+        Ok(Some(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use chrono::Utc;
+
+    fn geocode() -> GeocodeItem {
+        GeocodeItem {
+            address_key: "17 foster street norwood sa".to_string(),
+            lat: -34.91619329004659,
+            lng: 138.63740742198067,
+            time_to_live: Utc::now().timestamp() + 60,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let geocode = geocode();
+        let hash_map = geocode.to_map().unwrap();
+        assert_eq!(GeocodeItem::from_map(&hash_map).unwrap(), geocode);
+        assert!(!geocode.is_expired());
+    }
+
+    #[test]
+    fn test_reads_coordinates_written_as_strings() {
+        let geocode = geocode();
+        let mut hash_map = geocode.to_map().unwrap();
+        hash_map.insert(
+            "Lat".to_string(),
+            AttributeValue::S(geocode.lat.to_string()),
+        );
+        hash_map.insert(
+            "Lng".to_string(),
+            AttributeValue::S(geocode.lng.to_string()),
+        );
+        assert_eq!(GeocodeItem::from_map(&hash_map).unwrap(), geocode);
+    }
+}
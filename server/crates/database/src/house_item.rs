@@ -1,19 +1,23 @@
 use super::attribute_value_parser::parse_attribute_value;
 use super::paginated_models::PaginatedDbResponse;
-use crate::dynamodb_client_trait::IDynamoDbClient;
-use crate::paginated_models::DbKey;
+use crate::dynamodb_client_trait::{query_all, query_stream, IDynamoDbClient, PageLimits};
+use crate::paginated_models::{DbKey, InvalidKeyError};
 use anyhow::{Error, Ok};
 use aws_sdk_dynamodb::{
-    operation::query::QueryInput,
+    operation::query::{builders::QueryInputBuilder, QueryInput},
     types::{AttributeValue, Put, TransactWriteItem},
 };
-use chrono::{Duration, Utc};
-use std::{collections::HashMap, env};
+use database_derive::DynamoItem;
+use futures::TryStreamExt;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, DynamoItem)]
+#[dynamo(table_env = "HOUSES_TABLE_NAME", ttl_days = 1)]
 pub struct HouseItem {
+    #[dynamo(partition_key, gsi_sort_key = "CityCodeIndex")]
     pub h3_index: String,
+    #[dynamo(sort_key)]
     pub address: String,
+    #[dynamo(gsi_partition_key = "CityCodeIndex")]
     pub city_code: String,
     pub url: String,
     pub lat: f64,
@@ -22,130 +26,212 @@ pub struct HouseItem {
     pub price_upper: i32,
     pub num_bathrooms: i32,
     pub num_bedrooms: i32,
+    #[dynamo(name = "NumCarSpaces")]
     pub num_carspaces: i32,
     pub property_type: String,
 }
 
+// DynamoDB's IN takes at most 100 values
+pub const MAX_PROPERTY_TYPES: usize = 100;
+
+#[derive(Clone, Debug, Default)]
+pub struct HouseFilter {
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+    pub min_bedrooms: Option<i32>,
+    pub min_bathrooms: Option<i32>,
+    pub min_carspaces: Option<i32>,
+    pub property_types: Vec<String>,
+}
+
+impl HouseFilter {
+    fn apply(&self, mut builder: QueryInputBuilder) -> QueryInputBuilder {
+        let mut conditions = vec![];
+        // A house matches a price range if any part of its listed range overlaps it
+        let numeric_conditions = [
+            ("PriceUpper", ">=", ":min_price", self.min_price),
+            ("PriceLower", "<=", ":max_price", self.max_price),
+            ("NumBedrooms", ">=", ":min_bedrooms", self.min_bedrooms),
+            ("NumBathrooms", ">=", ":min_bathrooms", self.min_bathrooms),
+            ("NumCarSpaces", ">=", ":min_carspaces", self.min_carspaces),
+        ];
+        for (column, operator, value_name, value) in numeric_conditions {
+            if let Some(value) = value {
+                let column_name = format!("#{}", column);
+                conditions.push(format!("{} {} {}", column_name, operator, value_name));
+                builder = builder
+                    .expression_attribute_names(column_name, column)
+                    .expression_attribute_values(value_name, AttributeValue::N(value.to_string()));
+            }
+        }
+        if !self.property_types.is_empty() {
+            let mut value_names = vec![];
+            for (i, property_type) in self.property_types.iter().enumerate() {
+                let value_name = format!(":property_type_{}", i);
+                builder = builder.expression_attribute_values(
+                    &value_name,
+                    AttributeValue::S(property_type.to_string()),
+                );
+                value_names.push(value_name);
+            }
+            conditions.push(format!("#PropertyType IN ({})", value_names.join(", ")));
+            builder = builder.expression_attribute_names("#PropertyType", "PropertyType");
+        }
+        if conditions.is_empty() {
+            return builder;
+        }
+        builder.filter_expression(conditions.join(" AND "))
+    }
+}
+
 impl HouseItem {
-    pub fn from_map(hash_map: &HashMap<String, AttributeValue>) -> Result<Self, Error> {
-        let h3_index = parse_attribute_value::<String>(hash_map.get("H3Index"))?;
-        let address = parse_attribute_value::<String>(hash_map.get("Address"))?;
-        let city_code = parse_attribute_value::<String>(hash_map.get("CityCode"))?;
-        let url = parse_attribute_value::<String>(hash_map.get("Url"))?;
-        let lat = parse_attribute_value::<f64>(hash_map.get("Lat"))?;
-        let lng = parse_attribute_value::<f64>(hash_map.get("Lng"))?;
-        let price_lower = parse_attribute_value::<i32>(hash_map.get("PriceLower"))?;
-        let price_upper = parse_attribute_value::<i32>(hash_map.get("PriceUpper"))?;
-        let num_bathrooms = parse_attribute_value::<i32>(hash_map.get("NumBathrooms"))?;
-        let num_bedrooms = parse_attribute_value::<i32>(hash_map.get("NumBedrooms"))?;
-        let num_carspaces = parse_attribute_value::<i32>(hash_map.get("NumCarSpaces"))?;
-        let property_type = parse_attribute_value::<String>(hash_map.get("PropertyType"))?;
-        let item = Self {
-            h3_index,
-            address,
-            city_code,
-            url,
-            lat,
-            lng,
-            price_lower,
-            price_upper,
-            num_bathrooms,
-            num_bedrooms,
-            num_carspaces,
-            property_type,
+    // Listings from ingest_houses stay until an export replaces them, so unlike other writes
+    // they don't get a TimeToLive
+    pub fn save_listing(&self) -> Result<TransactWriteItem, Error> {
+        let mut hash_map = self.to_map()?;
+        hash_map.remove("TimeToLive");
+        let put_item = Put::builder()
+            .table_name(Self::get_table_name()?)
+            .set_item(Some(hash_map))
+            .build()?;
+        let transaction_item = TransactWriteItem::builder().put(put_item).build();
+        Ok(transaction_item)
+    }
+
+    pub async fn from_db(
+        h3_index: &str,
+        address: &str,
+        db: &dyn IDynamoDbClient,
+    ) -> Result<Option<Self>, Error> {
+        let transaction = Self::get(h3_index, address)?;
+        let output = match db.read_single(transaction).await? {
+            Some(output) => output,
+            None => return Ok(None),
         };
-        Ok(item)
+        let attribute = output.item.ok_or(anyhow::anyhow!("No item"))?;
+        let item = Self::from_map(&attribute)?;
+        Ok(Some(item))
     }
 
-    pub fn get_table_name() -> Result<String, Error> {
-        let name: String = env::var("HOUSES_TABLE_NAME")?;
-        Ok(name)
+    // Up to limit houses, read from as many pages as it takes. A full page might not be the
+    // last one, so its key is the last house's, in the form the query's index needs.
+    async fn list_from_db(
+        query_input: QueryInput,
+        limit: Option<i32>,
+        index_name: Option<&str>,
+        db: &dyn IDynamoDbClient,
+    ) -> Result<PaginatedDbResponse<Self>, Error> {
+        let limits = PageLimits {
+            max_items: limit.map(usize::try_from).transpose()?,
+            max_pages: None,
+        };
+        let items = query_all(db, query_input, limits).await?;
+        let results = items
+            .iter()
+            .map(Self::from_map)
+            .collect::<Result<Vec<_>, Error>>()?;
+        let last_evaluated_key = match (limits.max_items, results.last()) {
+            (Some(max_items), Some(last)) if results.len() >= max_items => match index_name {
+                Some(index_name) => Some(last.index_key(index_name)?),
+                None => Some(last.primary_key()),
+            },
+            _ => None,
+        };
+        Ok(PaginatedDbResponse {
+            items: results,
+            last_evaluated_key,
+        })
     }
 
     pub async fn list_by_h3_index_from_db(
         h3_index: &str,
+        filter: &HouseFilter,
         limit: Option<i32>,
         last_evaluated_key: Option<DbKey>,
         db: &dyn IDynamoDbClient,
     ) -> Result<PaginatedDbResponse<Self>, Error> {
-        let query_input = Self::query_by_h3_index(h3_index, limit, last_evaluated_key)?;
-        let query_output = db.query(query_input).await?;
-        let items = query_output.items.unwrap_or_default();
-        let mut results = Vec::new();
-        for item in items {
-            let item = Self::from_map(&item)?;
-            results.push(item);
-        }
-        Ok(PaginatedDbResponse {
-            items: results,
-            last_evaluated_key: query_output.last_evaluated_key,
-        })
+        let query_input = Self::query_by_h3_index(h3_index, filter, last_evaluated_key)?;
+        Self::list_from_db(query_input, limit, None, db).await
     }
 
-    pub async fn list_by_city_from_db(
-        city: &str,
+    pub async fn list_by_h3_indices_from_db(
+        h3_indices: &[String],
+        filter: &HouseFilter,
         limit: Option<i32>,
         last_evaluated_key: Option<DbKey>,
         db: &dyn IDynamoDbClient,
     ) -> Result<PaginatedDbResponse<Self>, Error> {
-        let query_input = Self::query_by_city(city, limit, last_evaluated_key)?;
-        let query_output = db.query(query_input).await?;
-        let items = query_output.items.unwrap_or_default();
-        let mut results = Vec::new();
-        for item in items {
-            let spatial_distance_item = Self::from_map(&item)?;
-            results.push(spatial_distance_item);
+        // Resume from the index that the previous page finished on
+        let start_position = match &last_evaluated_key {
+            Some(key) => {
+                let h3_index = parse_attribute_value::<String>(key.get("H3Index"))
+                    .map_err(|_| InvalidKeyError)?;
+                h3_indices
+                    .iter()
+                    .position(|index| index == &h3_index)
+                    .ok_or(InvalidKeyError)?
+            }
+            None => 0,
+        };
+        let mut results: Vec<Self> = Vec::new();
+        let mut start_key = last_evaluated_key;
+        for h3_index in h3_indices.iter().skip(start_position) {
+            let remaining = limit.map(|limit| limit - results.len() as i32);
+            let response =
+                Self::list_by_h3_index_from_db(h3_index, filter, remaining, start_key.take(), db)
+                    .await?;
+            results.extend(response.items);
+            // Only a full page has a key
+            if response.last_evaluated_key.is_some() {
+                return Ok(PaginatedDbResponse {
+                    items: results,
+                    last_evaluated_key: response.last_evaluated_key,
+                });
+            }
         }
         Ok(PaginatedDbResponse {
             items: results,
-            last_evaluated_key: query_output.last_evaluated_key,
+            last_evaluated_key: None,
         })
     }
 
-    pub fn save(&self) -> Result<TransactWriteItem, Error> {
-        let ttl_timestamp = (Utc::now() + Duration::days(1)).timestamp();
-        let put_item = Put::builder()
+    // Addresses are only unique within a tile, so this is the first house in the city with it.
+    // Pages are only read until it is found.
+    pub async fn find_by_address_in_city(
+        city: &str,
+        address: &str,
+        db: &dyn IDynamoDbClient,
+    ) -> Result<Option<Self>, Error> {
+        let query_input = QueryInput::builder()
             .table_name(Self::get_table_name()?)
-            .item("H3Index", AttributeValue::S(self.h3_index.to_string()))
-            .item("Address", AttributeValue::S(self.address.to_string()))
-            .item("CityCode", AttributeValue::S(self.city_code.clone()))
-            .item("Url", AttributeValue::S(self.url.to_string()))
-            .item("Lat", AttributeValue::N(self.lat.to_string()))
-            .item("Lng", AttributeValue::N(self.lng.to_string()))
-            .item(
-                "PriceLower",
-                AttributeValue::N(self.price_lower.to_string()),
-            )
-            .item(
-                "PriceUpper",
-                AttributeValue::N(self.price_upper.to_string()),
-            )
-            .item(
-                "NumBathrooms",
-                AttributeValue::N(self.num_bathrooms.to_string()),
-            )
-            .item(
-                "NumBedrooms",
-                AttributeValue::N(self.num_bedrooms.to_string()),
-            )
-            .item(
-                "NumCarspaces",
-                AttributeValue::N(self.num_carspaces.to_string()),
-            )
-            .item(
-                "PropertyType",
-                AttributeValue::S(self.property_type.to_string()),
-            )
-            .item("TimeToLive", AttributeValue::N(ttl_timestamp.to_string()))
+            .index_name("CityCodeIndex")
+            .key_condition_expression("#city_code = :city_code")
+            .filter_expression("#address = :address")
+            .expression_attribute_names("#city_code", "CityCode")
+            .expression_attribute_names("#address", "Address")
+            .expression_attribute_values(":city_code", AttributeValue::S(city.to_string()))
+            .expression_attribute_values(":address", AttributeValue::S(address.to_string()))
             .build()?;
-        let transaction_item = TransactWriteItem::builder().put(put_item).build();
-        Ok(transaction_item)
+        let mut items = std::pin::pin!(query_stream(db, query_input, PageLimits::default()));
+        let item = items.try_next().await?;
+        item.as_ref().map(Self::from_map).transpose()
     }
 
-    fn query_by_city(
+    pub async fn list_by_city_from_db(
         city: &str,
+        filter: &HouseFilter,
         limit: Option<i32>,
         last_evaluated_key: Option<DbKey>,
+        db: &dyn IDynamoDbClient,
+    ) -> Result<PaginatedDbResponse<Self>, Error> {
+        let query_input = Self::query_by_city(city, filter, last_evaluated_key)?;
+        Self::list_from_db(query_input, limit, Some("CityCodeIndex"), db).await
+    }
+
+    fn query_by_city(
+        city: &str,
+        filter: &HouseFilter,
+        last_evaluated_key: Option<DbKey>,
     ) -> Result<QueryInput, Error> {
         let mut builder = QueryInput::builder()
             .table_name(Self::get_table_name()?)
@@ -154,16 +240,14 @@ impl HouseItem {
             .expression_attribute_names("#city_code", "CityCode")
             .expression_attribute_values(":city_code", AttributeValue::S(city.to_string()))
             .set_exclusive_start_key(last_evaluated_key);
-        if let Some(limit) = limit {
-            builder = builder.limit(limit);
-        }
+        builder = filter.apply(builder);
         let query_input = builder.build()?;
         Ok(query_input)
     }
 
     fn query_by_h3_index(
         h3_index: &str,
-        limit: Option<i32>,
+        filter: &HouseFilter,
         last_evaluated_key: Option<DbKey>,
     ) -> Result<QueryInput, Error> {
         let mut builder = QueryInput::builder()
@@ -172,10 +256,191 @@ impl HouseItem {
             .expression_attribute_names("#h3_index", "H3Index")
             .expression_attribute_values(":h3_index", AttributeValue::S(h3_index.to_string()))
             .set_exclusive_start_key(last_evaluated_key);
-        if let Some(limit) = limit {
-            builder = builder.limit(limit);
-        }
+        builder = filter.apply(builder);
         let query_input = builder.build()?;
         Ok(query_input)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamodb_client_local::DynamoDbClient;
+    use std::collections::HashMap;
+    use tokio::sync::OnceCell;
+
+    static CLIENT: OnceCell<DynamoDbClient> = OnceCell::const_new();
+
+    async fn client() -> &'static DynamoDbClient {
+        CLIENT
+            .get_or_init(|| async { DynamoDbClient::new().await.unwrap() })
+            .await
+    }
+
+    const H3_INDICES: [&str; 3] = ["87b916800ffffff", "87b916804ffffff", "87b916801ffffff"];
+
+    fn house() -> HouseItem {
+        HouseItem {
+            h3_index: "87b916804ffffff".to_string(),
+            address: "17 Foster Street, Norwood".to_string(),
+            city_code: "Adelaide".to_string(),
+            url: "https://www.realestate.com.au/property-house-sa-norwood-145691580".to_string(),
+            lat: -34.91619329004659,
+            lng: 138.63740742198067,
+            price_lower: 2450000,
+            price_upper: 2650000,
+            num_bathrooms: 3,
+            num_bedrooms: 4,
+            num_carspaces: 2,
+            property_type: "House".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let house = house();
+        let hash_map = house.to_map().unwrap();
+        assert_eq!(HouseItem::from_map(&hash_map).unwrap(), house);
+    }
+
+    #[test]
+    fn test_attributes_match_filter_and_schema() {
+        let hash_map = house().to_map().unwrap();
+        // HouseFilter filters on NumCarSpaces, and numbers have to be N to compare
+        assert_eq!(
+            hash_map.get("NumCarSpaces"),
+            Some(&AttributeValue::N("2".to_string()))
+        );
+        assert!(matches!(hash_map.get("Lat"), Some(AttributeValue::N(_))));
+        assert!(matches!(hash_map.get("Lng"), Some(AttributeValue::N(_))));
+        assert!(hash_map.contains_key("TimeToLive"));
+    }
+
+    #[test]
+    fn test_listings_are_saved_without_time_to_live() {
+        let put = house().save_listing().unwrap().put.unwrap();
+        assert!(!put.item.contains_key("TimeToLive"));
+        assert_eq!(put.item["Address"], house().to_map().unwrap()["Address"]);
+    }
+
+    #[test]
+    fn test_keys() {
+        let house = house();
+        let primary_key = house.primary_key();
+        let mut key_columns: Vec<&str> = primary_key.keys().map(String::as_str).collect();
+        key_columns.sort();
+        assert_eq!(key_columns, vec!["Address", "H3Index"]);
+        let index_key = house.index_key("CityCodeIndex").unwrap();
+        let mut key_columns: Vec<&str> = index_key.keys().map(String::as_str).collect();
+        key_columns.sort();
+        assert_eq!(key_columns, vec!["Address", "CityCode", "H3Index"]);
+        assert!(house.index_key("PriceIndex").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pages_across_h3_indices() {
+        let db = client().await;
+        let h3_indices: Vec<String> = H3_INDICES.iter().map(|index| index.to_string()).collect();
+        let filter = HouseFilter::default();
+        let all = HouseItem::list_by_h3_indices_from_db(&h3_indices, &filter, None, None, db)
+            .await
+            .unwrap();
+        assert!(all.items.len() > 2);
+        assert!(all.last_evaluated_key.is_none());
+        let mut paged = Vec::new();
+        let mut last_evaluated_key = None;
+        loop {
+            let page = HouseItem::list_by_h3_indices_from_db(
+                &h3_indices,
+                &filter,
+                Some(2),
+                last_evaluated_key,
+                db,
+            )
+            .await
+            .unwrap();
+            assert!(page.items.len() <= 2);
+            paged.extend(page.items);
+            last_evaluated_key = page.last_evaluated_key;
+            if last_evaluated_key.is_none() {
+                break;
+            }
+        }
+        assert_eq!(paged, all.items);
+    }
+
+    #[tokio::test]
+    async fn test_key_outside_h3_indices_is_invalid() {
+        let db = client().await;
+        let h3_indices: Vec<String> = H3_INDICES.iter().map(|index| index.to_string()).collect();
+        let key = HashMap::from([
+            (
+                "H3Index".to_string(),
+                AttributeValue::S("87b914582ffffff".to_string()),
+            ),
+            (
+                "Address".to_string(),
+                AttributeValue::S("1 Main St".to_string()),
+            ),
+        ]);
+        let filter = HouseFilter::default();
+        for key in [Some(key), Some(HashMap::new())] {
+            let result =
+                HouseItem::list_by_h3_indices_from_db(&h3_indices, &filter, Some(2), key, db).await;
+            let error = result.err().unwrap();
+            assert!(error.downcast_ref::<InvalidKeyError>().is_some());
+        }
+    }
+
+    fn filter_query(filter: &HouseFilter) -> QueryInput {
+        filter
+            .apply(QueryInput::builder().table_name("Test-Houses"))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_empty_filter_has_no_expression() {
+        let query = filter_query(&HouseFilter::default());
+        assert_eq!(query.filter_expression, None);
+        assert_eq!(query.expression_attribute_names, None);
+        assert_eq!(query.expression_attribute_values, None);
+    }
+
+    #[test]
+    fn test_filter_expression() {
+        let filter = HouseFilter {
+            min_price: Some(500000),
+            max_price: Some(800000),
+            min_carspaces: Some(1),
+            property_types: vec!["House".to_string(), "Townhouse".to_string()],
+            ..Default::default()
+        };
+        let query = filter_query(&filter);
+        assert_eq!(
+            query.filter_expression.unwrap(),
+            "#PriceUpper >= :min_price AND #PriceLower <= :max_price \
+             AND #NumCarSpaces >= :min_carspaces \
+             AND #PropertyType IN (:property_type_0, :property_type_1)"
+        );
+        let names = query.expression_attribute_names.unwrap();
+        assert_eq!(names["#PriceUpper"], "PriceUpper");
+        assert_eq!(names["#NumCarSpaces"], "NumCarSpaces");
+        assert_eq!(names["#PropertyType"], "PropertyType");
+        assert!(!names.contains_key("#NumBedrooms"));
+        let values = query.expression_attribute_values.unwrap();
+        assert_eq!(
+            values[":min_price"],
+            AttributeValue::N("500000".to_string())
+        );
+        assert_eq!(
+            values[":max_price"],
+            AttributeValue::N("800000".to_string())
+        );
+        assert_eq!(
+            values[":property_type_1"],
+            AttributeValue::S("Townhouse".to_string())
+        );
+        assert_eq!(values.len(), 5);
+    }
+}
@@ -1,7 +1,11 @@
+// Lets the code generated by database_derive name this crate from inside it too
+extern crate self as database;
+
 pub mod attribute_value_parser;
 pub mod dynamodb_client_cloud;
 pub mod dynamodb_client_local;
 pub mod dynamodb_client_trait;
+pub mod geocode_item;
 pub mod house_item;
 pub mod paginated_models;
 pub mod requirement_item;
@@ -3,9 +3,22 @@ use aws_sdk_dynamodb::types::AttributeValue;
 use base64::{engine::general_purpose, Engine as _};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
 
 pub type DbKey = HashMap<String, AttributeValue>;
 
+// A pagination key from the client that doesn't match the listing it was passed to
+#[derive(Debug)]
+pub struct InvalidKeyError;
+
+impl fmt::Display for InvalidKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid last_evaluated_key")
+    }
+}
+
+impl std::error::Error for InvalidKeyError {}
+
 pub fn serialise_db_key(db_key: DbKey) -> Result<String> {
     let json_map: HashMap<_, _> = db_key
         .into_iter()
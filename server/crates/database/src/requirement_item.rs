@@ -1,22 +1,27 @@
-use super::attribute_value_parser::parse_attribute_value;
 use super::dynamodb_client_trait::IDynamoDbClient;
 use anyhow::Error;
-use aws_sdk_dynamodb::types::{AttributeValue, Get, Put, TransactGetItem, TransactWriteItem};
-use chrono::{Duration, Utc};
+use database_derive::DynamoItem;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env};
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct MapTile {
     pub h3_index: String,
     pub score: i32,
 }
 
+#[derive(Debug, PartialEq, DynamoItem)]
+#[dynamo(table_env = "REQUIREMENTS_TABLE_NAME", ttl_days = 1)]
 pub struct RequirementItem {
+    #[dynamo(gsi_partition_key = "CityCodeIndex")]
     pub city_code: String,
+    #[dynamo(partition_key)]
     pub requirement_id: Uuid,
+    #[dynamo(with = "map_tiles")]
     pub map_tiles: Vec<MapTile>,
+    pub request: Option<String>,
+    pub content_hash: Option<String>,
 }
 
 impl RequirementItem {
@@ -24,7 +29,7 @@ impl RequirementItem {
         requirement_id: &Uuid,
         db: &dyn IDynamoDbClient,
     ) -> Result<Option<Self>, Error> {
-        let transaction = Self::get(&requirement_id.to_string())?;
+        let transaction = Self::get(requirement_id)?;
         let output = match db.read_single(transaction).await? {
             Some(output) => output,
             None => return Ok(None),
@@ -34,69 +39,78 @@ impl RequirementItem {
         Ok(Some(item))
     }
 
-    pub fn from_map(hash_map: &HashMap<String, AttributeValue>) -> Result<Self, Error> {
-        let city_code = parse_attribute_value::<String>(hash_map.get("CityCode"))?;
-        let requirement_id = parse_attribute_value::<Uuid>(hash_map.get("RequirementId"))?;
-        let map_tiles_str = parse_attribute_value::<String>(hash_map.get("MapTiles"))?;
-        let map_tiles = RequirementItem::deserialise_map_tiles(&map_tiles_str)?;
-        let item = Self {
-            city_code,
-            requirement_id,
-            map_tiles,
-        };
-        Ok(item)
+    // In the order of the ids, with None for requirements that don't exist
+    pub async fn batch_from_db(
+        requirement_ids: &[Uuid],
+        db: &dyn IDynamoDbClient,
+    ) -> Result<Vec<Option<Self>>, Error> {
+        // Batch gets can't repeat a key
+        let unique_ids: HashSet<&Uuid> = requirement_ids.iter().collect();
+        let transactions = unique_ids
+            .into_iter()
+            .map(Self::get)
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut items = HashMap::new();
+        for attributes in db.batch_get(transactions).await? {
+            let item = Self::from_map(&attributes)?;
+            items.insert(item.requirement_id, item);
+        }
+        let items = requirement_ids
+            .iter()
+            .map(|requirement_id| items.remove(requirement_id))
+            .collect();
+        Ok(items)
     }
+}
 
-    fn get_table_name() -> Result<String, Error> {
-        let name: String = env::var("REQUIREMENTS_TABLE_NAME")?;
-        Ok(name)
-    }
+// Map tiles are stored as a JSON string
+mod map_tiles {
+    use super::MapTile;
+    use crate::attribute_value_parser::parse_attribute_value;
+    use anyhow::Error;
+    use aws_sdk_dynamodb::types::AttributeValue;
 
-    pub fn get(requirement_id: &str) -> Result<TransactGetItem, Error> {
-        let item = Get::builder()
-            .table_name(Self::get_table_name()?)
-            .key(
-                "RequirementId",
-                AttributeValue::S(requirement_id.to_string()),
-            )
-            .build()?;
-        let transaction_item = TransactGetItem::builder().get(item).build();
-        Ok(transaction_item)
+    pub fn parse(value: Option<&AttributeValue>) -> Result<Vec<MapTile>, Error> {
+        let json_str = parse_attribute_value::<String>(value)?;
+        Ok(serde_json::from_str(&json_str)?)
     }
 
-    pub fn save(&self) -> Result<TransactWriteItem, Error> {
-        let ttl_timestamp = (Utc::now() + Duration::days(1)).timestamp();
-        let put_item = Put::builder()
-            .table_name(Self::get_table_name()?)
-            .item("CityCode", AttributeValue::S(self.city_code.clone()))
-            .item(
-                "RequirementId",
-                AttributeValue::S(self.requirement_id.to_string()),
-            )
-            .item("MapTiles", AttributeValue::S(self.serialise_map_tiles()?))
-            .item("TimeToLive", AttributeValue::N(ttl_timestamp.to_string()))
-            .build()?;
-        let transaction_item = TransactWriteItem::builder().put(put_item).build();
-        Ok(transaction_item)
+    pub fn serialise(map_tiles: &[MapTile]) -> Result<Option<AttributeValue>, Error> {
+        Ok(Some(AttributeValue::S(serde_json::to_string(map_tiles)?)))
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn delete(&self) -> Result<TransactWriteItem, Error> {
-        let delete_item = aws_sdk_dynamodb::types::Delete::builder()
-            .table_name(Self::get_table_name()?)
-            .key(
-                "RequirementId",
-                AttributeValue::S(self.requirement_id.to_string()),
-            )
-            .build()?;
-        let transaction_item = TransactWriteItem::builder().delete(delete_item).build();
-        Ok(transaction_item)
+    fn requirement(request: Option<String>) -> RequirementItem {
+        RequirementItem {
+            city_code: "Adelaide".to_string(),
+            requirement_id: Uuid::now_v7(),
+            map_tiles: vec![MapTile {
+                h3_index: "87b916804ffffff".to_string(),
+                score: 12,
+            }],
+            request,
+            content_hash: None,
+        }
     }
 
-    pub fn deserialise_map_tiles(json_str: &str) -> Result<Vec<MapTile>, Error> {
-        Ok(serde_json::from_str(json_str)?)
+    #[test]
+    fn test_round_trip() {
+        for request in [None, Some("{}".to_string())] {
+            let requirement = requirement(request);
+            let hash_map = requirement.to_map().unwrap();
+            assert_eq!(RequirementItem::from_map(&hash_map).unwrap(), requirement);
+        }
     }
 
-    pub fn serialise_map_tiles(&self) -> Result<String, Error> {
-        Ok(serde_json::to_string(&self.map_tiles)?)
+    #[test]
+    fn test_empty_options_are_left_out() {
+        let hash_map = requirement(None).to_map().unwrap();
+        assert!(!hash_map.contains_key("Request"));
+        assert!(!hash_map.contains_key("ContentHash"));
+        assert!(hash_map.contains_key("TimeToLive"));
     }
 }
@@ -1,30 +1,112 @@
-use super::attribute_value_parser::parse_attribute_value;
-use crate::dynamodb_client_trait::IDynamoDbClient;
+use crate::dynamodb_client_trait::{query_all, IDynamoDbClient, PageLimits};
 use anyhow::{Error, Ok};
-use aws_sdk_dynamodb::{
-    operation::query::QueryInput,
-    types::{AttributeValue, Get, Put, TransactGetItem, TransactWriteItem},
-};
-use std::{collections::HashMap, env};
-use uuid::Uuid;
-
-#[derive(Clone, Debug)]
+use aws_sdk_dynamodb::{operation::query::QueryInput, types::AttributeValue};
+use chrono::{DateTime, Datelike, Days, NaiveTime, TimeZone, Weekday};
+use database_derive::DynamoItem;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TimeWindow {
+    WeekdayAmPeak,
+    WeekdayPmPeak,
+    OffPeak,
+    Weekend,
+}
+
+impl TimeWindow {
+    pub const ALL: [TimeWindow; 4] = [
+        TimeWindow::WeekdayAmPeak,
+        TimeWindow::WeekdayPmPeak,
+        TimeWindow::OffPeak,
+        TimeWindow::Weekend,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            TimeWindow::WeekdayAmPeak => "WeekdayAmPeak",
+            TimeWindow::WeekdayPmPeak => "WeekdayPmPeak",
+            TimeWindow::OffPeak => "OffPeak",
+            TimeWindow::Weekend => "Weekend",
+        }
+    }
+
+    // A typical departure for the window, on a Tuesday for weekdays and a Saturday for weekends
+    fn departure(&self) -> (Weekday, NaiveTime) {
+        let (weekday, hour, minute) = match self {
+            TimeWindow::WeekdayAmPeak => (Weekday::Tue, 8, 0),
+            TimeWindow::WeekdayPmPeak => (Weekday::Tue, 17, 30),
+            TimeWindow::OffPeak => (Weekday::Tue, 11, 0),
+            TimeWindow::Weekend => (Weekday::Sat, 11, 0),
+        };
+        let time = NaiveTime::from_hms_opt(hour, minute, 0).unwrap_or_default();
+        (weekday, time)
+    }
+
+    // The first departure for the window after `after`, in the city's time zone.
+    // Routing APIs only accept departure times in the future.
+    pub fn next_departure<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let (weekday, time) = self.departure();
+        let date = after.date_naive();
+        (0..=7)
+            .filter_map(|days| date.checked_add_days(Days::new(days)))
+            .filter(|date| date.weekday() == weekday)
+            .filter_map(|date| {
+                after
+                    .timezone()
+                    .from_local_datetime(&date.and_time(time))
+                    .earliest()
+            })
+            .find(|departure| departure > after)
+    }
+}
+
+// Driving and transit durations depend on when you leave, walking and cycling don't
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowDurations {
+    pub duration_drive: i32,
+    pub duration_transit: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, DynamoItem)]
+#[dynamo(table_env = "SPATIAL_DISTANCES_TABLE_NAME")]
 pub struct SpatialDistanceItem {
+    #[dynamo(gsi_partition_key = "CityCodeIndex")]
     pub city_code: String,
+    #[dynamo(partition_key, gsi_sort_key = "CityCodeIndex")]
     pub source_index: String,
+    #[dynamo(sort_key)]
     pub destination_index: String,
     pub duration_walk: i32,
     pub duration_cycle: i32,
     pub duration_drive: i32,
     pub duration_transit: i32,
+    #[dynamo(flatten, with = "window_durations")]
+    pub window_durations: BTreeMap<TimeWindow, WindowDurations>,
 }
 
 impl SpatialDistanceItem {
+    // Falls back to the undated duration if the window hasn't been populated
+    pub fn duration_drive_at(&self, time_window: Option<TimeWindow>) -> i32 {
+        time_window
+            .and_then(|window| self.window_durations.get(&window))
+            .map_or(self.duration_drive, |durations| durations.duration_drive)
+    }
+
+    pub fn duration_transit_at(&self, time_window: Option<TimeWindow>) -> i32 {
+        time_window
+            .and_then(|window| self.window_durations.get(&window))
+            .map_or(self.duration_transit, |durations| {
+                durations.duration_transit
+            })
+    }
+
     pub async fn from_db(
-        requirement_id: &Uuid,
+        source_index: &str,
+        destination_index: &str,
         db: &dyn IDynamoDbClient,
     ) -> Result<Option<Self>, Error> {
-        let transaction = Self::get(&requirement_id.to_string())?;
+        let transaction = Self::get(source_index, destination_index)?;
         let output = match db.read_single(transaction).await? {
             Some(output) => output,
             None => return Ok(None),
@@ -34,50 +116,12 @@ impl SpatialDistanceItem {
         Ok(Some(item))
     }
 
-    pub fn from_map(hash_map: &HashMap<String, AttributeValue>) -> Result<Self, Error> {
-        let city_code = parse_attribute_value::<String>(hash_map.get("CityCode"))?;
-        let source_index = parse_attribute_value::<String>(hash_map.get("SourceIndex"))?;
-        let destination_index = parse_attribute_value::<String>(hash_map.get("DestinationIndex"))?;
-        let duration_walk = parse_attribute_value::<i32>(hash_map.get("DurationWalk"))?;
-        let duration_cycle = parse_attribute_value::<i32>(hash_map.get("DurationCycle"))?;
-        let duration_drive = parse_attribute_value::<i32>(hash_map.get("DurationDrive"))?;
-        let duration_transit = parse_attribute_value::<i32>(hash_map.get("DurationTransit"))?;
-        let item = Self {
-            city_code,
-            source_index,
-            destination_index,
-            duration_walk,
-            duration_cycle,
-            duration_drive,
-            duration_transit,
-        };
-        Ok(item)
-    }
-
-    pub fn get_table_name() -> Result<String, Error> {
-        let name: String = env::var("SPATIAL_DISTANCES_TABLE_NAME")?;
-        Ok(name)
-    }
-
-    pub fn get(requirement_id: &str) -> Result<TransactGetItem, Error> {
-        let item = Get::builder()
-            .table_name(Self::get_table_name()?)
-            .key(
-                "RequirementId",
-                AttributeValue::S(requirement_id.to_string()),
-            )
-            .build()?;
-        let transaction_item = TransactGetItem::builder().get(item).build();
-        Ok(transaction_item)
-    }
-
     pub async fn list_by_city_from_db(
         city_code: &str,
         db: &dyn IDynamoDbClient,
     ) -> Result<Vec<Self>, Error> {
         let query_input = Self::query_by_city(city_code)?;
-        let query_output = db.query(query_input).await?;
-        let items = query_output.items.unwrap_or_default();
+        let items = query_all(db, query_input, PageLimits::default()).await?;
         let mut results = Vec::new();
         for item in items {
             let spatial_distance_item = Self::from_map(&item)?;
@@ -91,8 +135,7 @@ impl SpatialDistanceItem {
         db: &dyn IDynamoDbClient,
     ) -> Result<Vec<Self>, Error> {
         let query_input = Self::query_by_source_index(source_index)?;
-        let query_output = db.query(query_input).await?;
-        let items = query_output.items.unwrap_or_default();
+        let items = query_all(db, query_input, PageLimits::default()).await?;
         let mut results = Vec::new();
         for item in items {
             let spatial_distance_item = Self::from_map(&item)?;
@@ -101,39 +144,6 @@ impl SpatialDistanceItem {
         Ok(results)
     }
 
-    pub fn save(&self) -> Result<TransactWriteItem, Error> {
-        let put_item = Put::builder()
-            .table_name(Self::get_table_name()?)
-            .item("CityCode", AttributeValue::S(self.city_code.clone()))
-            .item(
-                "SourceIndex",
-                AttributeValue::S(self.source_index.to_string()),
-            )
-            .item(
-                "DestinationIndex",
-                AttributeValue::S(self.destination_index.to_string()),
-            )
-            .item(
-                "DurationWalk",
-                AttributeValue::N(self.duration_walk.to_string()),
-            )
-            .item(
-                "DurationCycle",
-                AttributeValue::N(self.duration_cycle.to_string()),
-            )
-            .item(
-                "DurationDrive",
-                AttributeValue::N(self.duration_drive.to_string()),
-            )
-            .item(
-                "DurationTransit",
-                AttributeValue::N(self.duration_transit.to_string()),
-            )
-            .build()?;
-        let transaction_item = TransactWriteItem::builder().put(put_item).build();
-        Ok(transaction_item)
-    }
-
     fn query_by_city(city_code: &str) -> Result<QueryInput, Error> {
         let query_input = QueryInput::builder()
             .table_name(Self::get_table_name()?)
@@ -158,3 +168,113 @@ impl SpatialDistanceItem {
         Ok(query_input)
     }
 }
+
+// Each populated window is stored as a pair of DurationDrive{Window} and
+// DurationTransit{Window} attributes
+mod window_durations {
+    use super::{TimeWindow, WindowDurations};
+    use crate::attribute_value_parser::parse_attribute_value;
+    use anyhow::Error;
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use std::collections::{BTreeMap, HashMap};
+
+    pub fn from_map(
+        hash_map: &HashMap<String, AttributeValue>,
+    ) -> Result<BTreeMap<TimeWindow, WindowDurations>, Error> {
+        let mut window_durations = BTreeMap::new();
+        for window in TimeWindow::ALL {
+            let duration_drive = parse_attribute_value::<Option<i32>>(
+                hash_map.get(&format!("DurationDrive{}", window.as_str())),
+            )?;
+            let duration_transit = parse_attribute_value::<Option<i32>>(
+                hash_map.get(&format!("DurationTransit{}", window.as_str())),
+            )?;
+            if let (Some(duration_drive), Some(duration_transit)) =
+                (duration_drive, duration_transit)
+            {
+                window_durations.insert(
+                    window,
+                    WindowDurations {
+                        duration_drive,
+                        duration_transit,
+                    },
+                );
+            }
+        }
+        Ok(window_durations)
+    }
+
+    pub fn to_map(
+        window_durations: &BTreeMap<TimeWindow, WindowDurations>,
+    ) -> Result<HashMap<String, AttributeValue>, Error> {
+        let mut hash_map = HashMap::new();
+        for (window, durations) in window_durations {
+            hash_map.insert(
+                format!("DurationDrive{}", window.as_str()),
+                AttributeValue::N(durations.duration_drive.to_string()),
+            );
+            hash_map.insert(
+                format!("DurationTransit{}", window.as_str()),
+                AttributeValue::N(durations.duration_transit.to_string()),
+            );
+        }
+        Ok(hash_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spatial_distance(
+        window_durations: BTreeMap<TimeWindow, WindowDurations>,
+    ) -> SpatialDistanceItem {
+        SpatialDistanceItem {
+            city_code: "Adelaide".to_string(),
+            source_index: "87b914582ffffff".to_string(),
+            destination_index: "87b916804ffffff".to_string(),
+            duration_walk: 3600,
+            duration_cycle: 1200,
+            duration_drive: 600,
+            duration_transit: 1500,
+            window_durations,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let window_durations = BTreeMap::from([(
+            TimeWindow::WeekdayAmPeak,
+            WindowDurations {
+                duration_drive: 900,
+                duration_transit: 1800,
+            },
+        )]);
+        for window_durations in [BTreeMap::new(), window_durations] {
+            let spatial_distance = spatial_distance(window_durations);
+            let hash_map = spatial_distance.to_map().unwrap();
+            assert_eq!(
+                SpatialDistanceItem::from_map(&hash_map).unwrap(),
+                spatial_distance
+            );
+        }
+    }
+
+    #[test]
+    fn test_window_attributes() {
+        let window_durations = BTreeMap::from([(
+            TimeWindow::Weekend,
+            WindowDurations {
+                duration_drive: 700,
+                duration_transit: 2000,
+            },
+        )]);
+        let hash_map = spatial_distance(window_durations).to_map().unwrap();
+        assert_eq!(
+            hash_map.get("DurationDriveWeekend"),
+            Some(&AttributeValue::N("700".to_string()))
+        );
+        assert!(!hash_map.contains_key("DurationDriveOffPeak"));
+        assert!(!hash_map.contains_key("TimeToLive"));
+    }
+}
@@ -0,0 +1,573 @@
+// Behaviour both IDynamoDbClient implementations must share. Runs against the local fake by
+// default, or against DynamoDB Local with --features dynamodb-local
+use aws_sdk_dynamodb::operation::query::{QueryInput, QueryOutput};
+use aws_sdk_dynamodb::operation::scan::ScanInput;
+use aws_sdk_dynamodb::types::{
+    AttributeValue, Delete, Get, Put, TransactGetItem, TransactWriteItem,
+};
+use database::attribute_value_parser::parse_attribute_value;
+use database::dynamodb_client_trait::{query_all, scan_stream, IDynamoDbClient, PageLimits};
+use futures::TryStreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+const HOUSES_TABLE: &str = "Contract-Houses";
+const REQUIREMENTS_TABLE: &str = "Contract-Requirements";
+
+type Item = HashMap<String, AttributeValue>;
+
+// Loading the fake's data takes a while, so every test shares one
+#[cfg(not(feature = "dynamodb-local"))]
+async fn client() -> Arc<dyn IDynamoDbClient> {
+    static CLIENT: OnceCell<Arc<dyn IDynamoDbClient>> = OnceCell::const_new();
+    CLIENT
+        .get_or_init(|| async {
+            let client = database::dynamodb_client_local::DynamoDbClient::new()
+                .await
+                .unwrap();
+            Arc::new(client) as Arc<dyn IDynamoDbClient>
+        })
+        .await
+        .clone()
+}
+
+// SDK clients can't outlive the test's runtime, so only creating the tables is shared
+#[cfg(feature = "dynamodb-local")]
+async fn client() -> Arc<dyn IDynamoDbClient> {
+    static TABLES: OnceCell<()> = OnceCell::const_new();
+    TABLES.get_or_init(create_tables).await;
+    Arc::new(
+        database::dynamodb_client_cloud::DynamoDbClient::new()
+            .await
+            .unwrap(),
+    )
+}
+
+// Expects DYNAMODB_ENDPOINT_URL, AWS_REGION and dummy credentials
+#[cfg(feature = "dynamodb-local")]
+async fn create_tables() {
+    use aws_sdk_dynamodb::types::{
+        AttributeDefinition, BillingMode, GlobalSecondaryIndex, KeySchemaElement, KeyType,
+        Projection, ProjectionType, ScalarAttributeType,
+    };
+
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let endpoint_url = std::env::var("DYNAMODB_ENDPOINT_URL").unwrap();
+    let dynamodb_config = aws_sdk_dynamodb::config::Builder::from(&config)
+        .endpoint_url(endpoint_url)
+        .build();
+    let client = aws_sdk_dynamodb::Client::from_conf(dynamodb_config);
+    let key = |column: &str, key_type: KeyType| {
+        KeySchemaElement::builder()
+            .attribute_name(column)
+            .key_type(key_type)
+            .build()
+            .unwrap()
+    };
+    let attribute = |column: &str| {
+        AttributeDefinition::builder()
+            .attribute_name(column)
+            .attribute_type(ScalarAttributeType::S)
+            .build()
+            .unwrap()
+    };
+    let city_code_index = GlobalSecondaryIndex::builder()
+        .index_name("CityCodeIndex")
+        .key_schema(key("CityCode", KeyType::Hash))
+        .key_schema(key("H3Index", KeyType::Range))
+        .projection(
+            Projection::builder()
+                .projection_type(ProjectionType::All)
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let tables = [
+        client
+            .create_table()
+            .table_name(HOUSES_TABLE)
+            .key_schema(key("H3Index", KeyType::Hash))
+            .key_schema(key("Address", KeyType::Range))
+            .attribute_definitions(attribute("H3Index"))
+            .attribute_definitions(attribute("Address"))
+            .attribute_definitions(attribute("CityCode"))
+            .global_secondary_indexes(city_code_index),
+        client
+            .create_table()
+            .table_name(REQUIREMENTS_TABLE)
+            .key_schema(key("RequirementId", KeyType::Hash))
+            .attribute_definitions(attribute("RequirementId")),
+    ];
+    for table in tables {
+        if let Err(error) = table.billing_mode(BillingMode::PayPerRequest).send().await {
+            let exists = error
+                .as_service_error()
+                .is_some_and(|error| error.is_resource_in_use_exception());
+            assert!(exists, "Failed to create table: {:?}", error);
+        }
+    }
+}
+
+// Every test writes under its own keys, so they can share tables and run in parallel
+fn unique(prefix: &str) -> String {
+    format!("{}-{}", prefix, Uuid::now_v7())
+}
+
+fn s(value: &str) -> AttributeValue {
+    AttributeValue::S(value.to_string())
+}
+
+fn n(value: i32) -> AttributeValue {
+    AttributeValue::N(value.to_string())
+}
+
+fn house(h3_index: &str, address: &str, city_code: &str, num_bedrooms: i32) -> Item {
+    HashMap::from([
+        ("H3Index".to_string(), s(h3_index)),
+        ("Address".to_string(), s(address)),
+        ("CityCode".to_string(), s(city_code)),
+        ("NumBedrooms".to_string(), n(num_bedrooms)),
+    ])
+}
+
+fn house_key(h3_index: &str, address: &str) -> Item {
+    HashMap::from([
+        ("H3Index".to_string(), s(h3_index)),
+        ("Address".to_string(), s(address)),
+    ])
+}
+
+fn requirement(requirement_id: &str, version: i32) -> Item {
+    HashMap::from([
+        ("RequirementId".to_string(), s(requirement_id)),
+        ("Version".to_string(), n(version)),
+    ])
+}
+
+fn requirement_key(requirement_id: &str) -> Item {
+    HashMap::from([("RequirementId".to_string(), s(requirement_id))])
+}
+
+fn put(table_name: &str, item: Item) -> TransactWriteItem {
+    let put = Put::builder()
+        .table_name(table_name)
+        .set_item(Some(item))
+        .build()
+        .unwrap();
+    TransactWriteItem::builder().put(put).build()
+}
+
+// A write that only succeeds if the stored version is still old_version
+fn versioned_put(requirement_id: &str, old_version: i32) -> TransactWriteItem {
+    let put = Put::builder()
+        .table_name(REQUIREMENTS_TABLE)
+        .set_item(Some(requirement(requirement_id, old_version + 1)))
+        .condition_expression("#version = :old_version")
+        .expression_attribute_names("#version", "Version")
+        .expression_attribute_values(":old_version", n(old_version))
+        .build()
+        .unwrap();
+    TransactWriteItem::builder().put(put).build()
+}
+
+fn versioned_delete(requirement_id: &str, old_version: i32) -> TransactWriteItem {
+    let delete = Delete::builder()
+        .table_name(REQUIREMENTS_TABLE)
+        .set_key(Some(requirement_key(requirement_id)))
+        .condition_expression("#version = :old_version")
+        .expression_attribute_names("#version", "Version")
+        .expression_attribute_values(":old_version", n(old_version))
+        .build()
+        .unwrap();
+    TransactWriteItem::builder().delete(delete).build()
+}
+
+async fn get(client: &dyn IDynamoDbClient, table_name: &str, key: Item) -> Option<Item> {
+    let get = Get::builder()
+        .table_name(table_name)
+        .set_key(Some(key))
+        .build()
+        .unwrap();
+    let item = TransactGetItem::builder().get(get).build();
+    let response = client.read_single(item).await.unwrap();
+    response.and_then(|response| response.item)
+}
+
+fn addresses(output: &QueryOutput) -> Vec<String> {
+    output
+        .items()
+        .iter()
+        .map(|item| parse_attribute_value::<String>(item.get("Address")).unwrap())
+        .collect()
+}
+
+// Follows last_evaluated_key until the last page, returning the addresses on each page
+async fn query_pages(client: &dyn IDynamoDbClient, query: QueryInput) -> Vec<Vec<String>> {
+    let mut pages = vec![];
+    let mut query = query;
+    loop {
+        let output = client.query(query.clone()).await.unwrap();
+        pages.push(addresses(&output));
+        match output.last_evaluated_key {
+            Some(last_evaluated_key) => query.exclusive_start_key = Some(last_evaluated_key),
+            None => return pages,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_put_get_delete() {
+    let client = client().await;
+    let h3_index = unique("h3");
+    let item = house(&h3_index, "1 King William St", "Adelaide", 3);
+    client
+        .write_single(put(HOUSES_TABLE, item.clone()))
+        .await
+        .unwrap();
+    let key = house_key(&h3_index, "1 King William St");
+    assert_eq!(
+        get(client.as_ref(), HOUSES_TABLE, key.clone()).await,
+        Some(item)
+    );
+
+    let delete = Delete::builder()
+        .table_name(HOUSES_TABLE)
+        .set_key(Some(key.clone()))
+        .build()
+        .unwrap();
+    client
+        .write_single(TransactWriteItem::builder().delete(delete).build())
+        .await
+        .unwrap();
+    assert_eq!(get(client.as_ref(), HOUSES_TABLE, key).await, None);
+}
+
+#[tokio::test]
+async fn test_missing_items() {
+    let client = client().await;
+    let requirement_id = unique("requirement");
+    assert_eq!(
+        get(
+            client.as_ref(),
+            REQUIREMENTS_TABLE,
+            requirement_key(&requirement_id)
+        )
+        .await,
+        None
+    );
+
+    // Deleting a missing item succeeds, unless the condition needs it to exist
+    let delete = Delete::builder()
+        .table_name(REQUIREMENTS_TABLE)
+        .set_key(Some(requirement_key(&requirement_id)))
+        .build()
+        .unwrap();
+    client
+        .write_single(TransactWriteItem::builder().delete(delete).build())
+        .await
+        .unwrap();
+    assert!(client
+        .write_single(versioned_delete(&requirement_id, 1))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_conditional_put() {
+    let client = client().await;
+    let requirement_id = unique("requirement");
+    let create = |version: i32| {
+        let put = Put::builder()
+            .table_name(REQUIREMENTS_TABLE)
+            .set_item(Some(requirement(&requirement_id, version)))
+            .condition_expression("attribute_not_exists(RequirementId)")
+            .build()
+            .unwrap();
+        TransactWriteItem::builder().put(put).build()
+    };
+    client.write_single(create(1)).await.unwrap();
+    assert!(client.write_single(create(2)).await.is_err());
+    assert_eq!(
+        get(
+            client.as_ref(),
+            REQUIREMENTS_TABLE,
+            requirement_key(&requirement_id)
+        )
+        .await,
+        Some(requirement(&requirement_id, 1))
+    );
+}
+
+#[tokio::test]
+async fn test_versioning() {
+    let client = client().await;
+    let requirement_id = unique("requirement");
+    client
+        .write_single(put(REQUIREMENTS_TABLE, requirement(&requirement_id, 1)))
+        .await
+        .unwrap();
+    client
+        .write_single(versioned_put(&requirement_id, 1))
+        .await
+        .unwrap();
+    // A second writer that read version 1 loses
+    assert!(client
+        .write_single(versioned_put(&requirement_id, 1))
+        .await
+        .is_err());
+    assert!(client
+        .write_single(versioned_delete(&requirement_id, 1))
+        .await
+        .is_err());
+    assert_eq!(
+        get(
+            client.as_ref(),
+            REQUIREMENTS_TABLE,
+            requirement_key(&requirement_id)
+        )
+        .await,
+        Some(requirement(&requirement_id, 2))
+    );
+    client
+        .write_single(versioned_delete(&requirement_id, 2))
+        .await
+        .unwrap();
+    assert_eq!(
+        get(
+            client.as_ref(),
+            REQUIREMENTS_TABLE,
+            requirement_key(&requirement_id)
+        )
+        .await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_failed_transaction_writes_nothing() {
+    let client = client().await;
+    let requirement_id = unique("requirement");
+    let h3_index = unique("h3");
+    let items = vec![
+        put(
+            HOUSES_TABLE,
+            house(&h3_index, "1 King William St", "Adelaide", 3),
+        ),
+        versioned_put(&requirement_id, 1),
+    ];
+    assert!(client.write(items).await.is_err());
+    assert_eq!(
+        get(
+            client.as_ref(),
+            HOUSES_TABLE,
+            house_key(&h3_index, "1 King William St")
+        )
+        .await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_query_pages_by_sort_key() {
+    let client = client().await;
+    let h3_index = unique("h3");
+    for address in ["e", "a", "d", "b", "c"] {
+        client
+            .write_single(put(HOUSES_TABLE, house(&h3_index, address, "Adelaide", 2)))
+            .await
+            .unwrap();
+    }
+    let query = QueryInput::builder()
+        .table_name(HOUSES_TABLE)
+        .key_condition_expression("#h3_index = :h3_index")
+        .expression_attribute_names("#h3_index", "H3Index")
+        .expression_attribute_values(":h3_index", s(&h3_index))
+        .limit(2)
+        .build()
+        .unwrap();
+    assert_eq!(
+        query_pages(client.as_ref(), query).await,
+        vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]
+    );
+}
+
+#[tokio::test]
+async fn test_query_index_with_filter() {
+    let client = client().await;
+    let city_code = unique("city");
+    for (h3_index, num_bedrooms) in [("h3-1", 1), ("h3-2", 4), ("h3-3", 2), ("h3-4", 5)] {
+        let item = house(
+            h3_index,
+            &format!("{} {}", city_code, h3_index),
+            &city_code,
+            num_bedrooms,
+        );
+        client.write_single(put(HOUSES_TABLE, item)).await.unwrap();
+    }
+    // The limit counts items read before the filter, so pages can be empty
+    let query = QueryInput::builder()
+        .table_name(HOUSES_TABLE)
+        .index_name("CityCodeIndex")
+        .key_condition_expression("#city_code = :city_code")
+        .filter_expression("#num_bedrooms >= :num_bedrooms")
+        .expression_attribute_names("#city_code", "CityCode")
+        .expression_attribute_names("#num_bedrooms", "NumBedrooms")
+        .expression_attribute_values(":city_code", s(&city_code))
+        .expression_attribute_values(":num_bedrooms", n(4))
+        .limit(1)
+        .build()
+        .unwrap();
+    let pages = query_pages(client.as_ref(), query).await;
+    assert!(pages[0].is_empty());
+    let matches: Vec<String> = pages.into_iter().flatten().collect();
+    assert_eq!(
+        matches,
+        vec![format!("{} h3-2", city_code), format!("{} h3-4", city_code)]
+    );
+}
+
+#[tokio::test]
+async fn test_batch_write_and_get() {
+    let client = client().await;
+    let h3_index = unique("h3");
+    // More than one BatchWriteItem and one BatchGetItem can hold
+    let addresses: Vec<String> = (0..120).map(|i| format!("{:03} Rundle Mall", i)).collect();
+    let items = addresses
+        .iter()
+        .map(|address| put(HOUSES_TABLE, house(&h3_index, address, "Adelaide", 1)))
+        .collect();
+    client.batch_write(items).await.unwrap();
+
+    let get = |address: &str| {
+        let get = Get::builder()
+            .table_name(HOUSES_TABLE)
+            .set_key(Some(house_key(&h3_index, address)))
+            .build()
+            .unwrap();
+        TransactGetItem::builder().get(get).build()
+    };
+    let mut gets: Vec<TransactGetItem> = addresses.iter().map(|address| get(address)).collect();
+    gets.push(get("Not an address"));
+    let mut found: Vec<String> = client
+        .batch_get(gets)
+        .await
+        .unwrap()
+        .iter()
+        .map(|item| parse_attribute_value::<String>(item.get("Address")).unwrap())
+        .collect();
+    found.sort();
+    assert_eq!(found, addresses);
+
+    let deletes = addresses[..100]
+        .iter()
+        .map(|address| {
+            let delete = Delete::builder()
+                .table_name(HOUSES_TABLE)
+                .set_key(Some(house_key(&h3_index, address)))
+                .build()
+                .unwrap();
+            TransactWriteItem::builder().delete(delete).build()
+        })
+        .collect();
+    client.batch_write(deletes).await.unwrap();
+    let gets = addresses.iter().map(|address| get(address)).collect();
+    assert_eq!(client.batch_get(gets).await.unwrap().len(), 20);
+}
+
+#[tokio::test]
+async fn test_batch_write_rejects_conditions() {
+    let client = client().await;
+    let requirement_id = unique("requirement");
+    assert!(client
+        .batch_write(vec![versioned_put(&requirement_id, 1)])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_scan_stream() {
+    let client = client().await;
+    let city_code = unique("city");
+    let items = (0..7)
+        .map(|i| {
+            let item = house(&unique("h3"), &format!("{} Grote St", i), &city_code, i);
+            put(HOUSES_TABLE, item)
+        })
+        .collect();
+    client.batch_write(items).await.unwrap();
+    let scan = ScanInput::builder()
+        .table_name(HOUSES_TABLE)
+        .index_name("CityCodeIndex")
+        .filter_expression("#city_code = :city_code")
+        .expression_attribute_names("#city_code", "CityCode")
+        .expression_attribute_values(":city_code", s(&city_code))
+        .limit(3)
+        .build()
+        .unwrap();
+    let items: Vec<Item> = scan_stream(client.as_ref(), scan, PageLimits::default())
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 7);
+}
+
+#[tokio::test]
+async fn test_query_all_limits() {
+    let client = client().await;
+    let h3_index = unique("h3");
+    let items = ["a", "b", "c", "d", "e"]
+        .iter()
+        .map(|address| put(HOUSES_TABLE, house(&h3_index, address, "Adelaide", 2)))
+        .collect();
+    client.batch_write(items).await.unwrap();
+    // Two houses to a page
+    let query = QueryInput::builder()
+        .table_name(HOUSES_TABLE)
+        .key_condition_expression("#h3_index = :h3_index")
+        .expression_attribute_names("#h3_index", "H3Index")
+        .expression_attribute_values(":h3_index", s(&h3_index))
+        .limit(2)
+        .build()
+        .unwrap();
+    let query_addresses = |limits: PageLimits| {
+        let query = query.clone();
+        let client = client.clone();
+        async move {
+            let items = query_all(client.as_ref(), query, limits).await.unwrap();
+            items
+                .iter()
+                .map(|item| parse_attribute_value::<String>(item.get("Address")).unwrap())
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(
+        query_addresses(PageLimits::default()).await,
+        vec!["a", "b", "c", "d", "e"]
+    );
+    let max_items = PageLimits {
+        max_items: Some(3),
+        max_pages: None,
+    };
+    assert_eq!(query_addresses(max_items).await, vec!["a", "b", "c"]);
+    let max_pages = PageLimits {
+        max_items: None,
+        max_pages: Some(2),
+    };
+    assert_eq!(query_addresses(max_pages).await, vec!["a", "b", "c", "d"]);
+    // Resumes after the query's own start key
+    let mut resumed = query.clone();
+    resumed.exclusive_start_key = Some(HashMap::from([
+        ("H3Index".to_string(), s(&h3_index)),
+        ("Address".to_string(), s("b")),
+    ]));
+    let items = query_all(client.as_ref(), resumed, PageLimits::default())
+        .await
+        .unwrap();
+    let addresses: Vec<String> = items
+        .iter()
+        .map(|item| parse_attribute_value::<String>(item.get("Address")).unwrap())
+        .collect();
+    assert_eq!(addresses, vec!["c", "d", "e"]);
+}
@@ -0,0 +1,164 @@
+use syn::{Data, DeriveInput, Error, Fields, Ident, LitInt, LitStr, Path, Result, Type};
+
+// #[dynamo(table_env = "HOUSES_TABLE_NAME", ttl_days = 1)] on the struct
+pub struct ItemAttributes {
+    pub table_env: LitStr,
+    pub ttl_days: Option<LitInt>,
+}
+
+pub enum KeyRole {
+    PartitionKey,
+    SortKey,
+}
+
+// #[dynamo(name = "NumCarSpaces", partition_key, gsi_sort_key = "CityCodeIndex", ...)] on a field
+pub struct FieldAttributes {
+    pub ident: Ident,
+    pub ty: Type,
+    pub name: String,
+    pub key: Option<KeyRole>,
+    pub indexes: Vec<(String, KeyRole)>,
+    pub ttl: bool,
+    pub with: Option<Path>,
+    pub flatten: bool,
+}
+
+pub fn parse_item(input: &DeriveInput) -> Result<ItemAttributes> {
+    let mut table_env = None;
+    let mut ttl_days = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("dynamo"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table_env") {
+                table_env = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("ttl_days") {
+                ttl_days = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("Expected table_env or ttl_days"));
+            }
+            Ok(())
+        })?;
+    }
+    let table_env = table_env.ok_or(Error::new_spanned(
+        &input.ident,
+        "Missing #[dynamo(table_env = \"...\")]",
+    ))?;
+    Ok(ItemAttributes {
+        table_env,
+        ttl_days,
+    })
+}
+
+pub fn parse_fields(input: &DeriveInput) -> Result<Vec<FieldAttributes>> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "DynamoItem needs named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "DynamoItem can only be derived for structs",
+            ))
+        }
+    };
+    let mut results = Vec::new();
+    for field in fields {
+        let ident = field.ident.clone().unwrap();
+        let mut attributes = FieldAttributes {
+            name: pascal_case(&ident.to_string()),
+            ident,
+            ty: field.ty.clone(),
+            key: None,
+            indexes: Vec::new(),
+            ttl: false,
+            with: None,
+            flatten: false,
+        };
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("dynamo"))
+        {
+            attr.parse_nested_meta(|meta| {
+                let index_name = || -> Result<String> {
+                    let index_name: LitStr = meta.value()?.parse()?;
+                    Ok(index_name.value())
+                };
+                if meta.path.is_ident("name") {
+                    let name: LitStr = meta.value()?.parse()?;
+                    attributes.name = name.value();
+                } else if meta.path.is_ident("partition_key") {
+                    attributes.key = Some(KeyRole::PartitionKey);
+                } else if meta.path.is_ident("sort_key") {
+                    attributes.key = Some(KeyRole::SortKey);
+                } else if meta.path.is_ident("gsi_partition_key") {
+                    attributes
+                        .indexes
+                        .push((index_name()?, KeyRole::PartitionKey));
+                } else if meta.path.is_ident("gsi_sort_key") {
+                    attributes.indexes.push((index_name()?, KeyRole::SortKey));
+                } else if meta.path.is_ident("ttl") {
+                    attributes.ttl = true;
+                } else if meta.path.is_ident("with") {
+                    let with: LitStr = meta.value()?.parse()?;
+                    attributes.with = Some(with.parse()?);
+                } else if meta.path.is_ident("flatten") {
+                    attributes.flatten = true;
+                } else {
+                    return Err(meta.error("Unrecognised dynamo attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        // Keys are written as they are, so they can't be converted or spread over attributes
+        let is_key = attributes.key.is_some() || !attributes.indexes.is_empty();
+        if is_key && (attributes.with.is_some() || attributes.flatten) {
+            return Err(Error::new_spanned(
+                &attributes.ident,
+                "Key attributes can't use with or flatten",
+            ));
+        }
+        if attributes.flatten && attributes.with.is_none() {
+            return Err(Error::new_spanned(
+                &attributes.ident,
+                "flatten needs a with module",
+            ));
+        }
+        results.push(attributes);
+    }
+    Ok(results)
+}
+
+// h3_index -> H3Index
+pub fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pascal_case() {
+        assert_eq!(pascal_case("h3_index"), "H3Index");
+        assert_eq!(pascal_case("time_to_live"), "TimeToLive");
+        assert_eq!(pascal_case("url"), "Url");
+    }
+}
@@ -0,0 +1,365 @@
+mod attributes;
+
+use attributes::{FieldAttributes, ItemAttributes, KeyRole};
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use std::collections::BTreeMap;
+use syn::{parse_macro_input, DeriveInput, Error, Ident, Result, Type};
+
+// Generates from_map and to_map, key builders, get, save and delete, and get_table_name for
+// an item struct in the database crate. Attributes are named after their fields in PascalCase
+// unless they have a name, and are parsed and written with the AttributeValueParser and
+// AttributeValueSerialiser traits, or the parse and serialise functions of a with module.
+// A flattened field's with module has from_map and to_map functions instead.
+//
+//     #[derive(DynamoItem)]
+//     #[dynamo(table_env = "HOUSES_TABLE_NAME", ttl_days = 1)]
+//     pub struct HouseItem {
+//         #[dynamo(partition_key, gsi_sort_key = "CityCodeIndex")]
+//         pub h3_index: String,
+//         #[dynamo(name = "NumCarSpaces")]
+//         pub num_carspaces: i32,
+//         ...
+//     }
+#[proc_macro_derive(DynamoItem, attributes(dynamo))]
+pub fn derive_dynamo_item(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let item = attributes::parse_item(input)?;
+    let fields = attributes::parse_fields(input)?;
+    let ident = &input.ident;
+    let table_name = table_name(&item);
+    let from_map = from_map(&fields);
+    let to_map = to_map(&item, &fields);
+    let keys = keys(input, &fields)?;
+    let ttl = ttl(input, &item, &fields)?;
+    Ok(quote! {
+        impl #ident {
+            #table_name
+            #from_map
+            #to_map
+            #keys
+            #ttl
+        }
+    })
+}
+
+// Tests and the test-support feature fall back to the local fake's table, HOUSES_TABLE_NAME ->
+// Test-Houses, so parallel tests never have to set the environment
+fn table_name(item: &ItemAttributes) -> TokenStream2 {
+    let table_env = &item.table_env;
+    let env = table_env.value();
+    let test_table = format!(
+        "Test-{}",
+        attributes::pascal_case(&env.trim_end_matches("_TABLE_NAME").to_lowercase())
+    );
+    quote! {
+        pub fn get_table_name() -> ::std::result::Result<::std::string::String, ::anyhow::Error> {
+            match ::std::env::var(#table_env) {
+                ::std::result::Result::Ok(name) => ::std::result::Result::Ok(name),
+                #[cfg(any(test, feature = "test-support"))]
+                ::std::result::Result::Err(_) => ::std::result::Result::Ok(#test_table.to_string()),
+                #[cfg(not(any(test, feature = "test-support")))]
+                ::std::result::Result::Err(error) => ::std::result::Result::Err(error.into()),
+            }
+        }
+    }
+}
+
+fn from_map(fields: &[FieldAttributes]) -> TokenStream2 {
+    let parse_fields = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        let name = &field.name;
+        let value = match (&field.with, field.flatten) {
+            (Some(with), true) => quote! { #with::from_map(hash_map)? },
+            (Some(with), false) => quote! {
+                #with::parse(hash_map.get(#name))
+                    .map_err(|error| error.context(concat!("Could not parse ", #name)))?
+            },
+            (None, _) => quote! {
+                <#ty as ::database::attribute_value_parser::AttributeValueParser>::parse(
+                    hash_map.get(#name),
+                )
+                .map_err(|error| error.context(concat!("Could not parse ", #name)))?
+            },
+        };
+        quote! { let #ident = #value; }
+    });
+    let idents = fields.iter().map(|field| &field.ident);
+    quote! {
+        pub fn from_map(
+            hash_map: &::std::collections::HashMap<
+                ::std::string::String,
+                ::aws_sdk_dynamodb::types::AttributeValue,
+            >,
+        ) -> ::std::result::Result<Self, ::anyhow::Error> {
+            #(#parse_fields)*
+            ::std::result::Result::Ok(Self { #(#idents),* })
+        }
+    }
+}
+
+fn to_map(item: &ItemAttributes, fields: &[FieldAttributes]) -> TokenStream2 {
+    let write_fields = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = &field.name;
+        match (&field.with, field.flatten) {
+            (Some(with), true) => quote! { hash_map.extend(#with::to_map(&self.#ident)?); },
+            (Some(with), false) => quote! {
+                if let Some(value) = #with::serialise(&self.#ident)? {
+                    hash_map.insert(#name.to_string(), value);
+                }
+            },
+            // Empty options are left out rather than written as nulls
+            (None, _) => quote! {
+                if let Some(value) =
+                    ::database::attribute_value_parser::AttributeValueSerialiser::serialise(
+                        &self.#ident,
+                    )
+                {
+                    hash_map.insert(#name.to_string(), value);
+                }
+            },
+        }
+    });
+    let write_ttl = item.ttl_days.as_ref().map(|ttl_days| {
+        quote! {
+            let ttl_timestamp =
+                (::chrono::Utc::now() + ::chrono::Duration::days(#ttl_days)).timestamp();
+            hash_map.insert(
+                "TimeToLive".to_string(),
+                ::aws_sdk_dynamodb::types::AttributeValue::N(ttl_timestamp.to_string()),
+            );
+        }
+    });
+    quote! {
+        pub fn to_map(
+            &self,
+        ) -> ::std::result::Result<
+            ::std::collections::HashMap<
+                ::std::string::String,
+                ::aws_sdk_dynamodb::types::AttributeValue,
+            >,
+            ::anyhow::Error,
+        > {
+            let mut hash_map = ::std::collections::HashMap::new();
+            #(#write_fields)*
+            #write_ttl
+            ::std::result::Result::Ok(hash_map)
+        }
+
+        pub fn save(
+            &self,
+        ) -> ::std::result::Result<::aws_sdk_dynamodb::types::TransactWriteItem, ::anyhow::Error>
+        {
+            let put_item = ::aws_sdk_dynamodb::types::Put::builder()
+                .table_name(Self::get_table_name()?)
+                .set_item(Some(self.to_map()?))
+                .build()?;
+            let transaction_item = ::aws_sdk_dynamodb::types::TransactWriteItem::builder()
+                .put(put_item)
+                .build();
+            ::std::result::Result::Ok(transaction_item)
+        }
+    }
+}
+
+// The partition key and optional sort key, checked to be one each
+fn key_fields<'a>(
+    span: &Ident,
+    description: &str,
+    roles: impl Iterator<Item = (&'a FieldAttributes, &'a KeyRole)>,
+) -> Result<Vec<&'a FieldAttributes>> {
+    let mut partition_keys = Vec::new();
+    let mut sort_keys = Vec::new();
+    for (field, role) in roles {
+        match role {
+            KeyRole::PartitionKey => partition_keys.push(field),
+            KeyRole::SortKey => sort_keys.push(field),
+        }
+    }
+    if partition_keys.len() != 1 || sort_keys.len() > 1 {
+        return Err(Error::new_spanned(
+            span,
+            format!(
+                "{} needs one partition_key and at most one sort_key",
+                description
+            ),
+        ));
+    }
+    partition_keys.extend(sort_keys);
+    Ok(partition_keys)
+}
+
+// Key parameters take strings as &str
+fn key_parameter_type(ty: &Type) -> TokenStream2 {
+    match ty {
+        Type::Path(path) if path.qself.is_none() && path.path.is_ident("String") => quote! { str },
+        _ => quote! { #ty },
+    }
+}
+
+fn keys(input: &DeriveInput, fields: &[FieldAttributes]) -> Result<TokenStream2> {
+    let primary_key_fields = key_fields(
+        &input.ident,
+        "The table",
+        fields
+            .iter()
+            .filter_map(|field| Some((field, field.key.as_ref()?))),
+    )?;
+    let parameters = primary_key_fields.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = key_parameter_type(&field.ty);
+        quote! { #ident: &#ty }
+    });
+    let parameters = quote! { #(#parameters),* };
+    let arguments = primary_key_fields.iter().map(|field| &field.ident);
+    let arguments = quote! { #(#arguments),* };
+    let self_arguments = primary_key_fields.iter().map(|field| {
+        let ident = &field.ident;
+        quote! { &self.#ident }
+    });
+    let insert_key = |field: &FieldAttributes, value: TokenStream2| {
+        let name = &field.name;
+        quote! {
+            if let Some(value) =
+                ::database::attribute_value_parser::AttributeValueSerialiser::serialise(#value)
+            {
+                key.insert(#name.to_string(), value);
+            }
+        }
+    };
+    let insert_primary_key = primary_key_fields.iter().map(|field| {
+        let ident = &field.ident;
+        insert_key(field, quote! { #ident })
+    });
+    // Keys for a GSI, as in its LastEvaluatedKey, also need the index's key attributes
+    let mut indexes: BTreeMap<&str, Vec<(&FieldAttributes, &KeyRole)>> = BTreeMap::new();
+    for field in fields {
+        for (index_name, role) in &field.indexes {
+            indexes.entry(index_name).or_default().push((field, role));
+        }
+    }
+    let mut index_arms = Vec::new();
+    for (index_name, roles) in indexes {
+        let description = format!("The index {}", index_name);
+        let index_fields = key_fields(&input.ident, &description, roles.into_iter())?;
+        let insert_index_key = index_fields.iter().map(|field| {
+            let ident = &field.ident;
+            insert_key(field, quote! { &self.#ident })
+        });
+        index_arms.push(quote! {
+            #index_name => {
+                let mut key = self.primary_key();
+                #(#insert_index_key)*
+                ::std::result::Result::Ok(key)
+            }
+        });
+    }
+    let ident = &input.ident;
+    let index_key = (!index_arms.is_empty()).then(|| {
+        quote! {
+            pub fn index_key(
+                &self,
+                index_name: &str,
+            ) -> ::std::result::Result<
+                ::std::collections::HashMap<
+                    ::std::string::String,
+                    ::aws_sdk_dynamodb::types::AttributeValue,
+                >,
+                ::anyhow::Error,
+            > {
+                match index_name {
+                    #(#index_arms)*
+                    _ => ::std::result::Result::Err(::anyhow::anyhow!(
+                        "{} does not have the index {}",
+                        stringify!(#ident),
+                        index_name
+                    )),
+                }
+            }
+        }
+    });
+    Ok(quote! {
+        pub fn key(
+            #parameters
+        ) -> ::std::collections::HashMap<
+            ::std::string::String,
+            ::aws_sdk_dynamodb::types::AttributeValue,
+        > {
+            let mut key = ::std::collections::HashMap::new();
+            #(#insert_primary_key)*
+            key
+        }
+
+        pub fn primary_key(
+            &self,
+        ) -> ::std::collections::HashMap<
+            ::std::string::String,
+            ::aws_sdk_dynamodb::types::AttributeValue,
+        > {
+            Self::key(#(#self_arguments),*)
+        }
+
+        #index_key
+
+        pub fn get(
+            #parameters
+        ) -> ::std::result::Result<::aws_sdk_dynamodb::types::TransactGetItem, ::anyhow::Error>
+        {
+            let item = ::aws_sdk_dynamodb::types::Get::builder()
+                .table_name(Self::get_table_name()?)
+                .set_key(Some(Self::key(#arguments)))
+                .build()?;
+            let transaction_item = ::aws_sdk_dynamodb::types::TransactGetItem::builder()
+                .get(item)
+                .build();
+            ::std::result::Result::Ok(transaction_item)
+        }
+
+        pub fn delete(
+            &self,
+        ) -> ::std::result::Result<::aws_sdk_dynamodb::types::TransactWriteItem, ::anyhow::Error>
+        {
+            let delete_item = ::aws_sdk_dynamodb::types::Delete::builder()
+                .table_name(Self::get_table_name()?)
+                .set_key(Some(self.primary_key()))
+                .build()?;
+            let transaction_item = ::aws_sdk_dynamodb::types::TransactWriteItem::builder()
+                .delete(delete_item)
+                .build();
+            ::std::result::Result::Ok(transaction_item)
+        }
+    })
+}
+
+fn ttl(
+    input: &DeriveInput,
+    item: &ItemAttributes,
+    fields: &[FieldAttributes],
+) -> Result<Option<TokenStream2>> {
+    let ttl_fields: Vec<&FieldAttributes> = fields.iter().filter(|field| field.ttl).collect();
+    if ttl_fields.len() + usize::from(item.ttl_days.is_some()) > 1 {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "Only one of ttl_days or a ttl field can be given",
+        ));
+    }
+    let Some(field) = ttl_fields.first() else {
+        return Ok(None);
+    };
+    let ident = &field.ident;
+    // DynamoDB can take days to delete items after their TTL, so expiry is checked on read too
+    Ok(Some(quote! {
+        pub fn is_expired(&self) -> bool {
+            self.#ident <= ::chrono::Utc::now().timestamp()
+        }
+    }))
+}
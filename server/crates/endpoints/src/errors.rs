@@ -1,19 +1,145 @@
+use anyhow::Error;
 use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum::Json;
+use database::paginated_models::InvalidKeyError;
+use h3_mapper::h3_client::UnknownCityError;
+use maps::errors::GoogleApiError;
 use serde::Serialize;
 use utoipa::ToSchema;
 
+#[derive(ToSchema, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    BadRequest,
+    Conflict,
+    UpstreamUnavailable,
+    Internal,
+}
+
 #[derive(ToSchema, Serialize)]
 pub struct ErrorResponse {
+    pub code: ErrorCode,
     pub error: String,
 }
 
-pub fn map_error_to_response(error: impl ToString) -> (StatusCode, Json<ErrorResponse>) {
-    println!("Error: {}", error.to_string());
-    (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(ErrorResponse {
-            error: error.to_string(),
-        }),
-    )
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound(String),
+    BadRequest(String),
+    Conflict(String),
+    UpstreamUnavailable(String),
+    Internal(Error),
+}
+
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::BadRequest(_) => ErrorCode::BadRequest,
+            ApiError::Conflict(_) => ErrorCode::Conflict,
+            ApiError::UpstreamUnavailable(_) => ErrorCode::UpstreamUnavailable,
+            ApiError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UpstreamUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        if error.downcast_ref::<UnknownCityError>().is_some() {
+            return ApiError::NotFound(error.to_string());
+        }
+        if error.downcast_ref::<InvalidKeyError>().is_some() {
+            return ApiError::BadRequest(error.to_string());
+        }
+        if error
+            .downcast_ref::<GoogleApiError>()
+            .is_some_and(GoogleApiError::is_retryable)
+        {
+            return ApiError::UpstreamUnavailable("The maps provider is unavailable".to_string());
+        }
+        ApiError::Internal(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status_code = self.status_code();
+        let code = self.code();
+        let error = match self {
+            ApiError::NotFound(message)
+            | ApiError::BadRequest(message)
+            | ApiError::Conflict(message)
+            | ApiError::UpstreamUnavailable(message) => message,
+            // Internal details are logged rather than returned to the caller
+            ApiError::Internal(error) => {
+                tracing::error!(error = ?error, "Internal server error");
+                "Internal server error".to_string()
+            }
+        };
+        (status_code, Json(ErrorResponse { code, error })).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_map_to_status_and_code() {
+        let unknown_city = || UnknownCityError {
+            city_code: "Atlantis".to_string(),
+        };
+        let cases: Vec<(Error, StatusCode, ErrorCode)> = vec![
+            (
+                unknown_city().into(),
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+            ),
+            // Context added on the way up doesn't hide the error
+            (
+                Error::from(unknown_city()).context("Failed to load map"),
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+            ),
+            (
+                InvalidKeyError.into(),
+                StatusCode::BAD_REQUEST,
+                ErrorCode::BadRequest,
+            ),
+            (
+                GoogleApiError::OverQueryLimit(None).into(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::UpstreamUnavailable,
+            ),
+            (
+                GoogleApiError::RequestDenied(None).into(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+            ),
+            (
+                anyhow::anyhow!("connection reset"),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+            ),
+        ];
+        for (error, status_code, code) in cases {
+            let message = format!("{:#}", error);
+            let api_error = ApiError::from(error);
+            assert_eq!(api_error.status_code(), status_code, "{}", message);
+            assert_eq!(api_error.code(), code, "{}", message);
+            assert_eq!(api_error.into_response().status(), status_code);
+        }
+    }
 }
@@ -1,17 +1,30 @@
-use super::models::{HouseRequestFilter, HouseResponse, HOUSE_TAG};
-use crate::errors::{map_error_to_response, ErrorResponse};
+use super::models::{
+    CommuteRequest, CommuteResponse, CommuteTarget, CommutesResponse, DurationSource,
+    HouseRequestFilter, HouseResponse, HOUSE_TAG,
+};
+use crate::errors::{ApiError, ErrorResponse};
+use crate::map::aggregation::ScoreAggregation;
 use crate::pagination::{PaginatedResponse, PaginationParams};
 use crate::state::AppState;
 use axum::extract::{Query, State};
-use axum::http::StatusCode;
 use axum::Json;
-use database::house_item::HouseItem;
+use database::dynamodb_client_trait::IDynamoDbClient;
+use database::house_item::{HouseFilter, HouseItem};
 use database::paginated_models::serialise_db_key;
+use database::requirement_item::RequirementItem;
+use database::spatial_distance_item::SpatialDistanceItem;
+use h3_mapper::h3_client::H3Client;
+use maps::models::Location;
+use maps::travel_time::TravelTimeProvider;
+use std::collections::HashMap;
 use std::sync::Arc;
 use utoipa_axum::router::OpenApiRouter;
+use uuid::Uuid;
 
 pub fn router() -> OpenApiRouter<Arc<AppState>> {
-    OpenApiRouter::new().routes(utoipa_axum::routes!(get_houses))
+    OpenApiRouter::new()
+        .routes(utoipa_axum::routes!(get_houses))
+        .routes(utoipa_axum::routes!(post_commutes))
     // .routes(utoipa_axum::routes!(get_house_by_id))
 }
 
@@ -31,7 +44,7 @@ pub fn router() -> OpenApiRouter<Arc<AppState>> {
 // pub async fn get_houses(
 //     State(state): State<Arc<AppState>>,
 //     Query(pagination): Query<PaginationParams>,
-// ) -> Result<Json<PaginatedResponse<HouseResponse>>, (StatusCode, Json<ErrorResponse>)> {
+// ) -> Result<Json<PaginatedResponse<HouseResponse>>, ApiError> {
 //     let page = pagination.page.unwrap_or(1);
 //     let page_size = pagination.page_size.unwrap_or(10);
 //     let num_houses = state.house_client.get_num_houses();
@@ -60,13 +73,23 @@ pub fn router() -> OpenApiRouter<Arc<AppState>> {
     path = "",
     tag = HOUSE_TAG,
     params(
-        ("limit" = Option<i32>, Query, description = "Maximum number of items to return"),
+        ("limit" = Option<i32>, Query, description = "Maximum number of items to return, from 1 to 100. Defaults to 20"),
         ("last_evaluated_key" = Option<String>, Query, description = "Last evaluated key from previous response"),
         ("city_code" = Option<String>, Query, description = "City code to filter houses"),
-        ("h3_index" = Option<String>, Query, description = "H3 geospatial index to filter houses")
+        ("h3_index" = Option<String>, Query, description = "H3 geospatial index to filter houses"),
+        ("requirement_ids" = Option<String>, Query, description = "Comma separated requirement IDs to score houses by"),
+        ("min_score" = Option<i32>, Query, description = "Minimum combined requirement score of a house"),
+        ("min_price" = Option<i32>, Query, description = "Minimum price of a house"),
+        ("max_price" = Option<i32>, Query, description = "Maximum price of a house"),
+        ("min_bedrooms" = Option<i32>, Query, description = "Minimum number of bedrooms"),
+        ("min_bathrooms" = Option<i32>, Query, description = "Minimum number of bathrooms"),
+        ("min_carspaces" = Option<i32>, Query, description = "Minimum number of car spaces"),
+        ("property_types" = Option<String>, Query, description = "Comma separated property types to include, at most 100")
     ),
     responses(
         (status = OK, body = PaginatedResponse<HouseResponse>),
+        (status = BAD_REQUEST, body = ErrorResponse, description = "Invalid filter, limit or pagination key"),
+        (status = NOT_FOUND, body = ErrorResponse, description = "City or requirement not found"),
         (status = INTERNAL_SERVER_ERROR, body = ErrorResponse, description = "Internal server error")
     )
 )]
@@ -74,44 +97,49 @@ pub async fn get_houses(
     State(state): State<Arc<AppState>>,
     Query(pagination): Query<PaginationParams>,
     Query(filter): Query<HouseRequestFilter>,
-) -> Result<Json<PaginatedResponse<HouseResponse>>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<PaginatedResponse<HouseResponse>>, ApiError> {
     let db = &*state.db_client;
     if filter.h3_index.is_some() && filter.city_code.is_some() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "h3_index and city_code can't both be provided".to_string(),
-            }),
+        return Err(ApiError::BadRequest(
+            "h3_index and city_code can't both be provided".to_string(),
         ));
     }
+    if let Some(city_code) = &filter.city_code {
+        state.h3_client.get_city(city_code)?;
+    }
+    let requirement_ids = filter.decode_requirement_ids()?;
+    let house_filter = filter.decode_house_filter()?;
+    if !requirement_ids.is_empty() {
+        return get_houses_by_requirements(
+            &state,
+            &pagination,
+            &filter,
+            &house_filter,
+            &requirement_ids,
+        )
+        .await;
+    }
     let db_response = if let Some(h3_index) = &filter.h3_index {
         HouseItem::list_by_h3_index_from_db(
             h3_index.as_str(),
-            pagination.limit,
-            pagination
-                .decode_last_evaluated_key()
-                .map_err(map_error_to_response)?,
+            &house_filter,
+            Some(pagination.page_size()?),
+            pagination.decode_last_evaluated_key()?,
             db,
         )
-        .await
-        .map_err(map_error_to_response)?
+        .await?
     } else if let Some(city_code) = &filter.city_code {
         HouseItem::list_by_city_from_db(
             city_code.as_str(),
-            pagination.limit,
-            pagination
-                .decode_last_evaluated_key()
-                .map_err(map_error_to_response)?,
+            &house_filter,
+            Some(pagination.page_size()?),
+            pagination.decode_last_evaluated_key()?,
             db,
         )
-        .await
-        .map_err(map_error_to_response)?
+        .await?
     } else {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Either h3_index or city_code must be provided".to_string(),
-            }),
+        return Err(ApiError::BadRequest(
+            "Either h3_index or city_code must be provided".to_string(),
         ));
     };
     let response = PaginatedResponse {
@@ -121,13 +149,216 @@ pub async fn get_houses(
             .map(HouseResponse::from)
             .collect(),
         last_evaluated_key: match db_response.last_evaluated_key {
-            Some(key) => Some(serialise_db_key(key).map_err(map_error_to_response)?),
+            Some(key) => Some(serialise_db_key(key)?),
             None => None,
         },
     };
     Ok(Json(response))
 }
 
+async fn get_houses_by_requirements(
+    state: &AppState,
+    pagination: &PaginationParams,
+    filter: &HouseRequestFilter,
+    house_filter: &HouseFilter,
+    requirement_ids: &[Uuid],
+) -> Result<Json<PaginatedResponse<HouseResponse>>, ApiError> {
+    let db = &*state.db_client;
+    if filter.h3_index.is_none() && filter.city_code.is_none() {
+        return Err(ApiError::BadRequest(
+            "Either h3_index or city_code must be provided".to_string(),
+        ));
+    }
+
+    // Load the requirements from the database
+    let items = RequirementItem::batch_from_db(requirement_ids, db).await?;
+    let mut requirements = vec![];
+    for (requirement_id, requirement) in requirement_ids.iter().zip(items) {
+        match requirement {
+            Some(requirement) => requirements.push(requirement),
+            None => {
+                return Err(ApiError::NotFound(format!(
+                    "Requirement {} not found",
+                    requirement_id
+                )))
+            }
+        }
+    }
+
+    if let Some(city_code) = &filter.city_code {
+        requirements.retain(|requirement| &requirement.city_code == city_code);
+    }
+    let tile_scores: HashMap<String, i32> = ScoreAggregation::Min
+        .score_tiles(&requirements)
+        .into_iter()
+        .map(|tile| (tile.h3_index, tile.mean_score))
+        .collect();
+
+    // Visit the best tiles first so that houses come back sorted by score
+    let min_score = filter.min_score.unwrap_or(0);
+    let mut scored_tiles = tile_scores
+        .iter()
+        .filter(|(h3_index, score)| {
+            **score >= min_score
+                && filter
+                    .h3_index
+                    .as_ref()
+                    .is_none_or(|filter_index| filter_index == *h3_index)
+        })
+        .collect::<Vec<_>>();
+    scored_tiles.sort_by(|(index_a, score_a), (index_b, score_b)| {
+        score_b.cmp(score_a).then_with(|| index_a.cmp(index_b))
+    });
+    let h3_indices = scored_tiles
+        .into_iter()
+        .map(|(h3_index, _)| h3_index.clone())
+        .collect::<Vec<_>>();
+
+    let db_response = HouseItem::list_by_h3_indices_from_db(
+        &h3_indices,
+        house_filter,
+        Some(pagination.page_size()?),
+        pagination.decode_last_evaluated_key()?,
+        db,
+    )
+    .await?;
+    let response = PaginatedResponse {
+        items: db_response
+            .items
+            .into_iter()
+            .map(|house| {
+                let score = tile_scores.get(&house.h3_index).copied();
+                HouseResponse {
+                    score,
+                    ..HouseResponse::from(house)
+                }
+            })
+            .collect(),
+        last_evaluated_key: match db_response.last_evaluated_key {
+            Some(key) => Some(serialise_db_key(key)?),
+            None => None,
+        },
+    };
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/commutes",
+    tag = HOUSE_TAG,
+    request_body = CommuteRequest,
+    responses(
+        (status = OK, body = CommutesResponse),
+        (status = BAD_REQUEST, body = ErrorResponse, description = "Invalid request"),
+        (status = NOT_FOUND, body = ErrorResponse, description = "City or house not found"),
+        (status = SERVICE_UNAVAILABLE, body = ErrorResponse, description = "Exact routes are unavailable"),
+        (status = INTERNAL_SERVER_ERROR, body = ErrorResponse, description = "Internal server error")
+    )
+)]
+pub async fn post_commutes(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CommuteRequest>,
+) -> Result<Json<CommutesResponse>, ApiError> {
+    let db = &*state.db_client;
+    let h3 = &state.h3_client;
+    h3.get_city(&request.city_code)?;
+    if request.exact_fallback && state.maps_client.is_none() {
+        return Err(ApiError::UpstreamUnavailable(
+            "Exact routes aren't available without a maps provider".to_string(),
+        ));
+    }
+    let (h3_index, origin) = match (&request.address, &request.h3_index) {
+        (Some(address), h3_index) => {
+            let house = find_house(&request.city_code, address, h3_index.as_deref(), db).await?;
+            let origin = Location {
+                lat: house.lat,
+                lng: house.lng,
+            };
+            (house.h3_index, origin)
+        }
+        // Without a house, exact routes start from the middle of the tile
+        (None, Some(h3_index)) => {
+            let (lat, lng) = H3Client::get_location_for_index(h3_index)
+                .map_err(|_| ApiError::BadRequest(format!("Invalid h3_index {}", h3_index)))?;
+            (h3_index.clone(), Location { lat, lng })
+        }
+        (None, None) => {
+            return Err(ApiError::BadRequest(
+                "Either address or h3_index must be provided".to_string(),
+            ))
+        }
+    };
+    // Every target is checked before any are routed
+    let target_indices = request
+        .targets
+        .iter()
+        .map(|target| target_index(h3, &request.city_code, target))
+        .collect::<Result<Vec<_>, _>>()?;
+    let time_window = request.time_window.map(Into::into);
+    let mut commutes = vec![];
+    for (target, target_index) in request.targets.into_iter().zip(target_indices) {
+        let stored_duration = if target_index == h3_index {
+            Some(0)
+        } else {
+            SpatialDistanceItem::from_db(&h3_index, &target_index, db)
+                .await?
+                .map(|item| target.travel_mode.duration(&item, time_window))
+                .filter(|duration| *duration >= 0)
+        };
+        let (duration, source) = match (stored_duration, &state.maps_client) {
+            (Some(duration), _) => (Some(duration), Some(DurationSource::Matrix)),
+            // Exact routes depart now, whatever the time window
+            (None, Some(maps_client)) if request.exact_fallback => {
+                let destination = Location {
+                    lat: target.lat,
+                    lng: target.lng,
+                };
+                let duration = maps_client
+                    .travel_time(origin, destination, &target.travel_mode.into(), None)
+                    .await?;
+                (duration, duration.map(|_| DurationSource::ExactRoute))
+            }
+            (None, _) => (None, None),
+        };
+        commutes.push(CommuteResponse {
+            address: target.address,
+            travel_mode: target.travel_mode,
+            duration,
+            source,
+        });
+    }
+    Ok(Json(CommutesResponse { h3_index, commutes }))
+}
+
+fn target_index(
+    h3: &H3Client,
+    city_code: &str,
+    target: &CommuteTarget,
+) -> Result<String, ApiError> {
+    let invalid = || ApiError::BadRequest(format!("Invalid location for {}", target.address));
+    if !(-90.0..=90.0).contains(&target.lat) || !(-180.0..=180.0).contains(&target.lng) {
+        return Err(invalid());
+    }
+    h3.get_index_for_location(city_code, target.lat, target.lng)
+        .map_err(|_| invalid())
+}
+
+// Houses are keyed by tile and address, so without the tile the city is searched
+async fn find_house(
+    city_code: &str,
+    address: &str,
+    h3_index: Option<&str>,
+    db: &dyn IDynamoDbClient,
+) -> Result<HouseItem, ApiError> {
+    let house = match h3_index {
+        Some(h3_index) => HouseItem::from_db(h3_index, address, db).await?,
+        None => HouseItem::find_by_address_in_city(city_code, address, db).await?,
+    };
+    house
+        .filter(|house| house.city_code == city_code)
+        .ok_or(ApiError::NotFound(format!("House {} not found", address)))
+}
+
 // #[utoipa::path(
 //     get,
 //     path = "/{id}",
@@ -159,3 +390,89 @@ pub async fn get_houses(
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_state;
+
+    fn request(request: serde_json::Value) -> Json<CommuteRequest> {
+        Json(serde_json::from_value(request).unwrap())
+    }
+
+    fn target(lat: f64, lng: f64) -> serde_json::Value {
+        serde_json::json!({
+            "address": "Work",
+            "lat": lat,
+            "lng": lng,
+            "travel_mode": "Driving",
+        })
+    }
+
+    #[tokio::test]
+    async fn test_commute_from_house() {
+        let request = request(serde_json::json!({
+            "city_code": "Adelaide",
+            "address": "17 Foster Street, Norwood",
+            "h3_index": "87b916804ffffff",
+            "targets": [target(-34.91619329004659, 138.63740742198067)],
+        }));
+        let Ok(Json(response)) = post_commutes(State(test_state().await), request).await else {
+            panic!("Commute failed");
+        };
+        assert_eq!(response.h3_index, "87b916804ffffff");
+        assert_eq!(response.commutes[0].duration, Some(0));
+        assert_eq!(response.commutes[0].source, Some(DurationSource::Matrix));
+    }
+
+    #[tokio::test]
+    async fn test_house_not_found() {
+        // The house is in a different tile
+        let request = request(serde_json::json!({
+            "city_code": "Adelaide",
+            "address": "17 Foster Street, Norwood",
+            "h3_index": "87b916800ffffff",
+            "targets": [],
+        }));
+        let result = post_commutes(State(test_state().await), request).await;
+        assert!(matches!(result, Err(ApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_commute_from_address() {
+        let request = request(serde_json::json!({
+            "city_code": "Adelaide",
+            "address": "17 Foster Street, Norwood",
+            "targets": [target(-34.91619329004659, 138.63740742198067)],
+        }));
+        let Ok(Json(response)) = post_commutes(State(test_state().await), request).await else {
+            panic!("Commute failed");
+        };
+        assert_eq!(response.h3_index, "87b916804ffffff");
+        assert_eq!(response.commutes[0].duration, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_address_not_in_city() {
+        let request = request(serde_json::json!({
+            "city_code": "Adelaide",
+            "address": "1 Nowhere Street, Norwood",
+            "targets": [],
+        }));
+        let result = post_commutes(State(test_state().await), request).await;
+        assert!(matches!(result, Err(ApiError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_target_location() {
+        for (lat, lng) in [(-134.9, 138.6), (-34.9, 238.6)] {
+            let request = request(serde_json::json!({
+                "city_code": "Adelaide",
+                "h3_index": "87b916804ffffff",
+                "targets": [target(-34.92, 138.6), target(lat, lng)],
+            }));
+            let result = post_commutes(State(test_state().await), request).await;
+            assert!(matches!(result, Err(ApiError::BadRequest(_))));
+        }
+    }
+}
@@ -1,5 +1,9 @@
-use database::house_item::HouseItem;
-use serde::Deserialize;
+use crate::errors::ApiError;
+use crate::map::models::{TimeWindow, TravelMode};
+use database::house_item::{HouseFilter, HouseItem, MAX_PROPERTY_TYPES};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
 
 pub const HOUSE_TAG: &str = "house";
 
@@ -16,6 +20,7 @@ pub struct HouseResponse {
     pub num_bedrooms: i32,
     pub num_carspaces: i32,
     pub property_type: String,
+    pub score: Option<i32>,
 }
 
 impl From<HouseItem> for HouseResponse {
@@ -32,6 +37,7 @@ impl From<HouseItem> for HouseResponse {
             num_bedrooms: house.num_bedrooms,
             num_carspaces: house.num_carspaces,
             property_type: house.property_type,
+            score: None,
         }
     }
 }
@@ -40,4 +46,152 @@ impl From<HouseItem> for HouseResponse {
 pub struct HouseRequestFilter {
     pub city_code: Option<String>,
     pub h3_index: Option<String>,
+    pub requirement_ids: Option<String>,
+    pub min_score: Option<i32>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+    pub min_bedrooms: Option<i32>,
+    pub min_bathrooms: Option<i32>,
+    pub min_carspaces: Option<i32>,
+    pub property_types: Option<String>,
+}
+
+impl HouseRequestFilter {
+    pub fn decode_requirement_ids(&self) -> Result<Vec<Uuid>, ApiError> {
+        let requirement_ids = match &self.requirement_ids {
+            Some(requirement_ids) => requirement_ids,
+            None => return Ok(vec![]),
+        };
+        requirement_ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                Uuid::parse_str(id)
+                    .map_err(|_| ApiError::BadRequest(format!("Invalid requirement ID {}", id)))
+            })
+            .collect()
+    }
+
+    pub fn decode_house_filter(&self) -> Result<HouseFilter, ApiError> {
+        if let (Some(min_price), Some(max_price)) = (self.min_price, self.max_price) {
+            if min_price > max_price {
+                return Err(ApiError::BadRequest(
+                    "min_price can't be greater than max_price".to_string(),
+                ));
+            }
+        }
+        let property_types: Vec<String> = match &self.property_types {
+            Some(property_types) => property_types
+                .split(',')
+                .map(str::trim)
+                .filter(|property_type| !property_type.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => vec![],
+        };
+        if property_types.len() > MAX_PROPERTY_TYPES {
+            return Err(ApiError::BadRequest(format!(
+                "At most {} property_types can be given",
+                MAX_PROPERTY_TYPES
+            )));
+        }
+        Ok(HouseFilter {
+            min_price: self.min_price,
+            max_price: self.max_price,
+            min_bedrooms: self.min_bedrooms,
+            min_bathrooms: self.min_bathrooms,
+            min_carspaces: self.min_carspaces,
+            property_types,
+        })
+    }
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct CommuteTarget {
+    pub address: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub travel_mode: TravelMode,
+}
+
+#[derive(ToSchema, Deserialize)]
+pub struct CommuteRequest {
+    pub city_code: String,
+    // The house is found by its address, in its tile if h3_index is given and otherwise in the
+    // city. Without an address only the tile is used.
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub h3_index: Option<String>,
+    pub targets: Vec<CommuteTarget>,
+    // Driving and public transport durations for this time of the week, if they are known
+    #[serde(default)]
+    pub time_window: Option<TimeWindow>,
+    // Route with the maps provider when the stored matrix has no duration
+    #[serde(default)]
+    pub exact_fallback: bool,
+}
+
+#[derive(ToSchema, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationSource {
+    // Between the centres of the house's and target's tiles
+    Matrix,
+    // Between the exact house and target locations
+    ExactRoute,
+}
+
+#[derive(ToSchema, Serialize)]
+pub struct CommuteResponse {
+    pub address: String,
+    pub travel_mode: TravelMode,
+    // Seconds, or null if it isn't known or there is no route
+    pub duration: Option<i32>,
+    pub source: Option<DurationSource>,
+}
+
+#[derive(ToSchema, Serialize)]
+pub struct CommutesResponse {
+    pub h3_index: String,
+    pub commutes: Vec<CommuteResponse>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(property_types: &str) -> HouseRequestFilter {
+        HouseRequestFilter {
+            city_code: None,
+            h3_index: None,
+            requirement_ids: None,
+            min_score: None,
+            min_price: None,
+            max_price: None,
+            min_bedrooms: None,
+            min_bathrooms: None,
+            min_carspaces: None,
+            property_types: Some(property_types.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_decode_property_types() {
+        let house_filter = filter(" House, ,Unit ").decode_house_filter().unwrap();
+        assert_eq!(house_filter.property_types, vec!["House", "Unit"]);
+    }
+
+    #[test]
+    fn test_too_many_property_types() {
+        let property_types = vec!["House"; MAX_PROPERTY_TYPES];
+        assert!(filter(&property_types.join(","))
+            .decode_house_filter()
+            .is_ok());
+        let property_types = vec!["House"; MAX_PROPERTY_TYPES + 1];
+        assert!(matches!(
+            filter(&property_types.join(",")).decode_house_filter(),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
 }
@@ -0,0 +1,279 @@
+use super::models::{MapTileResponse, RequirementScoreResponse};
+use crate::errors::ApiError;
+use database::requirement_item::RequirementItem;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregationMode {
+    Min,
+    Mean,
+    WeightedMean,
+    GeometricMean,
+    MustHave,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ScoreAggregation {
+    // A tile only scores as well as its worst requirement
+    #[default]
+    Min,
+    Mean,
+    // Requirements without a weight count once
+    WeightedMean {
+        weights: HashMap<Uuid, f64>,
+    },
+    // Any requirement scoring zero makes the whole tile zero
+    GeometricMean,
+    // A tile scores zero if any must-have requirement scores zero, otherwise it is the
+    // mean of the nice-to-have requirements, or of the must-haves if there are none
+    MustHave {
+        must_have: Vec<Uuid>,
+    },
+}
+
+impl ScoreAggregation {
+    pub fn mode(&self) -> AggregationMode {
+        match self {
+            ScoreAggregation::Min => AggregationMode::Min,
+            ScoreAggregation::Mean => AggregationMode::Mean,
+            ScoreAggregation::WeightedMean { .. } => AggregationMode::WeightedMean,
+            ScoreAggregation::GeometricMean => AggregationMode::GeometricMean,
+            ScoreAggregation::MustHave { .. } => AggregationMode::MustHave,
+        }
+    }
+
+    pub fn validate(&self, requirement_ids: &[Uuid]) -> Result<(), ApiError> {
+        let unknown_ids: Vec<&Uuid> = match self {
+            ScoreAggregation::WeightedMean { weights } => {
+                if weights
+                    .values()
+                    .any(|weight| !weight.is_finite() || *weight < 0.0)
+                {
+                    return Err(ApiError::BadRequest(
+                        "Weights must be non-negative numbers".to_string(),
+                    ));
+                }
+                weights.keys().collect()
+            }
+            ScoreAggregation::MustHave { must_have } => must_have.iter().collect(),
+            _ => vec![],
+        };
+        match unknown_ids.iter().find(|id| !requirement_ids.contains(id)) {
+            Some(id) => Err(ApiError::BadRequest(format!(
+                "Requirement {} is not in requirement_ids",
+                id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn aggregate(&self, scores: &[RequirementScoreResponse]) -> i32 {
+        if scores.is_empty() {
+            return 0;
+        }
+        match self {
+            ScoreAggregation::Min => scores.iter().map(|s| s.score).min().unwrap_or(0),
+            ScoreAggregation::Mean => mean(scores.iter().map(|s| (s.score, 1.0))),
+            ScoreAggregation::WeightedMean { weights } => mean(scores.iter().map(|s| {
+                let weight = weights.get(&s.requirement_id).copied().unwrap_or(1.0);
+                (s.score, weight)
+            })),
+            ScoreAggregation::GeometricMean => {
+                if scores.iter().any(|s| s.score <= 0) {
+                    return 0;
+                }
+                let log_sum: f64 = scores.iter().map(|s| (s.score as f64).ln()).sum();
+                (log_sum / scores.len() as f64).exp().round() as i32
+            }
+            ScoreAggregation::MustHave { must_have } => {
+                let (must_haves, nice_to_haves): (Vec<_>, Vec<_>) = scores
+                    .iter()
+                    .partition(|s| must_have.contains(&s.requirement_id));
+                if must_haves.iter().any(|s| s.score <= 0) {
+                    return 0;
+                }
+                if nice_to_haves.is_empty() {
+                    return mean(must_haves.iter().map(|s| (s.score, 1.0)));
+                }
+                mean(nice_to_haves.iter().map(|s| (s.score, 1.0)))
+            }
+        }
+    }
+
+    pub fn score_tiles(&self, requirements: &[RequirementItem]) -> Vec<MapTileResponse> {
+        let mut tile_scores: BTreeMap<String, Vec<RequirementScoreResponse>> = BTreeMap::new();
+        for requirement in requirements {
+            for tile in &requirement.map_tiles {
+                tile_scores.entry(tile.h3_index.clone()).or_default().push(
+                    RequirementScoreResponse {
+                        requirement_id: requirement.requirement_id,
+                        score: tile.score,
+                    },
+                );
+            }
+        }
+        tile_scores
+            .into_iter()
+            .map(|(h3_index, scores)| MapTileResponse {
+                h3_index,
+                mean_score: self.aggregate(&scores),
+                requirement_scores: scores,
+            })
+            .collect()
+    }
+}
+
+fn mean(weighted_scores: impl Iterator<Item = (i32, f64)>) -> i32 {
+    let mut total = 0.0;
+    let mut total_weight = 0.0;
+    for (score, weight) in weighted_scores {
+        total += score as f64 * weight;
+        total_weight += weight;
+    }
+    if total_weight == 0.0 {
+        return 0;
+    }
+    (total / total_weight).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::requirement_item::MapTile;
+
+    const TILE_A: &str = "87b91682effffff";
+    const TILE_B: &str = "87b916972ffffff";
+
+    fn requirement(id: u128, scores: [i32; 2]) -> RequirementItem {
+        RequirementItem {
+            city_code: "Adelaide".to_string(),
+            requirement_id: Uuid::from_u128(id),
+            map_tiles: vec![
+                MapTile {
+                    h3_index: TILE_A.to_string(),
+                    score: scores[0],
+                },
+                MapTile {
+                    h3_index: TILE_B.to_string(),
+                    score: scores[1],
+                },
+            ],
+            request: None,
+            content_hash: None,
+        }
+    }
+
+    fn fixtures() -> Vec<RequirementItem> {
+        vec![
+            requirement(1, [90, 0]),
+            requirement(2, [40, 60]),
+            requirement(3, [80, 100]),
+        ]
+    }
+
+    fn tile_scores(aggregation: ScoreAggregation) -> Vec<(String, i32)> {
+        aggregation
+            .score_tiles(&fixtures())
+            .into_iter()
+            .map(|tile| (tile.h3_index, tile.mean_score))
+            .collect()
+    }
+
+    #[test]
+    fn test_min() {
+        let scores = tile_scores(ScoreAggregation::Min);
+        assert_eq!(
+            scores,
+            vec![(TILE_A.to_string(), 40), (TILE_B.to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn test_mean() {
+        let scores = tile_scores(ScoreAggregation::Mean);
+        assert_eq!(
+            scores,
+            vec![(TILE_A.to_string(), 70), (TILE_B.to_string(), 53)]
+        );
+    }
+
+    #[test]
+    fn test_weighted_mean() {
+        let weights = HashMap::from([(Uuid::from_u128(1), 3.0), (Uuid::from_u128(2), 0.0)]);
+        let scores = tile_scores(ScoreAggregation::WeightedMean { weights });
+        assert_eq!(
+            scores,
+            vec![(TILE_A.to_string(), 88), (TILE_B.to_string(), 25)]
+        );
+    }
+
+    #[test]
+    fn test_weighted_mean_without_weight() {
+        let weights = HashMap::from([(Uuid::from_u128(1), 0.0), (Uuid::from_u128(2), 0.0)]);
+        let aggregation = ScoreAggregation::WeightedMean { weights };
+        let requirements = &fixtures()[..2];
+        let tiles = aggregation.score_tiles(requirements);
+        assert!(tiles.iter().all(|tile| tile.mean_score == 0));
+    }
+
+    #[test]
+    fn test_geometric_mean() {
+        let scores = tile_scores(ScoreAggregation::GeometricMean);
+        assert_eq!(
+            scores,
+            vec![(TILE_A.to_string(), 66), (TILE_B.to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn test_must_have() {
+        let must_have = vec![Uuid::from_u128(2)];
+        let scores = tile_scores(ScoreAggregation::MustHave { must_have });
+        assert_eq!(
+            scores,
+            vec![(TILE_A.to_string(), 85), (TILE_B.to_string(), 50)]
+        );
+    }
+
+    #[test]
+    fn test_must_have_failing() {
+        let must_have = vec![Uuid::from_u128(1)];
+        let scores = tile_scores(ScoreAggregation::MustHave { must_have });
+        assert_eq!(
+            scores,
+            vec![(TILE_A.to_string(), 60), (TILE_B.to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn test_must_have_only() {
+        let must_have = vec![1, 2, 3].into_iter().map(Uuid::from_u128).collect();
+        let scores = tile_scores(ScoreAggregation::MustHave { must_have });
+        assert_eq!(
+            scores,
+            vec![(TILE_A.to_string(), 70), (TILE_B.to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn test_validate_unknown_requirement() {
+        let aggregation = ScoreAggregation::MustHave {
+            must_have: vec![Uuid::from_u128(4)],
+        };
+        let requirement_ids = vec![Uuid::from_u128(1)];
+        assert!(aggregation.validate(&requirement_ids).is_err());
+    }
+
+    #[test]
+    fn test_validate_negative_weight() {
+        let weights = HashMap::from([(Uuid::from_u128(1), -1.0)]);
+        let aggregation = ScoreAggregation::WeightedMean { weights };
+        let requirement_ids = vec![Uuid::from_u128(1)];
+        assert!(aggregation.validate(&requirement_ids).is_err());
+    }
+}
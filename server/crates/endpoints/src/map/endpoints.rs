@@ -1,12 +1,10 @@
 use super::models::{
-    MapRequest, MapResponse, MapTileResponse, RequirementRequest, RequirementResponse,
-    RequirementScoreResponse, TravelMode, MAP_TAG,
+    MapRequest, MapResponse, MapTileResponse, RequirementRequest, RequirementResponse, MAP_TAG,
 };
-use crate::errors::{map_error_to_response, ErrorResponse};
+use crate::errors::{ApiError, ErrorResponse};
 use crate::state::AppState;
 use anyhow::Error;
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::{Path, State};
 use axum::Json;
 use axum_macros::debug_handler;
 use database::requirement_item::{MapTile, RequirementItem};
@@ -15,10 +13,12 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
+use uuid::Uuid;
 
 pub fn router() -> OpenApiRouter<Arc<AppState>> {
     OpenApiRouter::new()
         .routes(routes!(post_requirement))
+        .routes(routes!(get_requirement))
         .routes(routes!(get_map))
 }
 
@@ -29,6 +29,8 @@ pub fn router() -> OpenApiRouter<Arc<AppState>> {
     request_body = RequirementRequest,
     responses(
         (status = OK, body = RequirementResponse),
+        (status = BAD_REQUEST, body = ErrorResponse, description = "Invalid requirement"),
+        (status = NOT_FOUND, body = ErrorResponse, description = "City not found"),
         (status = INTERNAL_SERVER_ERROR, body = ErrorResponse, description = "Internal server error")
     )
 )]
@@ -36,14 +38,20 @@ pub fn router() -> OpenApiRouter<Arc<AppState>> {
 pub async fn post_requirement(
     State(state): State<Arc<AppState>>,
     Json(request): Json<RequirementRequest>,
-) -> Result<Json<RequirementResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<RequirementResponse>, ApiError> {
     let db = &*state.db_client;
     let h3 = &state.h3_client;
-    let requirement = RequirementItem::from_db(&request.requirement_id, db)
-        .await
-        .map_err(map_error_to_response)?;
-    if let Some(_requirement) = requirement {
-        // If the input hasn't changed, return
+    request.scoring_curve.validate(request.tolerated_duration)?;
+    request.location_match.validate(request.locations.len())?;
+    let content_hash = request.content_hash()?;
+    let requirement = RequirementItem::from_db(&request.requirement_id, db).await?;
+    if let Some(requirement) = requirement {
+        // If the input hasn't changed, the saved tiles are still correct. They are saved again
+        // anyway, to push back the TimeToLive of requirements that are still in use.
+        if requirement.content_hash.as_ref() == Some(&content_hash) {
+            db.write_single(requirement.save()?).await?;
+            return Ok(Json(RequirementResponse { recomputed: false }));
+        }
     }
     let unique_sources = request
         .locations
@@ -52,60 +60,85 @@ pub async fn post_requirement(
         .collect::<HashSet<String>>();
     let mut tiles_by_index: HashMap<(String, String), SpatialDistanceItem> = HashMap::new();
     for source in &unique_sources {
-        let items = SpatialDistanceItem::list_by_source_from_db(&source, db)
-            .await
-            .map_err(map_error_to_response)?;
+        let items = SpatialDistanceItem::list_by_source_from_db(source, db).await?;
         for item in items {
             tiles_by_index.insert((source.clone(), item.destination_index.clone()), item);
         }
     }
+    let time_window = request.time_window.map(Into::into);
     let mut map_tiles = vec![];
-    let city_indices = h3
-        .get_indices_for_city(&request.city_code)
-        .map_err(map_error_to_response)?;
+    let city_indices = h3.get_indices_for_city(&request.city_code)?;
     for destination_index in city_indices {
-        let mut duration = i32::MAX;
-        for location in &request.locations {
-            let source_index = location.h3_index.clone();
-            if source_index == destination_index {
-                duration = 0;
-                break;
-            }
-            let tile = tiles_by_index.get(&(source_index.clone(), destination_index.clone()));
-            // if the distance is not found, it exceeds the tolerated duration
-            let tile = match tile {
-                Some(tile) => tile,
-                None => continue,
-            };
-            duration = match request.travel_mode {
-                TravelMode::Driving => duration.min(tile.duration_drive),
-                TravelMode::Bicycling => duration.min(tile.duration_cycle),
-                TravelMode::PublicTransport => duration.min(tile.duration_transit),
-                TravelMode::Walking => duration.min(tile.duration_walk),
-            }
-        }
+        let durations = request
+            .locations
+            .iter()
+            .map(|location| {
+                // The location is inside the tile
+                if location.h3_index == destination_index {
+                    return Some(0);
+                }
+                // if the distance is not found, it exceeds the tolerated duration
+                let tile =
+                    tiles_by_index.get(&(location.h3_index.clone(), destination_index.clone()))?;
+                let duration = location
+                    .travel_mode
+                    .unwrap_or(request.travel_mode)
+                    .duration(tile, time_window);
+                (duration >= 0).then_some(duration)
+            })
+            .collect::<Vec<Option<i32>>>();
+        let duration = request.location_match.duration(&durations);
         let score = match duration {
-            i32::MAX => 0,
-            _ => 100 - 100.min((100 * duration) / request.tolerated_duration),
-            // _ => 100 - 100.min((50 * duration) / request.tolerated_duration),
+            None => 0,
+            Some(duration) => request.scoring_curve.score(
+                duration,
+                request.tolerated_duration,
+                request.soft_scoring,
+            ),
         };
         map_tiles.push(MapTile {
             h3_index: destination_index,
-            score: score,
+            score,
         });
     }
+    let request_json = serde_json::to_string(&request).map_err(|e| ApiError::Internal(e.into()))?;
     let requirement = RequirementItem {
         city_code: request.city_code,
         requirement_id: request.requirement_id,
-        map_tiles: map_tiles,
+        map_tiles,
+        request: Some(request_json),
+        content_hash: Some(content_hash),
     };
-    let transaction = requirement.save().map_err(map_error_to_response)?;
-    state
-        .db_client
-        .write_single(transaction)
-        .await
-        .map_err(map_error_to_response)?;
-    Ok(Json(RequirementResponse {}))
+    let transaction = requirement.save()?;
+    state.db_client.write_single(transaction).await?;
+    Ok(Json(RequirementResponse { recomputed: true }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/requirements/{requirement_id}",
+    tag = MAP_TAG,
+    params(
+        ("requirement_id" = Uuid, Path, description = "Requirement ID")
+    ),
+    responses(
+        (status = OK, body = RequirementRequest),
+        (status = NOT_FOUND, body = ErrorResponse, description = "Requirement not found"),
+        (status = INTERNAL_SERVER_ERROR, body = ErrorResponse, description = "Internal server error")
+    )
+)]
+pub async fn get_requirement(
+    State(state): State<Arc<AppState>>,
+    Path(requirement_id): Path<Uuid>,
+) -> Result<Json<RequirementRequest>, ApiError> {
+    let not_found = || ApiError::NotFound(format!("Requirement {} not found", requirement_id));
+    let requirement = RequirementItem::from_db(&requirement_id, &*state.db_client)
+        .await?
+        .ok_or_else(not_found)?;
+    // Requirements saved before requests were persisted can't be rebuilt
+    let request = requirement.request.ok_or_else(not_found)?;
+    let request = serde_json::from_str(&request).map_err(|e| ApiError::Internal(e.into()))?;
+    Ok(Json(request))
 }
 
 #[utoipa::path(
@@ -115,19 +148,18 @@ pub async fn post_requirement(
     request_body = MapRequest,
     responses(
         (status = OK, body = MapResponse),
+        (status = BAD_REQUEST, body = ErrorResponse, description = "Invalid aggregation"),
+        (status = NOT_FOUND, body = ErrorResponse, description = "City or requirement not found"),
         (status = INTERNAL_SERVER_ERROR, body = ErrorResponse, description = "Internal server error")
     )
 )]
 pub async fn get_map(
     State(state): State<Arc<AppState>>,
     Json(request): Json<MapRequest>,
-) -> Result<Json<MapResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<MapResponse>, ApiError> {
     // If there are no requirements, get all the h3 indices and return
     if request.requirement_ids.is_empty() {
-        let indices = state
-            .h3_client
-            .get_indices_for_city(&request.city_code)
-            .map_err(map_error_to_response)?;
+        let indices = state.h3_client.get_indices_for_city(&request.city_code)?;
         let tile_responses: Vec<MapTileResponse> = indices
             .into_iter()
             .map(|index| {
@@ -137,53 +169,120 @@ pub async fn get_map(
                     requirement_scores: vec![],
                 })
             })
-            .collect::<Result<Vec<_>, Error>>()
-            .map_err(map_error_to_response)?;
+            .collect::<Result<Vec<_>, Error>>()?;
         return Ok(Json(MapResponse {
+            aggregation: request.aggregation.mode(),
             tiles: tile_responses,
         }));
     }
 
+    request.aggregation.validate(&request.requirement_ids)?;
+
     // Load the requirements from the database
+    let items = RequirementItem::batch_from_db(&request.requirement_ids, &*state.db_client).await?;
     let mut requirements = vec![];
-    for requirement_id in &request.requirement_ids {
-        let requirement = RequirementItem::from_db(requirement_id, &*state.db_client)
-            .await
-            .map_err(map_error_to_response)?;
+    for (requirement_id, requirement) in request.requirement_ids.iter().zip(items) {
         if let Some(requirement) = requirement {
             requirements.push(requirement);
         } else {
-            return Err(map_error_to_response("Requirement not found"));
+            return Err(ApiError::NotFound(format!(
+                "Requirement {} not found",
+                requirement_id
+            )));
         }
     }
 
-    // Aggregate the scores of the tiles from all requirements
-    let mut tile_scores: HashMap<String, Vec<RequirementScoreResponse>> = HashMap::new();
-    for requirement in &requirements {
-        for tile in &requirement.map_tiles {
-            tile_scores
-                .entry(tile.h3_index.clone())
-                .or_insert_with(Vec::new)
-                .push(RequirementScoreResponse {
-                    requirement_id: requirement.requirement_id,
-                    score: tile.score,
-                });
-        }
+    let tiles = request.aggregation.score_tiles(&requirements);
+    Ok(Json(MapResponse {
+        aggregation: request.aggregation.mode(),
+        tiles,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_state;
+    use aws_sdk_dynamodb::types::{AttributeValue, Put, TransactWriteItem};
+    use database::attribute_value_parser::parse_attribute_value;
+
+    fn request(requirement_id: Uuid) -> RequirementRequest {
+        serde_json::from_value(serde_json::json!({
+            "requirement_id": requirement_id,
+            "city_code": "Adelaide",
+            "travel_mode": "Driving",
+            "locations": [{
+                "id": 1,
+                "address": "Rundle Mall",
+                "h3_index": "87b916804ffffff",
+                "lat": -34.92,
+                "lng": 138.6,
+            }],
+            "tolerated_duration": 1800,
+        }))
+        .unwrap()
     }
 
-    // Calculate the mean score for each tile
-    let mut map_tile_response = vec![];
-    for (h3_index, scores) in tile_scores {
-        // let mean_score = scores.iter().map(|s| s.score).sum::<i32>() as f32 / scores.len() as f32;
-        let min_score = scores.iter().map(|s| s.score).min().unwrap_or(0);
-        map_tile_response.push(MapTileResponse {
-            h3_index,
-            mean_score: min_score,
-            // mean_score: mean_score as i32,
-            requirement_scores: scores,
-        });
+    async fn stored_item(
+        state: &AppState,
+        requirement_id: &Uuid,
+    ) -> HashMap<String, AttributeValue> {
+        let get = RequirementItem::get(requirement_id).unwrap();
+        let output = state.db_client.read_single(get).await.unwrap().unwrap();
+        output.item.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_requirement_refreshes_time_to_live() {
+        let state = test_state().await;
+        let requirement_id = Uuid::now_v7();
+        let response = post_requirement(State(state.clone()), Json(request(requirement_id)))
+            .await
+            .unwrap();
+        assert!(response.recomputed);
+        // Age the saved requirement, as if it was posted a while ago
+        let mut item = stored_item(&state, &requirement_id).await;
+        item.insert("TimeToLive".to_string(), AttributeValue::N("1".to_string()));
+        let put = Put::builder()
+            .table_name("Test-Requirements")
+            .set_item(Some(item))
+            .build()
+            .unwrap();
+        let transaction = TransactWriteItem::builder().put(put).build();
+        state.db_client.write_single(transaction).await.unwrap();
+
+        let response = post_requirement(State(state.clone()), Json(request(requirement_id)))
+            .await
+            .unwrap();
+        assert!(!response.recomputed);
+        let item = stored_item(&state, &requirement_id).await;
+        let time_to_live = parse_attribute_value::<i64>(item.get("TimeToLive")).unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+        assert!(time_to_live > now.as_secs() as i64);
+    }
+
+    #[tokio::test]
+    async fn test_get_requirement_returns_posted_request() {
+        let state = test_state().await;
+        let requirement_id = Uuid::now_v7();
+        let response = post_requirement(State(state.clone()), Json(request(requirement_id)))
+            .await
+            .unwrap();
+        assert!(response.recomputed);
+        let Ok(Json(stored)) = get_requirement(State(state), Path(requirement_id)).await else {
+            panic!("Requirement not found");
+        };
+        assert_eq!(
+            serde_json::to_value(stored).unwrap(),
+            serde_json::to_value(request(requirement_id)).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_unknown_requirement() {
+        let result = get_requirement(State(test_state().await), Path(Uuid::now_v7())).await;
+        assert!(matches!(result, Err(ApiError::NotFound(_))));
     }
-    Ok(Json(MapResponse {
-        tiles: map_tile_response,
-    }))
 }
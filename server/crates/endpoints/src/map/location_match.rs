@@ -0,0 +1,84 @@
+use crate::errors::ApiError;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "match", rename_all = "snake_case")]
+pub enum LocationMatch {
+    // The tile is as close as its nearest location
+    #[default]
+    Any,
+    // The tile is as close as its furthest location
+    All,
+    // The tile is as close as the `count`th nearest location
+    AtLeast {
+        count: usize,
+    },
+}
+
+impl LocationMatch {
+    pub fn validate(&self, location_count: usize) -> Result<(), ApiError> {
+        match self {
+            LocationMatch::AtLeast { count } if *count == 0 || *count > location_count => {
+                Err(ApiError::BadRequest(format!(
+                    "count must be between 1 and the number of locations ({})",
+                    location_count
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Folds the duration to each location into a single duration for the tile.
+    // `None` means the location can't be reached from the tile.
+    pub fn duration(&self, durations: &[Option<i32>]) -> Option<i32> {
+        let mut reachable: Vec<i32> = durations.iter().flatten().copied().collect();
+        reachable.sort();
+        let count = match self {
+            LocationMatch::Any => 1,
+            LocationMatch::All => durations.len(),
+            LocationMatch::AtLeast { count } => *count,
+        };
+        match count {
+            0 => None,
+            _ => reachable.get(count - 1).copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DURATIONS: [Option<i32>; 4] = [Some(900), None, Some(300), Some(600)];
+
+    #[test]
+    fn test_any() {
+        assert_eq!(LocationMatch::Any.duration(&DURATIONS), Some(300));
+        assert_eq!(LocationMatch::Any.duration(&[None, None]), None);
+    }
+
+    #[test]
+    fn test_all() {
+        assert_eq!(LocationMatch::All.duration(&DURATIONS), None);
+        assert_eq!(LocationMatch::All.duration(&DURATIONS[2..]), Some(600));
+    }
+
+    #[test]
+    fn test_at_least() {
+        let location_match = LocationMatch::AtLeast { count: 2 };
+        assert_eq!(location_match.duration(&DURATIONS), Some(600));
+        let location_match = LocationMatch::AtLeast { count: 3 };
+        assert_eq!(location_match.duration(&DURATIONS), Some(900));
+        let location_match = LocationMatch::AtLeast { count: 4 };
+        assert_eq!(location_match.duration(&DURATIONS), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(LocationMatch::AtLeast { count: 0 }.validate(2).is_err());
+        assert!(LocationMatch::AtLeast { count: 3 }.validate(2).is_err());
+        assert!(LocationMatch::AtLeast { count: 2 }.validate(2).is_ok());
+        assert!(LocationMatch::All.validate(2).is_ok());
+    }
+}
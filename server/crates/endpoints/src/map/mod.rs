@@ -1,2 +1,5 @@
+pub mod aggregation;
 pub mod endpoints;
+pub mod location_match;
 pub mod models;
+pub mod scoring;
@@ -1,9 +1,15 @@
+use super::aggregation::{AggregationMode, ScoreAggregation};
+use super::location_match::LocationMatch;
+use super::scoring::ScoringCurve;
+use anyhow::Error;
+use database::spatial_distance_item::SpatialDistanceItem;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use utoipa::ToSchema;
 use uuid::Uuid;
 pub const MAP_TAG: &str = "map";
 
-#[derive(ToSchema, Deserialize)]
+#[derive(ToSchema, Serialize, Deserialize, Clone, Copy)]
 pub enum TravelMode {
     Driving,
     Walking,
@@ -11,29 +17,104 @@ pub enum TravelMode {
     PublicTransport,
 }
 
-#[derive(ToSchema, Deserialize)]
+impl TravelMode {
+    // Negative durations mark routes that haven't been calculated
+    pub fn duration(
+        &self,
+        item: &SpatialDistanceItem,
+        time_window: Option<database::spatial_distance_item::TimeWindow>,
+    ) -> i32 {
+        match self {
+            TravelMode::Driving => item.duration_drive_at(time_window),
+            TravelMode::Bicycling => item.duration_cycle,
+            TravelMode::PublicTransport => item.duration_transit_at(time_window),
+            TravelMode::Walking => item.duration_walk,
+        }
+    }
+}
+
+impl From<TravelMode> for maps::models::TravelMode {
+    fn from(travel_mode: TravelMode) -> Self {
+        match travel_mode {
+            TravelMode::Driving => Self::Driving,
+            TravelMode::Walking => Self::Walking,
+            TravelMode::Bicycling => Self::Bicycling,
+            TravelMode::PublicTransport => Self::Transit,
+        }
+    }
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Copy)]
+pub enum TimeWindow {
+    WeekdayAmPeak,
+    WeekdayPmPeak,
+    OffPeak,
+    Weekend,
+}
+
+impl From<TimeWindow> for database::spatial_distance_item::TimeWindow {
+    fn from(time_window: TimeWindow) -> Self {
+        match time_window {
+            TimeWindow::WeekdayAmPeak => Self::WeekdayAmPeak,
+            TimeWindow::WeekdayPmPeak => Self::WeekdayPmPeak,
+            TimeWindow::OffPeak => Self::OffPeak,
+            TimeWindow::Weekend => Self::Weekend,
+        }
+    }
+}
+
+#[derive(ToSchema, Serialize, Deserialize)]
 pub struct Location {
     pub id: i32,
     pub address: String,
     pub h3_index: String,
     pub lat: f64,
     pub lng: f64,
+    // Overrides the requirement's travel mode for this location
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub travel_mode: Option<TravelMode>,
 }
 
-#[derive(ToSchema, Deserialize)]
+#[derive(ToSchema, Serialize, Deserialize)]
 pub struct RequirementRequest {
     pub requirement_id: Uuid,
     // pub country_code: String,
     pub city_code: String,
     pub travel_mode: TravelMode,
+    // Driving and public transport durations for this time of the week, if they are known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_window: Option<TimeWindow>,
     pub locations: Vec<Location>,
+    #[serde(default)]
+    pub location_match: LocationMatch,
     pub tolerated_duration: i32,
+    #[serde(default)]
+    pub scoring_curve: ScoringCurve,
+    #[serde(default)]
+    pub soft_scoring: bool,
+}
+
+impl RequirementRequest {
+    // Hashes everything that affects the tiles, ignoring the order of the locations
+    pub fn content_hash(&self) -> Result<String, Error> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            object.remove("requirement_id");
+        }
+        if let Some(locations) = value.get_mut("locations").and_then(|l| l.as_array_mut()) {
+            locations.sort_by_key(|location| location.to_string());
+        }
+        let digest = Sha256::digest(value.to_string());
+        Ok(format!("{:x}", digest))
+    }
 }
 
 #[derive(ToSchema, Deserialize, Debug)]
 pub struct MapRequest {
     pub city_code: String,
     pub requirement_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub aggregation: ScoreAggregation,
 }
 
 // Each POST request has 1 or more requirements, and will return the map with the scores.
@@ -78,8 +159,11 @@ pub struct MapTileResponse {
 
 #[derive(ToSchema, Serialize)]
 pub struct MapResponse {
+    pub aggregation: AggregationMode,
     pub tiles: Vec<MapTileResponse>,
 }
 
 #[derive(ToSchema, Serialize)]
-pub struct RequirementResponse {}
+pub struct RequirementResponse {
+    pub recomputed: bool,
+}
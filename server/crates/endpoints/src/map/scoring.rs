@@ -0,0 +1,176 @@
+use crate::errors::ApiError;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub const MAX_SCORE: i32 = 100;
+
+#[derive(ToSchema, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "curve", rename_all = "snake_case")]
+pub enum ScoringCurve {
+    // Falls from 100 to 0 at the tolerated duration
+    #[default]
+    Linear,
+    // 100 up to the tolerated duration
+    Step,
+    // Halves every `half_life` seconds
+    Exponential {
+        half_life: i32,
+    },
+    // An S-curve centred on the tolerated duration, dropping faster with a larger `steepness`.
+    // It's scaled to start at 100, so it scores 50 * (1 + e^-steepness) at the tolerated
+    // duration, which is about 50 for steep curves but 68 at a steepness of 1.
+    Logistic {
+        steepness: f64,
+    },
+}
+
+impl ScoringCurve {
+    pub fn validate(&self, tolerated_duration: i32) -> Result<(), ApiError> {
+        if tolerated_duration <= 0 {
+            return Err(ApiError::BadRequest(
+                "tolerated_duration must be positive".to_string(),
+            ));
+        }
+        match self {
+            ScoringCurve::Exponential { half_life } if *half_life <= 0 => Err(
+                ApiError::BadRequest("half_life must be positive".to_string()),
+            ),
+            ScoringCurve::Logistic { steepness } if !steepness.is_finite() || *steepness <= 0.0 => {
+                Err(ApiError::BadRequest(
+                    "steepness must be a positive number".to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Hard scoring is zero past the tolerated duration. Soft scoring only changes scores past it,
+    // where it keeps following the curve and the step curve instead falls to zero at twice the
+    // tolerated duration. Linear curves already reach zero there, so soft scoring is the same.
+    pub fn score(&self, duration: i32, tolerated_duration: i32, soft: bool) -> i32 {
+        if duration > tolerated_duration && !soft {
+            return 0;
+        }
+        let duration = duration.max(0) as f64;
+        let tolerated_duration = tolerated_duration as f64;
+        let fraction = match self {
+            ScoringCurve::Linear => 1.0 - duration / tolerated_duration,
+            ScoringCurve::Step if duration <= tolerated_duration => 1.0,
+            ScoringCurve::Step => 2.0 - duration / tolerated_duration,
+            ScoringCurve::Exponential { half_life } => 0.5_f64.powf(duration / *half_life as f64),
+            ScoringCurve::Logistic { steepness } => {
+                // Scaled so that a duration of zero scores exactly 100
+                let logistic = |x: f64| 1.0 / (1.0 + (steepness * (x - 1.0)).exp());
+                logistic(duration / tolerated_duration) / logistic(0.0)
+            }
+        };
+        (fraction.clamp(0.0, 1.0) * MAX_SCORE as f64).round() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERATED_DURATION: i32 = 1200;
+
+    fn scores(curve: ScoringCurve, soft: bool) -> Vec<i32> {
+        [0, 300, 600, 1200, 1800, 2400, 3600]
+            .into_iter()
+            .map(|duration| curve.score(duration, TOLERATED_DURATION, soft))
+            .collect()
+    }
+
+    #[test]
+    fn test_linear() {
+        let scores = scores(ScoringCurve::Linear, false);
+        assert_eq!(scores, vec![100, 75, 50, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_linear_soft() {
+        let scores = scores(ScoringCurve::Linear, true);
+        assert_eq!(scores, vec![100, 75, 50, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_step() {
+        let scores = scores(ScoringCurve::Step, false);
+        assert_eq!(scores, vec![100, 100, 100, 100, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_step_soft() {
+        let scores = scores(ScoringCurve::Step, true);
+        assert_eq!(scores, vec![100, 100, 100, 100, 50, 0, 0]);
+    }
+
+    #[test]
+    fn test_exponential() {
+        let curve = ScoringCurve::Exponential { half_life: 600 };
+        assert_eq!(scores(curve, false), vec![100, 71, 50, 25, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_exponential_soft() {
+        let curve = ScoringCurve::Exponential { half_life: 600 };
+        assert_eq!(scores(curve, true), vec![100, 71, 50, 25, 13, 6, 2]);
+    }
+
+    #[test]
+    fn test_logistic() {
+        let curve = ScoringCurve::Logistic { steepness: 5.0 };
+        assert_eq!(scores(curve, false), vec![100, 98, 93, 50, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_logistic_soft() {
+        let curve = ScoringCurve::Logistic { steepness: 5.0 };
+        assert_eq!(scores(curve, true), vec![100, 98, 93, 50, 8, 1, 0]);
+    }
+
+    #[test]
+    fn test_logistic_shallow() {
+        let curve = ScoringCurve::Logistic { steepness: 1.0 };
+        assert_eq!(curve.score(0, TOLERATED_DURATION, false), 100);
+        assert_eq!(
+            curve.score(TOLERATED_DURATION, TOLERATED_DURATION, false),
+            68
+        );
+    }
+
+    #[test]
+    fn test_soft_matches_hard_within_tolerance() {
+        let curves = [
+            ScoringCurve::Linear,
+            ScoringCurve::Step,
+            ScoringCurve::Exponential { half_life: 600 },
+            ScoringCurve::Logistic { steepness: 5.0 },
+        ];
+        for curve in curves {
+            for duration in (0..=TOLERATED_DURATION).step_by(100) {
+                assert_eq!(
+                    curve.score(duration, TOLERATED_DURATION, true),
+                    curve.score(duration, TOLERATED_DURATION, false),
+                    "{:?} at {}",
+                    curve,
+                    duration
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ScoringCurve::Linear.validate(0).is_err());
+        assert!(ScoringCurve::Exponential { half_life: 0 }
+            .validate(TOLERATED_DURATION)
+            .is_err());
+        assert!(ScoringCurve::Logistic { steepness: -1.0 }
+            .validate(TOLERATED_DURATION)
+            .is_err());
+        assert!(ScoringCurve::Logistic { steepness: 5.0 }
+            .validate(TOLERATED_DURATION)
+            .is_ok());
+    }
+}
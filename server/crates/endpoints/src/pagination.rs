@@ -1,10 +1,13 @@
-use anyhow::Error;
+use crate::errors::ApiError;
 use database::paginated_models::deserialise_db_key;
 use database::paginated_models::DbKey;
 use serde::Deserialize;
 use serde::Serialize;
 use utoipa::ToSchema;
 
+pub const DEFAULT_PAGE_SIZE: i32 = 20;
+pub const MAX_PAGE_SIZE: i32 = 100;
+
 #[derive(Deserialize)]
 pub struct PaginationParams {
     pub limit: Option<i32>,
@@ -12,9 +15,25 @@ pub struct PaginationParams {
 }
 
 impl PaginationParams {
-    pub fn decode_last_evaluated_key(&self) -> Result<Option<DbKey>, Error> {
+    // Pages always have a size, so a request can't read a whole city at once
+    pub fn page_size(&self) -> Result<i32, ApiError> {
+        match self.limit {
+            None => Ok(DEFAULT_PAGE_SIZE),
+            Some(limit) if (1..=MAX_PAGE_SIZE).contains(&limit) => Ok(limit),
+            Some(_) => Err(ApiError::BadRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_PAGE_SIZE
+            ))),
+        }
+    }
+
+    pub fn decode_last_evaluated_key(&self) -> Result<Option<DbKey>, ApiError> {
         match &self.last_evaluated_key {
-            Some(key) => Ok(Some(deserialise_db_key(key)?)),
+            Some(key) => {
+                let key = deserialise_db_key(key)
+                    .map_err(|_| ApiError::BadRequest("Invalid last_evaluated_key".to_string()))?;
+                Ok(Some(key))
+            }
             None => Ok(None),
         }
     }
@@ -25,3 +44,27 @@ pub struct PaginatedResponse<T> {
     pub items: Vec<T>,
     pub last_evaluated_key: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pagination(limit: Option<i32>) -> PaginationParams {
+        PaginationParams {
+            limit,
+            last_evaluated_key: None,
+        }
+    }
+
+    #[test]
+    fn test_page_size() {
+        assert_eq!(pagination(None).page_size().unwrap(), DEFAULT_PAGE_SIZE);
+        assert_eq!(pagination(Some(5)).page_size().unwrap(), 5);
+        for limit in [-1, 0, MAX_PAGE_SIZE + 1] {
+            assert!(matches!(
+                pagination(Some(limit)).page_size(),
+                Err(ApiError::BadRequest(_))
+            ));
+        }
+    }
+}
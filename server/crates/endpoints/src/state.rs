@@ -1,9 +1,32 @@
 use database::dynamodb_client_trait::IDynamoDbClient;
 use h3_mapper::h3_client::H3Client;
 use houses::house_client::HouseClient;
+use maps::maps_provider::MapsProvider;
 
 pub struct AppState {
     pub db_client: Box<dyn IDynamoDbClient>,
     pub house_client: HouseClient,
     pub h3_client: H3Client,
+    // Only needed for exact routes, so the API can run without a maps API key
+    pub maps_client: Option<Box<dyn MapsProvider>>,
+}
+
+// Loading the local fake's data takes a while, so every test shares one state
+#[cfg(test)]
+pub async fn test_state() -> std::sync::Arc<AppState> {
+    use database::dynamodb_client_local::DynamoDbClient;
+    use tokio::sync::OnceCell;
+
+    static STATE: OnceCell<std::sync::Arc<AppState>> = OnceCell::const_new();
+    STATE
+        .get_or_init(|| async {
+            std::sync::Arc::new(AppState {
+                db_client: Box::new(DynamoDbClient::new().await.unwrap()),
+                house_client: HouseClient::new(),
+                h3_client: H3Client::new().unwrap(),
+                maps_client: None,
+            })
+        })
+        .await
+        .clone()
 }
@@ -1,71 +1,251 @@
 use anyhow::{Error, Result};
-use csv::ReaderBuilder;
-use geo::Polygon;
-use geojson::GeoJson;
+use geo::Geometry;
+use geojson::{Feature, GeoJson};
 use h3o::{
     geom::{ContainmentMode, TilerBuilder},
-    CellIndex, Resolution,
+    CellIndex, LatLng, Resolution,
 };
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
 
-pub struct H3Client {}
+const EMBEDDED_CITIES: [&str; 1] = [include_str!("cities/adelaide.geojson")];
+
+#[derive(Debug)]
+pub struct UnknownCityError {
+    pub city_code: String,
+}
+
+impl fmt::Display for UnknownCityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown city {}", self.city_code)
+    }
+}
+
+impl std::error::Error for UnknownCityError {}
+
+pub struct City {
+    pub city_code: String,
+    pub resolution: Resolution,
+    pub cells: Vec<CellIndex>,
+}
+
+impl City {
+    // Each city is a GeoJSON feature with `city_code` and `resolution` properties.
+    // A cell belongs to the city if its centroid is inside the boundary.
+    pub fn from_geojson(city_geojson: &str) -> Result<Self, Error> {
+        let feature = match city_geojson.parse::<GeoJson>()? {
+            GeoJson::Feature(feature) => feature,
+            _ => return Err(anyhow::anyhow!("Expected a GeoJSON feature")),
+        };
+        let city_code = Self::get_property(&feature, "city_code")?
+            .as_str()
+            .ok_or(anyhow::anyhow!("Expected city_code to be a string"))?
+            .to_string();
+        let resolution = Self::get_property(&feature, "resolution")?
+            .as_u64()
+            .ok_or(anyhow::anyhow!("Expected resolution to be a number"))?;
+        let resolution = Resolution::try_from(u8::try_from(resolution)?)?;
+        let geometry = feature
+            .geometry
+            .ok_or(anyhow::anyhow!("No geometry for {}", city_code))?;
+        let polygons = match Geometry::<f64>::try_from(geometry.value)? {
+            Geometry::Polygon(polygon) => vec![polygon],
+            Geometry::MultiPolygon(multi_polygon) => multi_polygon.0,
+            _ => return Err(anyhow::anyhow!("Invalid GeoJSON for {}", city_code)),
+        };
+        let mut tiler = TilerBuilder::new(resolution)
+            .containment_mode(ContainmentMode::ContainsCentroid)
+            .build();
+        tiler.add_batch(polygons)?;
+        let mut cells = tiler.into_coverage().collect::<Vec<_>>();
+        cells.sort();
+        Ok(City {
+            city_code,
+            resolution,
+            cells,
+        })
+    }
+
+    fn get_property<'a>(feature: &'a Feature, name: &str) -> Result<&'a serde_json::Value, Error> {
+        feature
+            .property(name)
+            .ok_or(anyhow::anyhow!("Missing property {}", name))
+    }
+}
+
+pub struct H3Client {
+    cities: HashMap<String, City>,
+}
 
 impl H3Client {
-    pub fn new() -> Self {
-        H3Client {}
-    }
-    pub fn get_indices_for_city(&self, _city_code: &str) -> Result<Vec<String>, Error> {
-        let csv_data = include_str!("adelaide_boundary.csv");
-        let mut reader = ReaderBuilder::new().from_reader(csv_data.as_bytes());
-        let mut items = Vec::new();
-        for result in reader.records() {
-            let record = result?;
-            let h3_index = record[0].to_string();
-            items.push(h3_index);
+    pub fn new() -> Result<Self, Error> {
+        Self::from_geojsons(EMBEDDED_CITIES.iter().map(|city| city.to_string()))
+    }
+
+    pub fn from_directory(path: &Path) -> Result<Self, Error> {
+        let mut city_geojsons = vec![];
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "geojson")
+            {
+                city_geojsons.push(fs::read_to_string(path)?);
+            }
         }
+        Self::from_geojsons(city_geojsons)
+    }
+
+    fn from_geojsons(city_geojsons: impl IntoIterator<Item = String>) -> Result<Self, Error> {
+        let mut cities = HashMap::new();
+        for city_geojson in city_geojsons {
+            let city = City::from_geojson(&city_geojson)?;
+            if cities.contains_key(&city.city_code) {
+                return Err(anyhow::anyhow!("Duplicate city {}", city.city_code));
+            }
+            cities.insert(city.city_code.clone(), city);
+        }
+        Ok(H3Client { cities })
+    }
+
+    pub fn get_city(&self, city_code: &str) -> Result<&City, Error> {
+        let city = self.cities.get(city_code).ok_or(UnknownCityError {
+            city_code: city_code.to_string(),
+        })?;
+        Ok(city)
+    }
+
+    pub fn get_indices_for_city(&self, city_code: &str) -> Result<Vec<String>, Error> {
+        let city = self.get_city(city_code)?;
+        let items = city.cells.iter().map(|cell| cell.to_string()).collect();
         Ok(items)
     }
 
-    pub fn get_cell_indices_for_city(&self, _city_code: &str) -> Result<Vec<CellIndex>, Error> {
-        let adelaide = r#"
-        {
-            "type": "Polygon",
-            "coordinates": [
-                [
-                    [138.51536049346163, -34.85490824066172],
-                    [138.6595633990527, -34.84255603861368],
-                    [138.6873056569005, -34.95834949048342],
-                    [138.5194630253865, -34.96855376947172],
-                    [138.51536049346163, -34.85490824066172]
-                ]
-            ]
+    // The cell containing a point, at the city's resolution. The point may be outside the city.
+    pub fn get_index_for_location(&self, city_code: &str, lat: f64, lng: f64) -> Result<String> {
+        let city = self.get_city(city_code)?;
+        let cell = LatLng::new(lat, lng)?.to_cell(city.resolution);
+        Ok(cell.to_string())
+    }
+
+    // The centre of a cell, as (lat, lng)
+    pub fn get_location_for_index(h3_index: &str) -> Result<(f64, f64)> {
+        let cell: CellIndex = h3_index.parse()?;
+        let lat_lng = LatLng::from(cell);
+        Ok((lat_lng.lat(), lat_lng.lng()))
+    }
+
+    pub fn get_cell_indices_for_city(&self, city_code: &str) -> Result<Vec<CellIndex>, Error> {
+        let city = self.get_city(city_code)?;
+        Ok(city.cells.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A square around a point, as a GeoJSON ring
+    fn square(lng: f64, lat: f64) -> serde_json::Value {
+        let size = 0.05;
+        serde_json::json!([[
+            [lng - size, lat - size],
+            [lng + size, lat - size],
+            [lng + size, lat + size],
+            [lng - size, lat + size],
+            [lng - size, lat - size],
+        ]])
+    }
+
+    fn city_geojson(city_code: &str, geometry: serde_json::Value) -> String {
+        serde_json::json!({
+            "type": "Feature",
+            "properties": { "city_code": city_code, "resolution": 7 },
+            "geometry": geometry,
+        })
+        .to_string()
+    }
+
+    fn cities_directory(name: &str, files: &[(&str, String)]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("h3_mapper_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&path).ok();
+        fs::create_dir_all(&path).unwrap();
+        for (file_name, contents) in files {
+            fs::write(path.join(file_name), contents).unwrap();
         }
-        "#;
-        // let adelaide = r#"
-        // {
-        //     "type": "Polygon",
-        //     "coordinates": [
-        //         [
-        //             [138.3788372027332, -34.55471765878935],
-        //             [138.98283859854226, -34.54192062482642],
-        //             [139.0177968795236, -35.31258726750041],
-        //             [138.40020059699214, -35.306247834898016],
-        //             [138.3788372027332, -34.55471765878935]
-        //         ]
-        //     ]
-        // }
-        // "#;
-
-        let city_geojson = adelaide;
-        let geojson: GeoJson = city_geojson.parse().unwrap();
-        let polygon: Polygon<f64> = match geojson {
-            GeoJson::Geometry(geometry) => geometry.value.try_into()?,
-            _ => return Err(anyhow::anyhow!("Invalid GeoJSON")),
-        };
-        let mut tiler = TilerBuilder::new(Resolution::Eight)
-            .containment_mode(ContainmentMode::Covers)
-            .build();
-        tiler.add(polygon)?;
-        let cells = tiler.into_coverage().collect::<Vec<_>>();
-        Ok(cells)
+        path
+    }
+
+    #[test]
+    fn test_unknown_city() {
+        let h3_client = H3Client::new().unwrap();
+        let error = h3_client.get_indices_for_city("Atlantis").unwrap_err();
+        let error = error.downcast_ref::<UnknownCityError>().unwrap();
+        assert_eq!(error.city_code, "Atlantis");
+        assert!(h3_client.get_city("Adelaide").is_ok());
+    }
+
+    #[test]
+    fn test_from_directory() {
+        let polygon = serde_json::json!({ "type": "Polygon", "coordinates": square(138.6, -34.9) });
+        let path = cities_directory(
+            "cities",
+            &[
+                ("adelaide.geojson", city_geojson("Adelaide", polygon)),
+                ("README.md", "Not a city".to_string()),
+            ],
+        );
+        let h3_client = H3Client::from_directory(&path);
+        fs::remove_dir_all(&path).unwrap();
+        let h3_client = h3_client.unwrap();
+        assert!(!h3_client
+            .get_indices_for_city("Adelaide")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_city_in_directory() {
+        let polygon = serde_json::json!({ "type": "Polygon", "coordinates": square(138.6, -34.9) });
+        let path = cities_directory(
+            "duplicates",
+            &[
+                (
+                    "adelaide.geojson",
+                    city_geojson("Adelaide", polygon.clone()),
+                ),
+                ("adelaide_copy.geojson", city_geojson("Adelaide", polygon)),
+            ],
+        );
+        let result = H3Client::from_directory(&path);
+        fs::remove_dir_all(&path).unwrap();
+        let error = result.err().unwrap();
+        assert_eq!(error.to_string(), "Duplicate city Adelaide");
+    }
+
+    #[test]
+    fn test_multi_polygon_covers_every_polygon() {
+        // The city centre and Mount Barker, which don't touch
+        let parts = [square(138.6, -34.9), square(138.86, -35.07)];
+        let multi_polygon = serde_json::json!({ "type": "MultiPolygon", "coordinates": parts });
+        let city = City::from_geojson(&city_geojson("Adelaide", multi_polygon)).unwrap();
+        let mut expected = vec![];
+        for part in parts {
+            let polygon = serde_json::json!({ "type": "Polygon", "coordinates": part });
+            let part = City::from_geojson(&city_geojson("Adelaide", polygon)).unwrap();
+            assert!(!part.cells.is_empty());
+            expected.extend(part.cells);
+        }
+        expected.sort();
+        assert_eq!(city.cells, expected);
+    }
+
+    #[test]
+    fn test_rejects_other_geometries() {
+        let point = serde_json::json!({ "type": "Point", "coordinates": [138.6, -34.9] });
+        assert!(City::from_geojson(&city_geojson("Adelaide", point)).is_err());
     }
 }
@@ -2,6 +2,7 @@ use super::models::House;
 use anyhow::Result;
 use csv::ReaderBuilder;
 
+#[derive(Default)]
 pub struct HouseClient {
     houses: Vec<House>,
 }
@@ -15,18 +16,16 @@ impl HouseClient {
         let csv_data = include_str!("houses.csv");
         let mut reader = ReaderBuilder::new().from_reader(csv_data.as_bytes());
         let mut houses = Vec::new();
-        let mut id = 1;
-        for result in reader.records() {
+        for (id, result) in (1..).zip(reader.records()) {
             let record = result?;
             let house = House {
-                id: id,
+                id,
                 address: record[0].to_string(),
                 url: record[1].to_string(),
                 lat: record[2].parse().ok(),
                 lon: record[3].parse().ok(),
             };
             houses.push(house);
-            id += 1;
         }
         self.houses = houses;
         Ok(())
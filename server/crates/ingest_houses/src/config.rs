@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub struct Config {
+    // A CSV or JSON listing export
+    pub input_path: PathBuf,
+    // For rows without a city
+    pub city_code: String,
+    pub batch_size: usize,
+    pub dry_run: bool,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self> {
+        let input_path = env::args()
+            .skip(1)
+            .find(|arg| !arg.starts_with("--"))
+            .context("Usage: ingest_houses [--dry-run] <listings.csv|listings.json>")?;
+        let config = Config {
+            input_path: PathBuf::from(input_path),
+            city_code: env_or("CITY_CODE", "Adelaide".to_string())?,
+            batch_size: env_or("BATCH_SIZE", 25)?,
+            dry_run: env::args().any(|arg| arg == "--dry-run"),
+        };
+        // DynamoDB transactions are limited to 100 items
+        if config.batch_size == 0 || config.batch_size > 100 {
+            return Err(anyhow::anyhow!("BATCH_SIZE must be between 1 and 100"));
+        }
+        Ok(config)
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> Result<T> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse::<T>()
+            .ok()
+            .with_context(|| format!("Invalid value for {}: {}", name, value)),
+        Err(_) => Ok(default),
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{Context, Result};
+use csv::ReaderBuilder;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// A row from a listing export, keyed by normalised column name
+pub type RawListing = HashMap<String, String>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listing {
+    pub address: String,
+    pub url: String,
+    pub city_code: String,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+    pub price_lower: i32,
+    pub price_upper: i32,
+    pub num_bathrooms: i32,
+    pub num_bedrooms: i32,
+    pub num_carspaces: i32,
+    pub property_type: String,
+}
+
+// Exports name columns differently, e.g. "NumCarSpaces", "num_carspaces" and "Lon" vs "Lng"
+fn normalise_column(column: &str) -> String {
+    let column: String = column
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    match column.as_str() {
+        "lon" | "long" | "longitude" => "lng".to_string(),
+        "latitude" => "lat".to_string(),
+        "bathrooms" => "numbathrooms".to_string(),
+        "bedrooms" => "numbedrooms".to_string(),
+        "carspaces" | "parking" => "numcarspaces".to_string(),
+        _ => column,
+    }
+}
+
+pub fn read_listings(path: &Path) -> Result<Vec<RawListing>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("csv") => read_csv(&contents),
+        Some("json") => read_json(&contents),
+        _ => Err(anyhow::anyhow!(
+            "Expected a .csv or .json file, got {}",
+            path.display()
+        )),
+    }
+}
+
+fn read_csv(contents: &str) -> Result<Vec<RawListing>> {
+    let mut reader = ReaderBuilder::new().from_reader(contents.as_bytes());
+    let columns: Vec<String> = reader.headers()?.iter().map(normalise_column).collect();
+    let mut listings = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let listing = columns
+            .iter()
+            .cloned()
+            .zip(record.iter().map(str::to_string))
+            .collect();
+        listings.push(listing);
+    }
+    Ok(listings)
+}
+
+// An array of objects, with numbers or strings as values
+fn read_json(contents: &str) -> Result<Vec<RawListing>> {
+    let rows: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_str(contents)?;
+    let listings = rows
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .filter_map(|(column, value)| {
+                    let value = match value {
+                        serde_json::Value::String(value) => value,
+                        serde_json::Value::Number(value) => value.to_string(),
+                        _ => return None,
+                    };
+                    Some((normalise_column(&column), value))
+                })
+                .collect()
+        })
+        .collect();
+    Ok(listings)
+}
+
+fn field<'a>(raw: &'a RawListing, column: &str) -> Option<&'a str> {
+    raw.get(column)
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+// Accepts "$585,000" as well as "585000"
+fn parse_price(value: &str) -> Option<i32> {
+    let digits: String = value
+        .chars()
+        .filter(|c| !matches!(c, '$' | ',' | ' '))
+        .collect();
+    digits.parse::<i32>().ok().filter(|price| *price > 0)
+}
+
+fn parse_count(raw: &RawListing, column: &str, reasons: &mut Vec<String>) -> i32 {
+    match field(raw, column).map(str::parse::<i32>) {
+        Some(Ok(count)) if count >= 0 => count,
+        Some(_) => {
+            reasons.push(format!("invalid {}", column));
+            0
+        }
+        None => {
+            reasons.push(format!("missing {}", column));
+            0
+        }
+    }
+}
+
+// "apartment" and "APARTMENT" become "Apartment"
+fn normalise_property_type(value: &str) -> String {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+impl Listing {
+    // Returns every problem with the row, rather than just the first
+    pub fn from_raw(raw: &RawListing, default_city_code: &str) -> Result<Self, Vec<String>> {
+        let mut reasons = vec![];
+        // Addresses are part of the key, so whitespace differences would create duplicates
+        let address = field(raw, "address")
+            .map(|address| address.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        if address.is_empty() {
+            reasons.push("missing address".to_string());
+        }
+        let url = field(raw, "url").unwrap_or_default().to_string();
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            reasons.push("missing or invalid url".to_string());
+        }
+        let city_code = field(raw, "citycode")
+            .unwrap_or(default_city_code)
+            .to_string();
+
+        let (lat, lng) = match (field(raw, "lat"), field(raw, "lng")) {
+            (Some(lat), Some(lng)) => match (lat.parse::<f64>(), lng.parse::<f64>()) {
+                (Ok(lat), Ok(lng))
+                    if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng) =>
+                {
+                    (Some(lat), Some(lng))
+                }
+                _ => {
+                    reasons.push("invalid lat/lng".to_string());
+                    (None, None)
+                }
+            },
+            // Geocoded from the address later
+            _ => (None, None),
+        };
+
+        let price = field(raw, "price").map(parse_price);
+        let price_lower = field(raw, "pricelower").map(parse_price).or(price);
+        let price_upper = field(raw, "priceupper").map(parse_price).or(price);
+        let (price_lower, price_upper) = match (price_lower, price_upper) {
+            (Some(Some(lower)), Some(Some(upper))) if lower <= upper => (lower, upper),
+            (Some(Some(_)), Some(Some(_))) => {
+                reasons.push("price_lower is greater than price_upper".to_string());
+                (0, 0)
+            }
+            (None, _) | (_, None) => {
+                reasons.push("missing price".to_string());
+                (0, 0)
+            }
+            _ => {
+                reasons.push("invalid price".to_string());
+                (0, 0)
+            }
+        };
+
+        let num_bathrooms = parse_count(raw, "numbathrooms", &mut reasons);
+        let num_bedrooms = parse_count(raw, "numbedrooms", &mut reasons);
+        let num_carspaces = parse_count(raw, "numcarspaces", &mut reasons);
+        let property_type = normalise_property_type(field(raw, "propertytype").unwrap_or_default());
+        if property_type.is_empty() {
+            reasons.push("missing property_type".to_string());
+        }
+
+        if !reasons.is_empty() {
+            return Err(reasons);
+        }
+        Ok(Listing {
+            address,
+            url,
+            city_code,
+            lat,
+            lng,
+            price_lower,
+            price_upper,
+            num_bathrooms,
+            num_bedrooms,
+            num_carspaces,
+            property_type,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "\
+Address,Url,PriceLower,PriceUpper,PropertyType,NumBathrooms,NumBedrooms,NumCarSpaces,CityCode,Lat,Lng
+\"81/223  North Terrace, Adelaide\",https://example.com/1,585000,\"$600,000\",apartment,1,2,1,Adelaide,-34.837,138.553
+";
+
+    #[test]
+    fn test_csv_listing() {
+        let raw = read_csv(CSV).unwrap();
+        let listing = Listing::from_raw(&raw[0], "Adelaide").unwrap();
+        assert_eq!(listing.address, "81/223 North Terrace, Adelaide");
+        assert_eq!((listing.price_lower, listing.price_upper), (585000, 600000));
+        assert_eq!(listing.property_type, "Apartment");
+        assert_eq!((listing.lat, listing.lng), (Some(-34.837), Some(138.553)));
+    }
+
+    #[test]
+    fn test_json_listing_without_coordinates() {
+        let json = r#"[{"address": "1 King William St, Adelaide", "url": "https://example.com/2",
+            "price": 750000, "property_type": "House", "bedrooms": 3, "bathrooms": 2, "carspaces": 1}]"#;
+        let raw = read_json(json).unwrap();
+        let listing = Listing::from_raw(&raw[0], "Adelaide").unwrap();
+        assert_eq!((listing.price_lower, listing.price_upper), (750000, 750000));
+        assert_eq!(listing.num_bedrooms, 3);
+        assert_eq!(listing.city_code, "Adelaide");
+        assert_eq!((listing.lat, listing.lng), (None, None));
+    }
+
+    #[test]
+    fn test_rejected_listing_has_every_reason() {
+        let raw = RawListing::from([
+            ("address".to_string(), "".to_string()),
+            ("pricelower".to_string(), "700000".to_string()),
+            ("priceupper".to_string(), "600000".to_string()),
+            ("lat".to_string(), "-134.9".to_string()),
+            ("lng".to_string(), "138.6".to_string()),
+        ]);
+        let reasons = Listing::from_raw(&raw, "Adelaide").unwrap_err();
+        assert!(reasons.contains(&"missing address".to_string()));
+        assert!(reasons.contains(&"price_lower is greater than price_upper".to_string()));
+        assert!(reasons.contains(&"invalid lat/lng".to_string()));
+        assert!(reasons.contains(&"missing numbedrooms".to_string()));
+    }
+}
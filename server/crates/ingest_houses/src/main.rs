@@ -0,0 +1,142 @@
+mod config;
+mod listing;
+mod validator;
+
+use anyhow::{Context, Result};
+use config::Config;
+#[cfg(feature = "cloud")]
+use database::dynamodb_client_cloud::DynamoDbClient;
+#[cfg(feature = "local")]
+use database::dynamodb_client_local::DynamoDbClient;
+use database::dynamodb_client_trait::IDynamoDbClient;
+use database::house_item::HouseItem;
+use dotenv::from_path;
+use h3_mapper::h3_client::H3Client;
+use listing::{read_listings, Listing};
+use maps::geocode_cache::{DynamoDbGeocodeCache, GeocodeCache, Geocoder, InMemoryGeocodeCache};
+use maps::maps_provider::{maps_provider_from_env, MapsProvider};
+use std::env;
+use std::sync::Arc;
+use validator::Validator;
+
+struct Rejection {
+    row: usize,
+    address: String,
+    reasons: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let env_path = env::current_dir()?.join("server/.env");
+    from_path(env_path).ok();
+    let config = Config::from_env()?;
+    let db_client: Arc<dyn IDynamoDbClient> = Arc::new(DynamoDbClient::new().await?);
+    let h3_client = H3Client::new()?;
+
+    let raw_listings = read_listings(&config.input_path)?;
+    println!(
+        "Read {} rows from {}",
+        raw_listings.len(),
+        config.input_path.display()
+    );
+    // Only set up when a row needs geocoding, so complete exports don't need an API key
+    let mut maps_client: Option<Box<dyn MapsProvider>> = None;
+    let cache: Box<dyn GeocodeCache> = match env::var("GEOCODE_CACHE_TABLE_NAME") {
+        Ok(_) => Box::new(DynamoDbGeocodeCache::new(db_client.clone())),
+        Err(_) => Box::new(InMemoryGeocodeCache::default()),
+    };
+    let geocoder = Geocoder::new(cache);
+    let mut validator = Validator::new(&h3_client);
+    let mut houses = vec![];
+    let mut rejections = vec![];
+    let mut num_to_geocode = 0;
+
+    for (index, raw) in raw_listings.iter().enumerate() {
+        let mut reject = |reasons: Vec<String>| {
+            rejections.push(Rejection {
+                row: index + 1,
+                address: raw.get("address").cloned().unwrap_or_default(),
+                reasons,
+            })
+        };
+        let listing = match Listing::from_raw(raw, &config.city_code) {
+            Ok(listing) => listing,
+            Err(reasons) => {
+                reject(reasons);
+                continue;
+            }
+        };
+        if let Err(reason) = validator.check_city(&listing.city_code) {
+            reject(vec![reason]);
+            continue;
+        }
+        let (lat, lng) = match (listing.lat, listing.lng) {
+            (Some(lat), Some(lng)) => (lat, lng),
+            _ if config.dry_run => {
+                num_to_geocode += 1;
+                continue;
+            }
+            _ => {
+                if maps_client.is_none() {
+                    maps_client = Some(maps_provider_from_env()?);
+                }
+                let maps_client = maps_client.as_deref().context("No maps client")?;
+                match geocoder.locate(maps_client, &listing.address).await {
+                    Ok(location) => (location.lat, location.lng),
+                    Err(error) => {
+                        reject(vec![format!("failed to geocode: {:#}", error)]);
+                        continue;
+                    }
+                }
+            }
+        };
+        match validator.house(listing, lat, lng) {
+            Ok(house) => houses.push(house),
+            Err(reason) => reject(vec![reason]),
+        }
+    }
+
+    let mut num_written = 0;
+    let mut num_failed = 0;
+    if !config.dry_run {
+        for batch in houses.chunks(config.batch_size) {
+            let items = batch
+                .iter()
+                .map(HouseItem::save_listing)
+                .collect::<Result<_>>()?;
+            match db_client.write(items).await {
+                Ok(()) => num_written += batch.len(),
+                Err(error) => {
+                    num_failed += batch.len();
+                    eprintln!("Failed to write {} houses: {:#}", batch.len(), error);
+                }
+            }
+        }
+    }
+
+    for rejection in &rejections {
+        println!(
+            "Rejected row {} ({}): {}",
+            rejection.row,
+            rejection.address,
+            rejection.reasons.join(", ")
+        );
+    }
+    let stats = geocoder.stats();
+    println!("Valid: {}", houses.len());
+    println!("Rejected: {}", rejections.len());
+    if config.dry_run {
+        println!("Would geocode: {}", num_to_geocode);
+        return Ok(());
+    }
+    println!(
+        "Geocoded: {} ({} from the cache)",
+        stats.hits + stats.misses,
+        stats.hits
+    );
+    println!("Written: {}", num_written);
+    if num_failed > 0 {
+        return Err(anyhow::anyhow!("{} houses failed to write", num_failed));
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use crate::listing::Listing;
+use database::house_item::HouseItem;
+use h3_mapper::h3_client::H3Client;
+use std::collections::{HashMap, HashSet};
+
+// Checks the parts of a listing that depend on the other rows and the city boundaries
+pub struct Validator<'a> {
+    h3_client: &'a H3Client,
+    city_cells: HashMap<String, HashSet<String>>,
+    seen_keys: HashSet<(String, String)>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(h3_client: &'a H3Client) -> Self {
+        Validator {
+            h3_client,
+            city_cells: HashMap::new(),
+            seen_keys: HashSet::new(),
+        }
+    }
+
+    // Checked before geocoding, so rows for unknown cities aren't paid for
+    pub fn check_city(&mut self, city_code: &str) -> Result<(), String> {
+        if !self.city_cells.contains_key(city_code) {
+            let indices = self
+                .h3_client
+                .get_indices_for_city(city_code)
+                .map_err(|error| error.to_string())?;
+            self.city_cells
+                .insert(city_code.to_string(), indices.into_iter().collect());
+        }
+        Ok(())
+    }
+
+    pub fn house(&mut self, listing: Listing, lat: f64, lng: f64) -> Result<HouseItem, String> {
+        self.check_city(&listing.city_code)?;
+        let h3_index = self
+            .h3_client
+            .get_index_for_location(&listing.city_code, lat, lng)
+            .map_err(|error| format!("invalid location: {}", error))?;
+        if !self.city_cells[&listing.city_code].contains(&h3_index) {
+            return Err(format!("outside {}", listing.city_code));
+        }
+        if !self
+            .seen_keys
+            .insert((h3_index.clone(), listing.address.clone()))
+        {
+            return Err("duplicate address".to_string());
+        }
+        Ok(HouseItem {
+            h3_index,
+            address: listing.address,
+            city_code: listing.city_code,
+            url: listing.url,
+            lat,
+            lng,
+            price_lower: listing.price_lower,
+            price_upper: listing.price_upper,
+            num_bathrooms: listing.num_bathrooms,
+            num_bedrooms: listing.num_bedrooms,
+            num_carspaces: listing.num_carspaces,
+            property_type: listing.property_type,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(address: &str, city_code: &str) -> Listing {
+        Listing {
+            address: address.to_string(),
+            url: "https://example.com/1".to_string(),
+            city_code: city_code.to_string(),
+            lat: None,
+            lng: None,
+            price_lower: 585000,
+            price_upper: 600000,
+            num_bathrooms: 1,
+            num_bedrooms: 2,
+            num_carspaces: 1,
+            property_type: "Apartment".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_house_in_city() {
+        let h3_client = H3Client::new().unwrap();
+        let mut validator = Validator::new(&h3_client);
+        let house = validator
+            .house(
+                listing("17 Foster Street, Norwood", "Adelaide"),
+                -34.9162,
+                138.6374,
+            )
+            .unwrap();
+        let expected = h3_client
+            .get_index_for_location("Adelaide", -34.9162, 138.6374)
+            .unwrap();
+        assert_eq!(house.h3_index, expected);
+        assert_eq!(house.num_carspaces, 1);
+    }
+
+    #[test]
+    fn test_rejects_unknown_city() {
+        let h3_client = H3Client::new().unwrap();
+        let mut validator = Validator::new(&h3_client);
+        assert!(validator.check_city("Atlantis").is_err());
+        assert!(validator.check_city("Adelaide").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_house_outside_city() {
+        let h3_client = H3Client::new().unwrap();
+        let mut validator = Validator::new(&h3_client);
+        // Sydney
+        let error = validator
+            .house(
+                listing("1 Macquarie Street", "Adelaide"),
+                -33.8617,
+                151.2131,
+            )
+            .unwrap_err();
+        assert_eq!(error, "outside Adelaide");
+    }
+
+    #[test]
+    fn test_rejects_duplicate_address() {
+        let h3_client = H3Client::new().unwrap();
+        let mut validator = Validator::new(&h3_client);
+        let address = "17 Foster Street, Norwood";
+        assert!(validator
+            .house(listing(address, "Adelaide"), -34.9162, 138.6374)
+            .is_ok());
+        let error = validator
+            .house(listing(address, "Adelaide"), -34.9162, 138.6374)
+            .unwrap_err();
+        assert_eq!(error, "duplicate address");
+    }
+}
@@ -0,0 +1,167 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+
+// A non-OK `status` from a Google Maps API response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GoogleApiError {
+    OverQueryLimit(Option<String>),
+    RequestDenied(Option<String>),
+    ZeroResults,
+    NotFound(Option<String>),
+    InvalidRequest(Option<String>),
+    // Google suggests these may succeed if retried
+    UnknownError(Option<String>),
+    Other {
+        status: String,
+        message: Option<String>,
+    },
+}
+
+impl GoogleApiError {
+    pub fn from_status(status: &str, error_message: Option<String>) -> Option<Self> {
+        let error = match status {
+            "OK" => return None,
+            "OVER_QUERY_LIMIT" | "OVER_DAILY_LIMIT" => {
+                GoogleApiError::OverQueryLimit(error_message)
+            }
+            "REQUEST_DENIED" => GoogleApiError::RequestDenied(error_message),
+            "ZERO_RESULTS" => GoogleApiError::ZeroResults,
+            "NOT_FOUND" => GoogleApiError::NotFound(error_message),
+            "INVALID_REQUEST" => GoogleApiError::InvalidRequest(error_message),
+            "UNKNOWN_ERROR" => GoogleApiError::UnknownError(error_message),
+            _ => GoogleApiError::Other {
+                status: status.to_string(),
+                message: error_message,
+            },
+        };
+        Some(error)
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            GoogleApiError::OverQueryLimit(_) | GoogleApiError::UnknownError(_)
+        )
+    }
+
+    // ZERO_RESULTS and NOT_FOUND describe the request rather than a problem with the call
+    pub fn is_answer(&self) -> bool {
+        matches!(
+            self,
+            GoogleApiError::ZeroResults | GoogleApiError::NotFound(_)
+        )
+    }
+
+    fn status(&self) -> &str {
+        match self {
+            GoogleApiError::OverQueryLimit(_) => "OVER_QUERY_LIMIT",
+            GoogleApiError::RequestDenied(_) => "REQUEST_DENIED",
+            GoogleApiError::ZeroResults => "ZERO_RESULTS",
+            GoogleApiError::NotFound(_) => "NOT_FOUND",
+            GoogleApiError::InvalidRequest(_) => "INVALID_REQUEST",
+            GoogleApiError::UnknownError(_) => "UNKNOWN_ERROR",
+            GoogleApiError::Other { status, .. } => status,
+        }
+    }
+
+    fn message(&self) -> Option<&String> {
+        match self {
+            GoogleApiError::OverQueryLimit(message)
+            | GoogleApiError::RequestDenied(message)
+            | GoogleApiError::NotFound(message)
+            | GoogleApiError::InvalidRequest(message)
+            | GoogleApiError::UnknownError(message)
+            | GoogleApiError::Other { message, .. } => message.as_ref(),
+            GoogleApiError::ZeroResults => None,
+        }
+    }
+}
+
+impl fmt::Display for GoogleApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "Google Maps API returned {}: {}", self.status(), message),
+            None => write!(f, "Google Maps API returned {}", self.status()),
+        }
+    }
+}
+
+impl std::error::Error for GoogleApiError {}
+
+pub trait ApiResponse {
+    fn status(&self) -> &str;
+    fn error_message(&self) -> Option<&String>;
+}
+
+#[derive(Deserialize)]
+struct StatusResponse {
+    status: String,
+    #[serde(default)]
+    error_message: Option<String>,
+}
+
+// The error in a raw response body, if any. Bodies that aren't JSON, like static maps, have none.
+pub fn response_error(body: &[u8]) -> Option<GoogleApiError> {
+    let response: StatusResponse = serde_json::from_slice(body).ok()?;
+    GoogleApiError::from_status(&response.status, response.error_message)
+}
+
+// Parses a JSON response, turning a non-OK status into a GoogleApiError
+pub fn parse_response<T: DeserializeOwned + ApiResponse>(body: &[u8]) -> anyhow::Result<T> {
+    let response: T = serde_json::from_slice(body)?;
+    match GoogleApiError::from_status(response.status(), response.error_message().cloned()) {
+        Some(error) => Err(error.into()),
+        None => Ok(response),
+    }
+}
+
+// Whether a failed request is worth retrying. Errors that aren't from the API, like timeouts, are.
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<GoogleApiError>()
+        .is_none_or(GoogleApiError::is_retryable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GeocodeResponse;
+
+    #[test]
+    fn test_parse_ok() {
+        let body = br#"{"results": [], "status": "OK"}"#;
+        let response: GeocodeResponse = parse_response(body).unwrap();
+        assert!(response.results.is_empty());
+    }
+
+    #[test]
+    fn test_parse_error_status() {
+        let body = br#"{"results": [], "status": "REQUEST_DENIED", "error_message": "The provided API key is invalid."}"#;
+        let error = parse_response::<GeocodeResponse>(body).err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<GoogleApiError>(),
+            Some(&GoogleApiError::RequestDenied(Some(
+                "The provided API key is invalid.".to_string()
+            )))
+        );
+        assert!(!is_retryable(&error));
+    }
+
+    #[test]
+    fn test_retryable() {
+        let over_query_limit = GoogleApiError::from_status("OVER_QUERY_LIMIT", None).unwrap();
+        assert!(over_query_limit.is_retryable());
+        assert!(!GoogleApiError::ZeroResults.is_retryable());
+        assert!(is_retryable(&anyhow::anyhow!("connection reset")));
+    }
+
+    #[test]
+    fn test_response_error() {
+        assert_eq!(
+            response_error(br#"{"routes": [], "status": "ZERO_RESULTS"}"#),
+            Some(GoogleApiError::ZeroResults)
+        );
+        assert_eq!(response_error(b"\x89PNG"), None);
+    }
+}
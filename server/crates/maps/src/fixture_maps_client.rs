@@ -0,0 +1,222 @@
+use super::errors::{parse_response, response_error};
+use super::maps_client::{
+    directions_request, geocode_request, static_map_request, GoogleMapsClient,
+};
+use super::maps_provider::MapsProvider;
+use super::models::{DirectionsResponse, GeocodeResponse, TravelMode};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::fs;
+use std::path::PathBuf;
+
+enum FixtureMode {
+    Replay,
+    Record(GoogleMapsClient),
+}
+
+// Serves responses recorded from Google, one file per request, so code using the maps API
+// can run without network access or an API key
+pub struct FixtureMapsClient {
+    directory: PathBuf,
+    mode: FixtureMode,
+}
+
+impl FixtureMapsClient {
+    pub fn replay(directory: PathBuf) -> Self {
+        FixtureMapsClient {
+            directory,
+            mode: FixtureMode::Replay,
+        }
+    }
+
+    // Requests that have already been recorded are replayed rather than sent again
+    pub fn record(directory: PathBuf, client: GoogleMapsClient) -> Self {
+        FixtureMapsClient {
+            directory,
+            mode: FixtureMode::Record(client),
+        }
+    }
+
+    async fn response(&self, request: &str, extension: &str) -> Result<Vec<u8>> {
+        let path = self.directory.join(fixture_name(request, extension));
+        if path.exists() {
+            return fs::read(&path).with_context(|| format!("Failed to read {}", path.display()));
+        }
+        match &self.mode {
+            FixtureMode::Replay => Err(anyhow::anyhow!(
+                "No recorded response for {} in {}",
+                request,
+                path.display()
+            )),
+            FixtureMode::Record(client) => {
+                let response = client.fetch(request).await?;
+                // Failures like REQUEST_DENIED are returned but not kept, so they aren't replayed
+                if let Some(error) = response_error(&response).filter(|error| !error.is_answer()) {
+                    return Err(error.into());
+                }
+                fs::create_dir_all(&self.directory)?;
+                fs::write(&path, &response)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                Ok(response)
+            }
+        }
+    }
+}
+
+// The API name followed by a hash of the request, e.g. geocode-af63bd4c8601b7be.json.
+// FNV-1a is used as it is stable across builds, unlike the standard library's hasher.
+pub fn fixture_name(request: &str, extension: &str) -> String {
+    let hash = request.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    let api = request.split(['/', '?']).next().unwrap_or_default();
+    format!("{}-{:016x}.{}", api, hash, extension)
+}
+
+#[async_trait]
+impl MapsProvider for FixtureMapsClient {
+    async fn geocode(&self, address: &str) -> Result<GeocodeResponse> {
+        let response = self.response(&geocode_request(address), "json").await?;
+        parse_response(&response)
+    }
+
+    async fn get_directions(
+        &self,
+        origin_lat: f64,
+        origin_lng: f64,
+        destination_lat: f64,
+        destination_lng: f64,
+        travel_mode: &TravelMode,
+        departure_time: Option<i64>,
+    ) -> Result<DirectionsResponse> {
+        let request = directions_request(
+            origin_lat,
+            origin_lng,
+            destination_lat,
+            destination_lng,
+            travel_mode,
+            departure_time,
+        );
+        let response = self.response(&request, "json").await?;
+        parse_response(&response)
+    }
+
+    async fn fetch_combined_static_map_data(
+        &self,
+        paths: &[String],
+        colors: &[String],
+    ) -> Result<Vec<u8>> {
+        self.response(&static_map_request(paths, colors), "png")
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::GoogleApiError;
+    use crate::models::Location;
+    use crate::travel_time::TravelTimeProvider;
+    use std::path::Path;
+
+    fn client() -> FixtureMapsClient {
+        FixtureMapsClient::replay(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures"))
+    }
+
+    #[test]
+    fn test_fixture_name() {
+        let name = fixture_name(
+            &geocode_request("Adelaide Central Market, Adelaide SA, Australia"),
+            "json",
+        );
+        assert_eq!(name, "geocode-85b63376ec6255fa.json");
+    }
+
+    #[tokio::test]
+    async fn test_replay_geocode() {
+        let response = client()
+            .geocode("Adelaide Central Market, Adelaide SA, Australia")
+            .await
+            .unwrap();
+        let location = response.results[0].geometry.location;
+        assert_eq!((location.lat, location.lng), (-34.9298, 138.5966));
+    }
+
+    #[tokio::test]
+    async fn test_replay_directions() {
+        let response = client()
+            .get_directions(
+                -34.9298,
+                138.5966,
+                -34.9455,
+                138.5306,
+                &TravelMode::Driving,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.routes[0].legs[0].duration.value, 846);
+    }
+
+    #[tokio::test]
+    async fn test_driving_uses_traffic_at_departure_time() {
+        let provider: Box<dyn MapsProvider> = Box::new(client());
+        let origin = Location {
+            lat: -34.9298,
+            lng: 138.5966,
+        };
+        let airport = Location {
+            lat: -34.9455,
+            lng: 138.5306,
+        };
+        // 8am and 11am on a Tuesday in Adelaide
+        let mut durations = vec![];
+        for departure_time in [None, Some(1792445400), Some(1792456200)] {
+            let duration = provider
+                .travel_time(origin, airport, &TravelMode::Driving, departure_time)
+                .await
+                .unwrap();
+            durations.push(duration);
+        }
+        assert_eq!(durations, vec![Some(846), Some(1187), Some(902)]);
+    }
+
+    #[tokio::test]
+    async fn test_replay_zero_results() {
+        let provider: Box<dyn MapsProvider> = Box::new(client());
+        let origin = Location {
+            lat: -34.9298,
+            lng: 138.5966,
+        };
+        let island = Location {
+            lat: -35.7752,
+            lng: 137.2142,
+        };
+        let directions = provider
+            .get_directions(
+                origin.lat,
+                origin.lng,
+                island.lat,
+                island.lng,
+                &TravelMode::Driving,
+                None,
+            )
+            .await;
+        let error = directions.err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<GoogleApiError>(),
+            Some(&GoogleApiError::ZeroResults)
+        );
+        let duration = provider
+            .travel_time(origin, island, &TravelMode::Driving, None)
+            .await
+            .unwrap();
+        assert_eq!(duration, None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_missing() {
+        let response = client().geocode("Somewhere not recorded").await;
+        assert!(response.is_err());
+    }
+}
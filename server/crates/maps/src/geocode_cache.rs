@@ -0,0 +1,229 @@
+use super::maps_provider::MapsProvider;
+use super::models::Location;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use database::dynamodb_client_trait::IDynamoDbClient;
+use database::geocode_item::GeocodeItem;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Addresses rarely move, so results are kept for a month
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[async_trait]
+pub trait GeocodeCache: Send + Sync {
+    // Keys are normalised addresses. Expired entries are treated as missing.
+    async fn get(&self, key: &str) -> Result<Option<Location>>;
+    async fn put(&self, key: &str, location: Location) -> Result<()>;
+}
+
+// Lowercase with whitespace collapsed, so "1 King William St,  Adelaide" and
+// "1 king william st, adelaide" share an entry
+pub fn normalise_address(address: &str) -> String {
+    address
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .replace(" ,", ",")
+        .to_lowercase()
+}
+
+pub struct InMemoryGeocodeCache {
+    entries: Mutex<HashMap<String, (Location, Instant)>>,
+    ttl: Duration,
+}
+
+impl InMemoryGeocodeCache {
+    pub fn new(ttl: Duration) -> Self {
+        InMemoryGeocodeCache {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+}
+
+impl Default for InMemoryGeocodeCache {
+    fn default() -> Self {
+        InMemoryGeocodeCache::new(DEFAULT_TTL)
+    }
+}
+
+#[async_trait]
+impl GeocodeCache for InMemoryGeocodeCache {
+    async fn get(&self, key: &str) -> Result<Option<Location>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((location, expires_at)) if *expires_at > Instant::now() => Ok(Some(*location)),
+            Some(_) => {
+                entries.remove(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, key: &str, location: Location) -> Result<()> {
+        let expires_at = Instant::now() + self.ttl;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (location, expires_at));
+        Ok(())
+    }
+}
+
+pub struct DynamoDbGeocodeCache {
+    db_client: Arc<dyn IDynamoDbClient>,
+    ttl: Duration,
+}
+
+impl DynamoDbGeocodeCache {
+    pub fn new(db_client: Arc<dyn IDynamoDbClient>) -> Self {
+        DynamoDbGeocodeCache {
+            db_client,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+#[async_trait]
+impl GeocodeCache for DynamoDbGeocodeCache {
+    async fn get(&self, key: &str) -> Result<Option<Location>> {
+        let item = GeocodeItem::from_db(key, self.db_client.as_ref()).await?;
+        let location = item.filter(|item| !item.is_expired()).map(|item| Location {
+            lat: item.lat,
+            lng: item.lng,
+        });
+        Ok(location)
+    }
+
+    async fn put(&self, key: &str, location: Location) -> Result<()> {
+        let item = GeocodeItem {
+            address_key: key.to_string(),
+            lat: location.lat,
+            lng: location.lng,
+            time_to_live: Utc::now().timestamp() + self.ttl.as_secs() as i64,
+        };
+        self.db_client.write_single(item.save()?).await
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// Geocodes addresses through a cache, only calling the maps API on a miss
+pub struct Geocoder {
+    cache: Box<dyn GeocodeCache>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Geocoder {
+    pub fn new(cache: Box<dyn GeocodeCache>) -> Self {
+        Geocoder {
+            cache,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn locate(&self, maps_client: &dyn MapsProvider, address: &str) -> Result<Location> {
+        let key = normalise_address(address);
+        if let Some(location) = self.cache.get(&key).await? {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(location);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let location = maps_client
+            .geocode(address)
+            .await?
+            .results
+            .first()
+            .with_context(|| format!("No results found for {}", address))?
+            .geometry
+            .location;
+        self.cache.put(&key, location).await?;
+        Ok(location)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Geocoder {
+    fn default() -> Self {
+        Geocoder::new(Box::new(InMemoryGeocodeCache::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture_maps_client::FixtureMapsClient;
+    use std::path::Path;
+
+    const ADDRESS: &str = "Adelaide Central Market, Adelaide SA, Australia";
+
+    fn client() -> FixtureMapsClient {
+        FixtureMapsClient::replay(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures"))
+    }
+
+    #[test]
+    fn test_normalise_address() {
+        assert_eq!(
+            normalise_address("  105/62 Hurtle Square ,  ADELAIDE SA "),
+            "105/62 hurtle square, adelaide sa"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_locate_counts_hits_and_misses() {
+        let geocoder = Geocoder::default();
+        let first = geocoder.locate(&client(), ADDRESS).await.unwrap();
+        let second = geocoder
+            .locate(&client(), &ADDRESS.to_uppercase())
+            .await
+            .unwrap();
+        assert_eq!((first.lat, first.lng), (second.lat, second.lng));
+        assert_eq!(geocoder.stats(), CacheStats { hits: 1, misses: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_cached_address_is_not_geocoded() {
+        let cache = InMemoryGeocodeCache::default();
+        let location = Location { lat: 1.0, lng: 2.0 };
+        cache.put("somewhere not recorded", location).await.unwrap();
+        let geocoder = Geocoder::new(Box::new(cache));
+        // Replaying would fail, as there is no fixture for this address
+        let cached = geocoder
+            .locate(&client(), "Somewhere not recorded")
+            .await
+            .unwrap();
+        assert_eq!((cached.lat, cached.lng), (1.0, 2.0));
+    }
+
+    #[tokio::test]
+    async fn test_expired_entry_is_missing() {
+        let cache = InMemoryGeocodeCache::new(Duration::ZERO);
+        cache
+            .put("key", Location { lat: 1.0, lng: 2.0 })
+            .await
+            .unwrap();
+        assert!(cache.get("key").await.unwrap().is_none());
+    }
+}
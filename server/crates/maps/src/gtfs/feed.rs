@@ -0,0 +1,250 @@
+use crate::models::Location;
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate};
+use chrono_tz::Tz;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub struct Stop {
+    pub stop_id: String,
+    pub location: Location,
+}
+
+pub struct StopTime {
+    pub stop: usize,
+    // Seconds after midnight at the start of the service day, which can be past 24:00:00
+    pub arrival: u32,
+    pub departure: u32,
+}
+
+pub struct Trip {
+    pub service: usize,
+    pub stop_times: Vec<StopTime>,
+}
+
+struct Calendar {
+    // Monday first
+    weekdays: [bool; 7],
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+}
+
+// The parts of a GTFS feed needed for routing
+pub struct GtfsFeed {
+    pub timezone: Tz,
+    pub stops: Vec<Stop>,
+    pub trips: Vec<Trip>,
+    service_ids: Vec<String>,
+    calendars: HashMap<usize, Calendar>,
+    // From calendar_dates.txt, true where service is added and false where it is removed
+    exceptions: HashMap<(usize, NaiveDate), bool>,
+}
+
+#[derive(Deserialize)]
+struct AgencyRow {
+    agency_timezone: String,
+}
+
+#[derive(Deserialize)]
+struct StopRow {
+    stop_id: String,
+    stop_lat: Option<f64>,
+    stop_lon: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct TripRow {
+    service_id: String,
+    trip_id: String,
+}
+
+#[derive(Deserialize)]
+struct StopTimeRow {
+    trip_id: String,
+    arrival_time: String,
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: u32,
+}
+
+#[derive(Deserialize)]
+struct CalendarRow {
+    service_id: String,
+    monday: u8,
+    tuesday: u8,
+    wednesday: u8,
+    thursday: u8,
+    friday: u8,
+    saturday: u8,
+    sunday: u8,
+    start_date: String,
+    end_date: String,
+}
+
+#[derive(Deserialize)]
+struct CalendarDateRow {
+    service_id: String,
+    date: String,
+    exception_type: u8,
+}
+
+impl GtfsFeed {
+    // Loads an unzipped feed. Either calendar.txt or calendar_dates.txt may be left out.
+    pub fn from_directory(path: &Path) -> Result<Self> {
+        let agencies: Vec<AgencyRow> = read_rows(&path.join("agency.txt"))?;
+        let timezone = agencies
+            .first()
+            .context("No agency in agency.txt")?
+            .agency_timezone
+            .parse::<Tz>()
+            .map_err(|_| anyhow::anyhow!("Invalid agency_timezone"))?;
+
+        let mut stops = vec![];
+        let mut stop_indices = HashMap::new();
+        for row in read_rows::<StopRow>(&path.join("stops.txt"))? {
+            // Stations and entrances without a location can't be walked to
+            if let (Some(lat), Some(lng)) = (row.stop_lat, row.stop_lon) {
+                stop_indices.insert(row.stop_id.clone(), stops.len());
+                stops.push(Stop {
+                    stop_id: row.stop_id,
+                    location: Location { lat, lng },
+                });
+            }
+        }
+
+        let mut service_ids = vec![];
+        let mut service_indices: HashMap<String, usize> = HashMap::new();
+        let mut service_index = |service_id: String| {
+            *service_indices
+                .entry(service_id.clone())
+                .or_insert_with(|| {
+                    service_ids.push(service_id);
+                    service_ids.len() - 1
+                })
+        };
+
+        let mut trip_services = HashMap::new();
+        for row in read_rows::<TripRow>(&path.join("trips.txt"))? {
+            trip_services.insert(row.trip_id, service_index(row.service_id));
+        }
+
+        let mut calendars = HashMap::new();
+        let calendar_path = path.join("calendar.txt");
+        if calendar_path.exists() {
+            for row in read_rows::<CalendarRow>(&calendar_path)? {
+                let weekdays = [
+                    row.monday,
+                    row.tuesday,
+                    row.wednesday,
+                    row.thursday,
+                    row.friday,
+                    row.saturday,
+                    row.sunday,
+                ]
+                .map(|day| day == 1);
+                let calendar = Calendar {
+                    weekdays,
+                    start_date: parse_date(&row.start_date)?,
+                    end_date: parse_date(&row.end_date)?,
+                };
+                calendars.insert(service_index(row.service_id), calendar);
+            }
+        }
+        let mut exceptions = HashMap::new();
+        let calendar_dates_path = path.join("calendar_dates.txt");
+        if calendar_dates_path.exists() {
+            for row in read_rows::<CalendarDateRow>(&calendar_dates_path)? {
+                let date = parse_date(&row.date)?;
+                exceptions.insert(
+                    (service_index(row.service_id), date),
+                    row.exception_type == 1,
+                );
+            }
+        }
+
+        let mut trip_stop_times: HashMap<String, Vec<(u32, StopTime)>> = HashMap::new();
+        for row in read_rows::<StopTimeRow>(&path.join("stop_times.txt"))? {
+            let Some(stop) = stop_indices.get(&row.stop_id) else {
+                continue;
+            };
+            let stop_time = StopTime {
+                stop: *stop,
+                arrival: parse_time(&row.arrival_time)?,
+                departure: parse_time(&row.departure_time)?,
+            };
+            trip_stop_times
+                .entry(row.trip_id)
+                .or_default()
+                .push((row.stop_sequence, stop_time));
+        }
+        let mut trips = vec![];
+        for (trip_id, mut stop_times) in trip_stop_times {
+            let service = *trip_services
+                .get(&trip_id)
+                .with_context(|| format!("Trip {} is not in trips.txt", trip_id))?;
+            stop_times.sort_by_key(|(stop_sequence, _)| *stop_sequence);
+            trips.push(Trip {
+                service,
+                stop_times: stop_times
+                    .into_iter()
+                    .map(|(_, stop_time)| stop_time)
+                    .collect(),
+            });
+        }
+
+        Ok(GtfsFeed {
+            timezone,
+            stops,
+            trips,
+            service_ids,
+            calendars,
+            exceptions,
+        })
+    }
+
+    pub fn num_services(&self) -> usize {
+        self.service_ids.len()
+    }
+
+    pub fn is_active(&self, service: usize, date: NaiveDate) -> bool {
+        if let Some(active) = self.exceptions.get(&(service, date)) {
+            return *active;
+        }
+        self.calendars.get(&service).is_some_and(|calendar| {
+            calendar.start_date <= date
+                && date <= calendar.end_date
+                && calendar.weekdays[date.weekday().num_days_from_monday() as usize]
+        })
+    }
+}
+
+fn read_rows<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut rows = vec![];
+    for row in reader.deserialize() {
+        rows.push(row.with_context(|| format!("Invalid row in {}", path.display()))?);
+    }
+    Ok(rows)
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y%m%d").with_context(|| format!("Invalid date {}", date))
+}
+
+// HH:MM:SS, where the hours can be 24 or more for trips running past midnight
+fn parse_time(time: &str) -> Result<u32> {
+    let parts = time
+        .split(':')
+        .map(|part| part.parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid time {}", time))?;
+    match parts[..] {
+        [hours, minutes, seconds] => Ok(hours * 3600 + minutes * 60 + seconds),
+        _ => Err(anyhow::anyhow!("Invalid time {}", time)),
+    }
+}
@@ -0,0 +1,359 @@
+use super::feed::GtfsFeed;
+use crate::models::{Location, TravelMode};
+use crate::travel_time::TravelTimeProvider;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Timelike};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// Walking is measured in a straight line, so this is slower than a typical walking speed
+const WALKING_METERS_PER_SECOND: f64 = 1.0;
+const MAX_ACCESS_METERS: f64 = 1000.0;
+const MAX_TRANSFER_METERS: f64 = 250.0;
+const MAX_ROUNDS: usize = 6;
+const CACHED_SEARCHES: usize = 32;
+const UNREACHED: u32 = u32::MAX;
+
+// Trips that visit the same stops in the same order
+struct Pattern {
+    stops: Vec<usize>,
+    // (service, arrival and departure at each stop)
+    trips: Vec<(usize, Vec<(u32, u32)>)>,
+}
+
+type Arrivals = Arc<Vec<u32>>;
+type SearchCache = VecDeque<((u64, u64, i64), Arrivals)>;
+
+// Earliest arrival public transport routing over a GTFS feed, using RAPTOR
+// (Delling, Pajor and Werneck, "Round-Based Public Transit Routing").
+// Only trips on the service day of the departure are used, so trips running past midnight
+// from the day before are missed.
+pub struct GtfsRouter {
+    feed: GtfsFeed,
+    patterns: Vec<Pattern>,
+    // The patterns through each stop, with the stop's position in them
+    stop_patterns: Vec<Vec<(usize, usize)>>,
+    transfers: Vec<Vec<(usize, u32)>>,
+    cache: Mutex<SearchCache>,
+}
+
+impl GtfsRouter {
+    pub fn from_directory(path: &Path) -> Result<Self> {
+        let feed = GtfsFeed::from_directory(path)
+            .with_context(|| format!("Failed to load GTFS feed {}", path.display()))?;
+        Ok(Self::new(feed))
+    }
+
+    pub fn new(feed: GtfsFeed) -> Self {
+        let mut pattern_indices: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut patterns: Vec<Pattern> = vec![];
+        for trip in &feed.trips {
+            let stops: Vec<usize> = trip.stop_times.iter().map(|s| s.stop).collect();
+            let times = trip
+                .stop_times
+                .iter()
+                .map(|s| (s.arrival, s.departure))
+                .collect();
+            let index = *pattern_indices.entry(stops.clone()).or_insert_with(|| {
+                patterns.push(Pattern {
+                    stops,
+                    trips: vec![],
+                });
+                patterns.len() - 1
+            });
+            patterns[index].trips.push((trip.service, times));
+        }
+        let mut stop_patterns = vec![vec![]; feed.stops.len()];
+        for (pattern_index, pattern) in patterns.iter_mut().enumerate() {
+            pattern
+                .trips
+                .sort_by_key(|(_, times)| times.first().map(|(_, departure)| *departure));
+            for (position, stop) in pattern.stops.iter().enumerate() {
+                stop_patterns[*stop].push((pattern_index, position));
+            }
+        }
+        let transfers = Self::find_transfers(&feed);
+        GtfsRouter {
+            feed,
+            patterns,
+            stop_patterns,
+            transfers,
+            cache: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // Walking transfers between nearby stops, found by sweeping stops in order of latitude
+    fn find_transfers(feed: &GtfsFeed) -> Vec<Vec<(usize, u32)>> {
+        let mut transfers = vec![vec![]; feed.stops.len()];
+        let mut by_latitude: Vec<usize> = (0..feed.stops.len()).collect();
+        by_latitude.sort_by(|a, b| {
+            feed.stops[*a]
+                .location
+                .lat
+                .total_cmp(&feed.stops[*b].location.lat)
+        });
+        // A degree of latitude is at least 110km
+        let max_degrees = MAX_TRANSFER_METERS / 110_000.0;
+        for (i, from) in by_latitude.iter().enumerate() {
+            let from_location = feed.stops[*from].location;
+            for to in &by_latitude[i + 1..] {
+                let to_location = feed.stops[*to].location;
+                if to_location.lat - from_location.lat > max_degrees {
+                    break;
+                }
+                let meters = from_location.distance_to(&to_location);
+                if meters <= MAX_TRANSFER_METERS {
+                    let seconds = walking_seconds(meters);
+                    transfers[*from].push((*to, seconds));
+                    transfers[*to].push((*from, seconds));
+                }
+            }
+        }
+        transfers
+    }
+
+    // Seconds from origin to destination leaving at `departure_time` (seconds since the epoch),
+    // walking instead if it is quicker. None if neither can get there.
+    pub fn route(
+        &self,
+        origin: Location,
+        destination: Location,
+        departure_time: i64,
+    ) -> Result<Option<i32>> {
+        let departure = DateTime::from_timestamp(departure_time, 0)
+            .context("Invalid departure time")?
+            .with_timezone(&self.feed.timezone);
+        let start = departure.num_seconds_from_midnight();
+        let arrivals =
+            self.earliest_arrivals(origin, departure.date_naive(), start, departure_time)?;
+        let mut arrival = UNREACHED;
+        let direct_meters = origin.distance_to(&destination);
+        if direct_meters <= MAX_ACCESS_METERS {
+            arrival = start + walking_seconds(direct_meters);
+        }
+        for (stop, seconds) in self.nearby_stops(destination) {
+            if arrivals[stop] != UNREACHED {
+                arrival = arrival.min(arrivals[stop] + seconds);
+            }
+        }
+        let duration = match arrival {
+            UNREACHED => None,
+            arrival => Some((arrival - start) as i32),
+        };
+        Ok(duration)
+    }
+
+    fn nearby_stops(&self, location: Location) -> Vec<(usize, u32)> {
+        self.feed
+            .stops
+            .iter()
+            .enumerate()
+            .filter_map(|(index, stop)| {
+                let meters = location.distance_to(&stop.location);
+                (meters <= MAX_ACCESS_METERS).then_some((index, walking_seconds(meters)))
+            })
+            .collect()
+    }
+
+    fn earliest_arrivals(
+        &self,
+        origin: Location,
+        date: NaiveDate,
+        start: u32,
+        departure_time: i64,
+    ) -> Result<Arrivals> {
+        let key = (origin.lat.to_bits(), origin.lng.to_bits(), departure_time);
+        let cached = self
+            .lock_cache()?
+            .iter()
+            .find(|(cached_key, _)| *cached_key == key)
+            .map(|(_, arrivals)| arrivals.clone());
+        if let Some(arrivals) = cached {
+            return Ok(arrivals);
+        }
+        let arrivals = Arc::new(self.raptor(origin, date, start));
+        let mut cache = self.lock_cache()?;
+        if cache.len() >= CACHED_SEARCHES {
+            cache.pop_front();
+        }
+        cache.push_back((key, arrivals.clone()));
+        Ok(arrivals)
+    }
+
+    // Each round rides one more vehicle, then walks to nearby stops
+    fn raptor(&self, origin: Location, date: NaiveDate, start: u32) -> Vec<u32> {
+        let active: Vec<bool> = (0..self.feed.num_services())
+            .map(|service| self.feed.is_active(service, date))
+            .collect();
+        let mut arrivals = vec![UNREACHED; self.feed.stops.len()];
+        let mut marked = vec![];
+        for (stop, seconds) in self.nearby_stops(origin) {
+            arrivals[stop] = start + seconds;
+            marked.push(stop);
+        }
+        for _ in 0..MAX_ROUNDS {
+            if marked.is_empty() {
+                break;
+            }
+            // Only board each pattern at the first stop reached in the last round
+            let mut queue: HashMap<usize, usize> = HashMap::new();
+            for stop in marked.drain(..) {
+                for (pattern, position) in &self.stop_patterns[stop] {
+                    let earliest = queue.entry(*pattern).or_insert(*position);
+                    *earliest = (*earliest).min(*position);
+                }
+            }
+            let previous = arrivals.clone();
+            for (pattern_index, first_position) in queue {
+                let pattern = &self.patterns[pattern_index];
+                let mut trip: Option<&Vec<(u32, u32)>> = None;
+                for position in first_position..pattern.stops.len() {
+                    let stop = pattern.stops[position];
+                    if let Some(times) = trip {
+                        let arrival = times[position].0;
+                        if arrival < arrivals[stop] {
+                            arrivals[stop] = arrival;
+                            marked.push(stop);
+                        }
+                    }
+                    let ready = previous[stop];
+                    if ready == UNREACHED || trip.is_some_and(|times| times[position].1 <= ready) {
+                        continue;
+                    }
+                    // Catch the earliest trip leaving this stop after arriving in the last round
+                    trip = pattern
+                        .trips
+                        .iter()
+                        .filter(|(service, times)| active[*service] && times[position].1 >= ready)
+                        .map(|(_, times)| times)
+                        .min_by_key(|times| times[position].1)
+                        .or(trip);
+                }
+            }
+            let ridden = marked.clone();
+            for stop in ridden {
+                for (to, seconds) in &self.transfers[stop] {
+                    let arrival = arrivals[stop] + seconds;
+                    if arrival < arrivals[*to] {
+                        arrivals[*to] = arrival;
+                        marked.push(*to);
+                    }
+                }
+            }
+            marked.sort();
+            marked.dedup();
+        }
+        arrivals
+    }
+
+    fn lock_cache(&self) -> Result<MutexGuard<'_, SearchCache>> {
+        self.cache
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Search cache lock poisoned"))
+    }
+}
+
+fn walking_seconds(meters: f64) -> u32 {
+    (meters / WALKING_METERS_PER_SECOND).round() as u32
+}
+
+#[async_trait]
+impl TravelTimeProvider for GtfsRouter {
+    async fn travel_time(
+        &self,
+        origin: Location,
+        destination: Location,
+        travel_mode: &TravelMode,
+        departure_time: Option<i64>,
+    ) -> Result<Option<i32>> {
+        if *travel_mode != TravelMode::Transit {
+            return Ok(None);
+        }
+        let departure_time =
+            departure_time.context("Public transport routing needs a departure time")?;
+        self.route(origin, destination, departure_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use chrono_tz::Australia::Adelaide;
+
+    // Stops in the test feed
+    const HOME: Location = Location {
+        lat: -34.93,
+        lng: 138.60,
+    };
+    const OFFICE: Location = Location {
+        lat: -34.93,
+        lng: 138.64,
+    };
+    const HOSPITAL: Location = Location {
+        lat: -34.95,
+        lng: 138.62,
+    };
+
+    fn router() -> GtfsRouter {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/gtfs/test_feed");
+        GtfsRouter::from_directory(&path).unwrap()
+    }
+
+    fn departure(day: u32, month: u32, hour: u32, minute: u32) -> i64 {
+        Adelaide
+            .with_ymd_and_hms(2026, month, day, hour, minute, 0)
+            .unwrap()
+            .timestamp()
+    }
+
+    #[test]
+    fn test_direct() {
+        // Tuesday, catching the 08:00 arriving at 08:20
+        let duration = router().route(HOME, OFFICE, departure(20, 10, 7, 55));
+        assert_eq!(duration.unwrap(), Some(25 * 60));
+    }
+
+    #[test]
+    fn test_missed_trip() {
+        // The next trip leaves at 08:30 and arrives at 08:50
+        let duration = router().route(HOME, OFFICE, departure(20, 10, 8, 1));
+        assert_eq!(duration.unwrap(), Some(49 * 60));
+    }
+
+    #[test]
+    fn test_transfer() {
+        // Arrives at the shops at 08:10, walks 22m to the interchange and catches the 08:15
+        let duration = router().route(HOME, HOSPITAL, departure(20, 10, 7, 55));
+        assert_eq!(duration.unwrap(), Some(30 * 60));
+    }
+
+    #[test]
+    fn test_weekend() {
+        let router = router();
+        let saturday = router.route(HOME, OFFICE, departure(24, 10, 9, 55));
+        assert_eq!(saturday.unwrap(), Some(25 * 60));
+        // No services after 10:00
+        let sunday = router.route(HOME, OFFICE, departure(25, 10, 11, 0));
+        assert_eq!(sunday.unwrap(), None);
+    }
+
+    #[test]
+    fn test_removed_service() {
+        // Weekday services don't run on Christmas Day
+        let duration = router().route(HOME, OFFICE, departure(25, 12, 7, 55));
+        assert_eq!(duration.unwrap(), None);
+    }
+
+    #[test]
+    fn test_walking() {
+        let nearby = Location {
+            lat: HOME.lat,
+            lng: HOME.lng + 0.005,
+        };
+        let duration = router().route(HOME, nearby, departure(20, 10, 7, 55));
+        assert_eq!(duration.unwrap(), Some(456));
+    }
+}
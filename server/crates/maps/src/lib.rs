@@ -1,2 +1,9 @@
+pub mod errors;
+pub mod fixture_maps_client;
+pub mod geocode_cache;
+pub mod gtfs;
 pub mod maps_client;
+pub mod maps_provider;
 pub mod models;
+pub mod osm_router;
+pub mod travel_time;
@@ -1,14 +1,29 @@
+use super::errors::{parse_response, GoogleApiError};
+use super::maps_provider::MapsProvider;
 use super::models::{DirectionsResponse, GeocodeResponse, TravelMode};
-use anyhow::Result;
-use reqwest::Client;
-use std::fs::File;
-use std::io::{copy, Cursor};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use std::fmt;
+use url::form_urlencoded::Serializer;
+use url::Url;
+
+const BASE_URL: &str = "https://maps.googleapis.com/maps/api";
 
 pub struct GoogleMapsClient {
     api_key: String,
     client: Client,
 }
 
+// The key is left out so it can't end up in logs
+impl fmt::Debug for GoogleMapsClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GoogleMapsClient")
+            .field("api_key", &"<redacted>")
+            .finish()
+    }
+}
+
 impl GoogleMapsClient {
     pub fn new(api_key: String) -> Self {
         GoogleMapsClient {
@@ -17,70 +32,135 @@ impl GoogleMapsClient {
         }
     }
 
-    pub async fn geocode(&self, address: &str) -> Result<GeocodeResponse> {
-        let url = format!(
-            "https://maps.googleapis.com/maps/api/geocode/json?address={}&key={}",
-            address, self.api_key
-        );
+    // Requests are paths relative to the Maps API, without the key. Nothing is retried here,
+    // so callers that retry can do it behind their own rate limiting. A 429 is returned as
+    // OverQueryLimit, and error statuses in the body are left to parse_response.
+    pub async fn fetch(&self, request: &str) -> Result<Vec<u8>> {
+        let mut url = Url::parse(&format!("{}/{}", BASE_URL, request))
+            .with_context(|| format!("Invalid request {}", request))?;
+        url.query_pairs_mut().append_pair("key", &self.api_key);
+        // reqwest errors include the URL, and with it the key
         let response = self
             .client
-            .get(&url)
+            .get(url)
             .send()
-            .await?
-            .json::<GeocodeResponse>()
-            .await?;
-        Ok(response)
+            .await
+            .map_err(|error| error.without_url())
+            .with_context(|| format!("Failed to fetch {}", request))?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(GoogleApiError::OverQueryLimit(None).into());
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|error| error.without_url())
+            .with_context(|| format!("Failed to read {}", request))?
+            .to_vec();
+        Ok(bytes)
+    }
+}
+
+pub fn geocode_request(address: &str) -> String {
+    let query = Serializer::new(String::new())
+        .append_pair("address", address)
+        .finish();
+    format!("geocode/json?{}", query)
+}
+
+// `departure_time` is seconds since the epoch, and must not be in the past
+pub fn directions_request(
+    origin_lat: f64,
+    origin_lng: f64,
+    destination_lat: f64,
+    destination_lng: f64,
+    travel_mode: &TravelMode,
+    departure_time: Option<i64>,
+) -> String {
+    let mut query = Serializer::new(String::new());
+    query
+        .append_pair("origin", &format!("{},{}", origin_lat, origin_lng))
+        .append_pair(
+            "destination",
+            &format!("{},{}", destination_lat, destination_lng),
+        )
+        .append_pair("mode", travel_mode.as_str());
+    if let Some(departure_time) = departure_time {
+        query.append_pair("departure_time", &departure_time.to_string());
     }
+    format!("directions/json?{}", query.finish())
+}
 
-    pub async fn get_directions(
+pub fn static_map_request(paths: &[String], colors: &[String]) -> String {
+    let mut query = Serializer::new(String::new());
+    query.append_pair("size", "600x300");
+    for (path, color) in paths.iter().zip(colors.iter()) {
+        query.append_pair("path", &format!("weight:5|color:{}|enc:{}", color, path));
+    }
+    format!("staticmap?{}", query.finish())
+}
+
+#[async_trait]
+impl MapsProvider for GoogleMapsClient {
+    async fn geocode(&self, address: &str) -> Result<GeocodeResponse> {
+        let response = self.fetch(&geocode_request(address)).await?;
+        parse_response(&response)
+    }
+
+    async fn get_directions(
         &self,
         origin_lat: f64,
         origin_lng: f64,
         destination_lat: f64,
         destination_lng: f64,
         travel_mode: &TravelMode,
+        departure_time: Option<i64>,
     ) -> Result<DirectionsResponse> {
-        let url = format!(
-            "https://maps.googleapis.com/maps/api/directions/json?origin={},{}&destination={},{}&mode={}&key={}",
-            origin_lat, origin_lng, destination_lat, destination_lng, travel_mode.as_str(), self.api_key
+        let request = directions_request(
+            origin_lat,
+            origin_lng,
+            destination_lat,
+            destination_lng,
+            travel_mode,
+            departure_time,
         );
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await?
-            .json::<DirectionsResponse>()
-            .await?;
-        Ok(response)
+        let response = self.fetch(&request).await?;
+        parse_response(&response)
     }
 
-    pub async fn fetch_combined_static_map_data(
+    async fn fetch_combined_static_map_data(
         &self,
         paths: &[String],
         colors: &[String],
     ) -> Result<Vec<u8>> {
-        let path_param: String = paths
-            .iter()
-            .zip(colors.iter())
-            .map(|(path, color)| format!("path=weight:5|color:{}|enc:{}", color, path))
-            .collect::<Vec<String>>()
-            .join("&");
-        let map_url = format!(
-            "https://maps.googleapis.com/maps/api/staticmap?size=600x300&{}&key={}",
-            path_param, self.api_key
-        );
-        let response = self.client.get(map_url).send().await?;
-        let bytes = response.bytes().await?;
-        Ok(bytes.to_vec())
+        self.fetch(&static_map_request(paths, colors)).await
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn save_map_to_file(&self, data: &[u8], file_path: &str) -> Result<()> {
-        let mut file = File::create(file_path)?;
-        let mut cursor = Cursor::new(data);
-        copy(&mut cursor, &mut file)?;
+    #[test]
+    fn test_geocode_request_is_encoded() {
+        let request = geocode_request("105/62 Hurtle Square, Adelaide SA 5000 & more");
+        assert_eq!(
+            request,
+            "geocode/json?address=105%2F62+Hurtle+Square%2C+Adelaide+SA+5000+%26+more"
+        );
+    }
 
-        println!("Map image saved as {}", file_path);
+    #[test]
+    fn test_static_map_request_is_encoded() {
+        let request = static_map_request(&["a?b@c".to_string()], &["0xff0000ff".to_string()]);
+        assert_eq!(
+            request,
+            "staticmap?size=600x300&path=weight%3A5%7Ccolor%3A0xff0000ff%7Cenc%3Aa%3Fb%40c"
+        );
+    }
 
-        Ok(())
+    #[test]
+    fn test_debug_redacts_key() {
+        let client = GoogleMapsClient::new("secret-key".to_string());
+        assert!(!format!("{:?}", client).contains("secret-key"));
     }
 }
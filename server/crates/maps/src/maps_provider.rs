@@ -0,0 +1,43 @@
+use super::fixture_maps_client::FixtureMapsClient;
+use super::maps_client::GoogleMapsClient;
+use super::models::{DirectionsResponse, GeocodeResponse, TravelMode};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::env;
+use std::path::PathBuf;
+
+#[async_trait]
+pub trait MapsProvider: Send + Sync {
+    async fn geocode(&self, address: &str) -> Result<GeocodeResponse>;
+    async fn get_directions(
+        &self,
+        origin_lat: f64,
+        origin_lng: f64,
+        destination_lat: f64,
+        destination_lng: f64,
+        travel_mode: &TravelMode,
+        departure_time: Option<i64>,
+    ) -> Result<DirectionsResponse>;
+    // A PNG of the encoded polylines, each drawn in the matching colour
+    async fn fetch_combined_static_map_data(
+        &self,
+        paths: &[String],
+        colors: &[String],
+    ) -> Result<Vec<u8>>;
+}
+
+// Calls Google unless MAPS_FIXTURES_DIR is set, in which case recorded responses are replayed.
+// With MAPS_RECORD=true as well, responses from Google are saved there for later.
+pub fn maps_provider_from_env() -> Result<Box<dyn MapsProvider>> {
+    let api_key = || env::var("GOOGLE_MAPS_API_KEY").context("GOOGLE_MAPS_API_KEY must be set");
+    let record = env::var("MAPS_RECORD").is_ok_and(|record| record == "true");
+    let provider: Box<dyn MapsProvider> = match env::var("MAPS_FIXTURES_DIR") {
+        Ok(directory) if record => Box::new(FixtureMapsClient::record(
+            PathBuf::from(directory),
+            GoogleMapsClient::new(api_key()?),
+        )),
+        Ok(directory) => Box::new(FixtureMapsClient::replay(PathBuf::from(directory))),
+        Err(_) => Box::new(GoogleMapsClient::new(api_key()?)),
+    };
+    Ok(provider)
+}
@@ -1,8 +1,22 @@
-use serde::Deserialize;
+use super::errors::ApiResponse;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 pub struct GeocodeResponse {
     pub results: Vec<GeocodeResult>,
+    pub status: String,
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+impl ApiResponse for GeocodeResponse {
+    fn status(&self) -> &str {
+        &self.status
+    }
+
+    fn error_message(&self) -> Option<&String> {
+        self.error_message.as_ref()
+    }
 }
 
 #[derive(Deserialize)]
@@ -15,15 +29,43 @@ pub struct Geometry {
     pub location: Location,
 }
 
-#[derive(Deserialize, Copy, Clone)]
+#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
 pub struct Location {
     pub lat: f64,
     pub lng: f64,
 }
 
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+impl Location {
+    // Haversine distance in meters
+    pub fn distance_to(&self, other: &Location) -> f64 {
+        let d_lat = (other.lat - self.lat).to_radians();
+        let d_lng = (other.lng - self.lng).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2)
+            + self.lat.to_radians().cos()
+                * other.lat.to_radians().cos()
+                * (d_lng / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct DirectionsResponse {
     pub routes: Vec<Route>,
+    pub status: String,
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+impl ApiResponse for DirectionsResponse {
+    fn status(&self) -> &str {
+        &self.status
+    }
+
+    fn error_message(&self) -> Option<&String> {
+        self.error_message.as_ref()
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -40,6 +82,9 @@ pub struct OverviewPolyline {
 #[derive(Deserialize, Debug)]
 pub struct Leg {
     pub duration: Duration,
+    // Only given for driving with a departure time, and is the only duration that uses traffic
+    pub duration_in_traffic: Option<Duration>,
+    pub distance: Distance,
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,6 +93,15 @@ pub struct Duration {
     pub value: i32,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct Distance {
+    pub text: String,
+    // Meters
+    pub value: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TravelMode {
     Driving,
     Walking,
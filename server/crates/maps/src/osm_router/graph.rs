@@ -0,0 +1,142 @@
+use super::parser::OsmData;
+use super::speed_profile::SpeedProfile;
+use crate::models::Location;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+const GRID_CELL_DEGREES: f64 = 0.01;
+// Locations further than this from the network aren't routed
+pub const MAX_SNAP_DISTANCE_METERS: f64 = 1000.0;
+pub const UNREACHABLE: u32 = u32::MAX;
+
+struct Edge {
+    to: u32,
+    millis: u32,
+}
+
+// The road network for a single travel mode, with costs in milliseconds
+pub struct Graph {
+    locations: Vec<Location>,
+    edges: Vec<Vec<Edge>>,
+    grid: HashMap<(i32, i32), Vec<u32>>,
+}
+
+impl Graph {
+    pub fn build(data: &OsmData, profile: &SpeedProfile) -> Self {
+        let mut graph = Graph {
+            locations: vec![],
+            edges: vec![],
+            grid: HashMap::new(),
+        };
+        let mut node_indices: HashMap<i64, u32> = HashMap::new();
+        for way in &data.ways {
+            let Some(speed) = profile.speed(&way.tags) else {
+                continue;
+            };
+            let (forward, backward) = profile.directions(&way.tags);
+            let mut previous: Option<u32> = None;
+            for node_id in &way.node_ids {
+                let Some(&(lat, lng)) = data.nodes.get(node_id) else {
+                    // Ways can reference nodes outside a clipped extract
+                    previous = None;
+                    continue;
+                };
+                let index = *node_indices
+                    .entry(*node_id)
+                    .or_insert_with(|| graph.add_node(Location { lat, lng }));
+                if let Some(previous) = previous {
+                    let meters = graph.locations[previous as usize]
+                        .distance_to(&graph.locations[index as usize]);
+                    let millis = (meters / (speed / 3.6) * 1000.0).round() as u32;
+                    if forward {
+                        graph.edges[previous as usize].push(Edge { to: index, millis });
+                    }
+                    if backward {
+                        graph.edges[index as usize].push(Edge {
+                            to: previous,
+                            millis,
+                        });
+                    }
+                }
+                previous = Some(index);
+            }
+        }
+        graph
+    }
+
+    fn add_node(&mut self, location: Location) -> u32 {
+        let index = self.locations.len() as u32;
+        self.locations.push(location);
+        self.edges.push(vec![]);
+        self.grid
+            .entry(grid_cell(location))
+            .or_default()
+            .push(index);
+        index
+    }
+
+    // Searches outwards ring by ring until no closer node can be found
+    pub fn nearest_node(&self, location: Location) -> Option<u32> {
+        // Cells are narrowest east to west
+        let cell_meters = Location {
+            lat: location.lat,
+            lng: location.lng + GRID_CELL_DEGREES,
+        }
+        .distance_to(&location);
+        let max_ring = (MAX_SNAP_DISTANCE_METERS / cell_meters).ceil() as i32 + 1;
+        let (row, column) = grid_cell(location);
+        let mut nearest: Option<(u32, f64)> = None;
+        for ring in 0..=max_ring {
+            for dr in -ring..=ring {
+                for dc in -ring..=ring {
+                    if dr.abs() != ring && dc.abs() != ring {
+                        continue;
+                    }
+                    let Some(indices) = self.grid.get(&(row + dr, column + dc)) else {
+                        continue;
+                    };
+                    for index in indices {
+                        let meters = location.distance_to(&self.locations[*index as usize]);
+                        if nearest.is_none_or(|(_, nearest_meters)| meters < nearest_meters) {
+                            nearest = Some((*index, meters));
+                        }
+                    }
+                }
+            }
+            if nearest.is_some_and(|(_, meters)| meters <= ring as f64 * cell_meters) {
+                break;
+            }
+        }
+        nearest
+            .filter(|(_, meters)| *meters <= MAX_SNAP_DISTANCE_METERS)
+            .map(|(index, _)| index)
+    }
+
+    // Dijkstra from the source to every node, UNREACHABLE where there is no route
+    pub fn shortest_times(&self, source: u32) -> Vec<u32> {
+        let mut times = vec![UNREACHABLE; self.locations.len()];
+        let mut queue = BinaryHeap::new();
+        times[source as usize] = 0;
+        queue.push(Reverse((0, source)));
+        while let Some(Reverse((time, node))) = queue.pop() {
+            if time > times[node as usize] {
+                continue;
+            }
+            for edge in &self.edges[node as usize] {
+                let next_time = time.saturating_add(edge.millis);
+                if next_time < times[edge.to as usize] {
+                    times[edge.to as usize] = next_time;
+                    queue.push(Reverse((next_time, edge.to)));
+                }
+            }
+        }
+        times
+    }
+}
+
+fn grid_cell(location: Location) -> (i32, i32) {
+    (
+        (location.lat / GRID_CELL_DEGREES).floor() as i32,
+        (location.lng / GRID_CELL_DEGREES).floor() as i32,
+    )
+}
@@ -0,0 +1,7 @@
+mod graph;
+#[allow(clippy::module_inception)]
+mod osm_router;
+mod parser;
+pub mod speed_profile;
+
+pub use osm_router::*;
@@ -0,0 +1,213 @@
+use super::graph::{Graph, UNREACHABLE};
+use super::parser::parse_osm_xml;
+use super::speed_profile::SpeedProfile;
+use crate::models::{Location, TravelMode};
+use crate::travel_time::TravelTimeProvider;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// Shortest path trees are kept for the most recent origins, since batch jobs route
+// from the same origin to many destinations in a row
+const CACHED_TREES: usize = 32;
+
+type ShortestTimes = Arc<Vec<u32>>;
+type RouteCache = VecDeque<((TravelMode, u32), ShortestTimes)>;
+
+// Routes walking, cycling and driving over an OpenStreetMap extract, with no network access.
+// Times are for free-flowing traffic and don't include getting to and from the nearest road.
+pub struct OsmRouter {
+    graphs: HashMap<TravelMode, Graph>,
+    cache: Mutex<RouteCache>,
+}
+
+impl OsmRouter {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open OSM extract {}", path.display()))?;
+        Self::from_reader(BufReader::new(file))
+    }
+
+    pub fn from_reader(reader: impl BufRead) -> Result<Self> {
+        let data = parse_osm_xml(reader)?;
+        let graphs = [
+            TravelMode::Walking,
+            TravelMode::Bicycling,
+            TravelMode::Driving,
+        ]
+        .into_iter()
+        .filter_map(|mode| {
+            Some((
+                mode,
+                Graph::build(&data, &SpeedProfile::for_travel_mode(&mode)?),
+            ))
+        })
+        .collect();
+        Ok(OsmRouter {
+            graphs,
+            cache: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    // Seconds from origin to destination, or None if either is off the network or there is no route
+    pub fn route(
+        &self,
+        origin: Location,
+        destination: Location,
+        travel_mode: &TravelMode,
+    ) -> Result<Option<i32>> {
+        let Some(graph) = self.graphs.get(travel_mode) else {
+            return Ok(None);
+        };
+        let (Some(source), Some(target)) =
+            (graph.nearest_node(origin), graph.nearest_node(destination))
+        else {
+            return Ok(None);
+        };
+        let times = self.shortest_times(graph, *travel_mode, source)?;
+        let duration = match times[target as usize] {
+            UNREACHABLE => None,
+            millis => Some(((millis as f64) / 1000.0).round() as i32),
+        };
+        Ok(duration)
+    }
+
+    fn shortest_times(
+        &self,
+        graph: &Graph,
+        travel_mode: TravelMode,
+        source: u32,
+    ) -> Result<ShortestTimes> {
+        let key = (travel_mode, source);
+        let cached = self
+            .lock_cache()?
+            .iter()
+            .find(|(cached_key, _)| *cached_key == key)
+            .map(|(_, times)| times.clone());
+        if let Some(times) = cached {
+            return Ok(times);
+        }
+        let times = Arc::new(graph.shortest_times(source));
+        let mut cache = self.lock_cache()?;
+        if cache.len() >= CACHED_TREES {
+            cache.pop_front();
+        }
+        cache.push_back((key, times.clone()));
+        Ok(times)
+    }
+
+    fn lock_cache(&self) -> Result<MutexGuard<'_, RouteCache>> {
+        self.cache
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Route cache lock poisoned"))
+    }
+}
+
+#[async_trait]
+impl TravelTimeProvider for OsmRouter {
+    async fn travel_time(
+        &self,
+        origin: Location,
+        destination: Location,
+        travel_mode: &TravelMode,
+        _departure_time: Option<i64>,
+    ) -> Result<Option<i32>> {
+        self.route(origin, destination, travel_mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTRACT: &str = include_str!("test_extract.osm");
+
+    // Nodes in the test extract, 0.01 degrees of longitude (about 912m) apart
+    const HOME: Location = Location {
+        lat: -34.93,
+        lng: 138.60,
+    };
+    const SHOPS: Location = Location {
+        lat: -34.93,
+        lng: 138.61,
+    };
+    const OFFICE: Location = Location {
+        lat: -34.93,
+        lng: 138.62,
+    };
+    const AIRPORT: Location = Location {
+        lat: -34.93,
+        lng: 138.64,
+    };
+    const PARK: Location = Location {
+        lat: -34.92,
+        lng: 138.60,
+    };
+    const ISLAND: Location = Location {
+        lat: -34.93,
+        lng: 138.70,
+    };
+
+    fn router() -> OsmRouter {
+        OsmRouter::from_reader(EXTRACT.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_walking() {
+        // 1824m at 5 km/h
+        let duration = router().route(HOME, OFFICE, &TravelMode::Walking).unwrap();
+        assert_eq!(duration, Some(1313));
+    }
+
+    #[test]
+    fn test_driving_maxspeed() {
+        // 912m at 40 km/h then 912m at the 60 km/h limit
+        let duration = router().route(HOME, OFFICE, &TravelMode::Driving).unwrap();
+        assert_eq!(duration, Some(82 + 55));
+    }
+
+    #[test]
+    fn test_oneway() {
+        let router = router();
+        let there = router.route(OFFICE, AIRPORT, &TravelMode::Driving).unwrap();
+        let back = router.route(AIRPORT, OFFICE, &TravelMode::Driving).unwrap();
+        assert!(there.is_some());
+        assert_eq!(back, None);
+    }
+
+    #[test]
+    fn test_footway() {
+        let router = router();
+        let walking = router.route(HOME, PARK, &TravelMode::Walking).unwrap();
+        let driving = router.route(HOME, PARK, &TravelMode::Driving).unwrap();
+        let cycling = router.route(HOME, PARK, &TravelMode::Bicycling).unwrap();
+        assert!(walking.is_some());
+        assert_eq!(driving, None);
+        // The footway allows bicycles
+        assert!(cycling.is_some());
+    }
+
+    #[test]
+    fn test_disconnected() {
+        let duration = router().route(HOME, ISLAND, &TravelMode::Walking).unwrap();
+        assert_eq!(duration, None);
+    }
+
+    #[test]
+    fn test_same_location() {
+        let duration = router()
+            .route(SHOPS, SHOPS, &TravelMode::Bicycling)
+            .unwrap();
+        assert_eq!(duration, Some(0));
+    }
+
+    #[test]
+    fn test_transit() {
+        let duration = router().route(HOME, OFFICE, &TravelMode::Transit).unwrap();
+        assert_eq!(duration, None);
+    }
+}
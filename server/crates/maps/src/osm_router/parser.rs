@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+pub struct OsmWay {
+    pub node_ids: Vec<i64>,
+    pub tags: HashMap<String, String>,
+}
+
+pub struct OsmData {
+    pub nodes: HashMap<i64, (f64, f64)>,
+    // Only ways tagged as highways are kept
+    pub ways: Vec<OsmWay>,
+}
+
+// Reads an OpenStreetMap XML extract. PBF extracts can be converted with
+// `osmium cat extract.osm.pbf -o extract.osm`.
+pub fn parse_osm_xml(reader: impl BufRead) -> Result<OsmData> {
+    let mut reader = Reader::from_reader(reader);
+    let mut buffer = vec![];
+    let mut nodes = HashMap::new();
+    let mut ways = vec![];
+    let mut current_way: Option<OsmWay> = None;
+    loop {
+        let event = reader.read_event_into(&mut buffer)?;
+        match &event {
+            Event::Start(element) | Event::Empty(element) => match element.name().as_ref() {
+                b"node" => {
+                    let id = attribute(element, "id")?.parse::<i64>()?;
+                    let lat = attribute(element, "lat")?.parse::<f64>()?;
+                    let lng = attribute(element, "lon")?.parse::<f64>()?;
+                    nodes.insert(id, (lat, lng));
+                }
+                b"way" if matches!(event, Event::Start(_)) => {
+                    current_way = Some(OsmWay {
+                        node_ids: vec![],
+                        tags: HashMap::new(),
+                    });
+                }
+                b"nd" => {
+                    if let Some(way) = current_way.as_mut() {
+                        way.node_ids
+                            .push(attribute(element, "ref")?.parse::<i64>()?);
+                    }
+                }
+                b"tag" => {
+                    if let Some(way) = current_way.as_mut() {
+                        way.tags
+                            .insert(attribute(element, "k")?, attribute(element, "v")?);
+                    }
+                }
+                _ => {}
+            },
+            Event::End(element) if element.name().as_ref() == b"way" => {
+                if let Some(way) = current_way.take() {
+                    if way.tags.contains_key("highway") {
+                        ways.push(way);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buffer.clear();
+    }
+    Ok(OsmData { nodes, ways })
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<String> {
+    let attribute = element
+        .try_get_attribute(name)?
+        .with_context(|| format!("Missing attribute {}", name))?;
+    Ok(attribute.unescape_value()?.into_owned())
+}
@@ -0,0 +1,174 @@
+use crate::models::TravelMode;
+use std::collections::HashMap;
+
+const BLOCKED_ACCESS: [&str; 3] = ["no", "private", "dismount"];
+
+pub struct SpeedProfile {
+    // km/h on each kind of highway the mode can use
+    pub highway_speeds: HashMap<String, f64>,
+    // km/h on other highways where access is explicitly allowed, e.g. a footway with bicycle=yes
+    pub permitted_speed: f64,
+    // Checked in order, so later and more specific tags override earlier ones
+    pub access_tags: Vec<String>,
+    pub oneway_tags: Vec<String>,
+    pub use_maxspeed: bool,
+}
+
+impl SpeedProfile {
+    pub fn for_travel_mode(travel_mode: &TravelMode) -> Option<Self> {
+        match travel_mode {
+            TravelMode::Walking => Some(Self::walking()),
+            TravelMode::Bicycling => Some(Self::cycling()),
+            TravelMode::Driving => Some(Self::driving()),
+            // Needs timetables rather than a road network
+            TravelMode::Transit => None,
+        }
+    }
+
+    pub fn walking() -> Self {
+        SpeedProfile {
+            highway_speeds: speeds(&[
+                ("footway", 5.0),
+                ("pedestrian", 5.0),
+                ("path", 5.0),
+                ("steps", 3.0),
+                ("track", 5.0),
+                ("cycleway", 5.0),
+                ("living_street", 5.0),
+                ("residential", 5.0),
+                ("service", 5.0),
+                ("unclassified", 5.0),
+                ("tertiary", 5.0),
+                ("tertiary_link", 5.0),
+                ("secondary", 5.0),
+                ("secondary_link", 5.0),
+                ("primary", 5.0),
+                ("primary_link", 5.0),
+                ("trunk", 5.0),
+                ("trunk_link", 5.0),
+            ]),
+            permitted_speed: 5.0,
+            access_tags: tags(&["access", "foot"]),
+            oneway_tags: vec![],
+            use_maxspeed: false,
+        }
+    }
+
+    pub fn cycling() -> Self {
+        SpeedProfile {
+            highway_speeds: speeds(&[
+                ("cycleway", 18.0),
+                ("path", 14.0),
+                ("track", 12.0),
+                ("living_street", 10.0),
+                ("residential", 16.0),
+                ("service", 14.0),
+                ("unclassified", 16.0),
+                ("tertiary", 16.0),
+                ("tertiary_link", 16.0),
+                ("secondary", 16.0),
+                ("secondary_link", 16.0),
+                ("primary", 16.0),
+                ("primary_link", 16.0),
+                ("trunk", 16.0),
+                ("trunk_link", 16.0),
+            ]),
+            permitted_speed: 12.0,
+            access_tags: tags(&["access", "vehicle", "bicycle"]),
+            oneway_tags: tags(&["oneway", "oneway:bicycle"]),
+            use_maxspeed: false,
+        }
+    }
+
+    pub fn driving() -> Self {
+        SpeedProfile {
+            highway_speeds: speeds(&[
+                ("motorway", 100.0),
+                ("motorway_link", 60.0),
+                ("trunk", 80.0),
+                ("trunk_link", 50.0),
+                ("primary", 60.0),
+                ("primary_link", 50.0),
+                ("secondary", 50.0),
+                ("secondary_link", 40.0),
+                ("tertiary", 50.0),
+                ("tertiary_link", 40.0),
+                ("unclassified", 40.0),
+                ("residential", 40.0),
+                ("living_street", 10.0),
+                ("service", 20.0),
+            ]),
+            permitted_speed: 20.0,
+            access_tags: tags(&["access", "vehicle", "motor_vehicle", "motorcar"]),
+            oneway_tags: tags(&["oneway"]),
+            use_maxspeed: true,
+        }
+    }
+
+    // km/h along the way, or None if the mode can't use it
+    pub fn speed(&self, way_tags: &HashMap<String, String>) -> Option<f64> {
+        let highway = way_tags.get("highway")?;
+        let mut speed = self.highway_speeds.get(highway).copied();
+        for access_tag in &self.access_tags {
+            match way_tags.get(access_tag).map(String::as_str) {
+                Some(access) if BLOCKED_ACCESS.contains(&access) => speed = None,
+                Some(_) => speed = speed.or(Some(self.permitted_speed)),
+                None => {}
+            }
+        }
+        let speed = speed?;
+        if !self.use_maxspeed {
+            return Some(speed);
+        }
+        let maxspeed = way_tags
+            .get("maxspeed")
+            .and_then(|maxspeed| parse_maxspeed(maxspeed));
+        Some(maxspeed.unwrap_or(speed))
+    }
+
+    // Whether the way can be travelled in the direction of its nodes, and against it
+    pub fn directions(&self, way_tags: &HashMap<String, String>) -> (bool, bool) {
+        if self.oneway_tags.is_empty() {
+            return (true, true);
+        }
+        let implied_oneway = way_tags.get("junction").is_some_and(|j| j == "roundabout")
+            || way_tags.get("highway").is_some_and(|h| h == "motorway");
+        let mut directions = match implied_oneway {
+            true => (true, false),
+            false => (true, true),
+        };
+        for oneway_tag in &self.oneway_tags {
+            match way_tags.get(oneway_tag).map(String::as_str) {
+                Some("yes" | "true" | "1") => directions = (true, false),
+                Some("-1" | "reverse") => directions = (false, true),
+                Some("no" | "false" | "0") => directions = (true, true),
+                _ => {}
+            }
+        }
+        directions
+    }
+}
+
+fn speeds(highway_speeds: &[(&str, f64)]) -> HashMap<String, f64> {
+    highway_speeds
+        .iter()
+        .map(|(highway, speed)| (highway.to_string(), *speed))
+        .collect()
+}
+
+fn tags(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+// Handles "60" and "40 mph", other values like "signals" are ignored
+fn parse_maxspeed(maxspeed: &str) -> Option<f64> {
+    let (value, mph) = match maxspeed.strip_suffix("mph") {
+        Some(value) => (value.trim(), true),
+        None => (maxspeed.trim(), false),
+    };
+    let speed = value.parse::<f64>().ok().filter(|speed| *speed > 0.0)?;
+    match mph {
+        true => Some(speed * 1.609),
+        false => Some(speed),
+    }
+}
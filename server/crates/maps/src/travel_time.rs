@@ -0,0 +1,57 @@
+use super::errors::GoogleApiError;
+use super::maps_provider::MapsProvider;
+use super::models::{Leg, Location, TravelMode};
+use anyhow::Result;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait TravelTimeProvider: Send + Sync {
+    // Seconds to travel from origin to destination, or None if there is no route.
+    // `departure_time` is seconds since the epoch, and is ignored by providers without timetables or traffic.
+    async fn travel_time(
+        &self,
+        origin: Location,
+        destination: Location,
+        travel_mode: &TravelMode,
+        departure_time: Option<i64>,
+    ) -> Result<Option<i32>>;
+}
+
+#[async_trait]
+impl TravelTimeProvider for Box<dyn MapsProvider> {
+    async fn travel_time(
+        &self,
+        origin: Location,
+        destination: Location,
+        travel_mode: &TravelMode,
+        departure_time: Option<i64>,
+    ) -> Result<Option<i32>> {
+        let response = self
+            .get_directions(
+                origin.lat,
+                origin.lng,
+                destination.lat,
+                destination.lng,
+                travel_mode,
+                departure_time,
+            )
+            .await;
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => match error.downcast_ref::<GoogleApiError>() {
+                // There is no route, or one of the points couldn't be snapped to a road
+                Some(api_error) if api_error.is_answer() => return Ok(None),
+                _ => return Err(error),
+            },
+        };
+        let leg_duration = |leg: &Leg| match (&leg.duration_in_traffic, departure_time) {
+            (Some(duration_in_traffic), Some(_)) => duration_in_traffic.value,
+            _ => leg.duration.value,
+        };
+        let duration = response
+            .routes
+            .first()
+            .map(|route| route.legs.iter().map(leg_duration).sum());
+        Ok(duration)
+    }
+}
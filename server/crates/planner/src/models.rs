@@ -1,7 +1,25 @@
 use maps::models::{Location, TravelMode};
+use serde::Serialize;
 
 pub struct TargetLocation {
     pub address: String,
     pub travel_mode: TravelMode,
     pub location: Location,
 }
+
+#[derive(Serialize, Debug)]
+pub struct CommuteReport {
+    pub address: String,
+    pub location: Location,
+    pub commutes: Vec<Commute>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Commute {
+    pub target_address: String,
+    pub travel_mode: TravelMode,
+    pub duration_seconds: i32,
+    pub distance_meters: i32,
+    // Google's encoded polyline format
+    pub polyline: String,
+}
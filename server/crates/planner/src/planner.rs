@@ -1,25 +1,49 @@
-use super::models::TargetLocation;
+use super::models::{Commute, CommuteReport, TargetLocation};
 use anyhow::{Context, Result};
 use houses::models::House;
-use maps::maps_client::GoogleMapsClient;
-use maps::models::TravelMode;
+use maps::geocode_cache::{CacheStats, Geocoder};
+use maps::maps_provider::MapsProvider;
+use maps::models::{Location, TravelMode};
+use std::io::Write;
+
+const ROUTE_COLORS: [&str; 3] = ["0xff0000ff", "0x00ff00ff", "0x0000ffff"];
 
 pub struct Planner {
-    maps_client: GoogleMapsClient,
+    maps_client: Box<dyn MapsProvider>,
+    geocoder: Geocoder,
     targets: Vec<TargetLocation>,
 }
 
 impl Planner {
-    pub fn new(maps_client: GoogleMapsClient) -> Self {
+    pub fn new(maps_client: Box<dyn MapsProvider>, geocoder: Geocoder) -> Self {
         Planner {
             maps_client,
+            geocoder,
             targets: vec![],
         }
     }
 
+    pub fn geocode_stats(&self) -> CacheStats {
+        self.geocoder.stats()
+    }
+
+    // Houses that already have coordinates are never geocoded
+    async fn locate_house(&self, house: &House) -> Result<Location> {
+        match (house.lat, house.lon) {
+            (Some(lat), Some(lng)) => Ok(Location { lat, lng }),
+            _ => {
+                self.geocoder
+                    .locate(self.maps_client.as_ref(), &house.address)
+                    .await
+            }
+        }
+    }
+
     pub async fn add_target(&mut self, address: &str, travel_mode: TravelMode) -> Result<()> {
-        let geocode_response = self.maps_client.geocode(address).await?;
-        let location = geocode_response.results[0].geometry.location;
+        let location = self
+            .geocoder
+            .locate(self.maps_client.as_ref(), address)
+            .await?;
         self.targets.push(TargetLocation {
             address: address.to_string(),
             travel_mode,
@@ -28,57 +52,137 @@ impl Planner {
         Ok(())
     }
 
-    pub async fn plan(&self, house: &House) -> Result<()> {
-        let house_location = self
-            .maps_client
-            .geocode(&house.address)
-            .await?
-            .results
-            .first()
-            .context("No results found for the origin address.")?
-            .geometry
-            .location;
-
-        let lat = house_location.lat;
-        let lon = house_location.lng;
-        let mut paths = vec![];
-        let colors = vec![
-            "0xff0000ff".to_string(),
-            "0x00ff00ff".to_string(),
-            "0x0000ffff".to_string(),
-        ];
-
+    pub async fn plan(&self, house: &House) -> Result<CommuteReport> {
+        let location = self.locate_house(house).await?;
+        let mut commutes = vec![];
         for target in &self.targets {
             let directions_response = self
                 .maps_client
                 .get_directions(
-                    lat,
-                    lon,
+                    location.lat,
+                    location.lng,
                     target.location.lat,
                     target.location.lng,
                     &target.travel_mode,
+                    None,
                 )
                 .await?;
-            let duration = &directions_response.routes[0].legs[0].duration.text;
-            println!("Time to {}: {}", target.address, duration);
-            let overview_polyline = &directions_response.routes[0].overview_polyline.points;
-            paths.push(overview_polyline.clone());
+            let route = directions_response
+                .routes
+                .first()
+                .with_context(|| format!("No route found to {}", target.address))?;
+            commutes.push(Commute {
+                target_address: target.address.clone(),
+                travel_mode: target.travel_mode,
+                duration_seconds: route.legs.iter().map(|leg| leg.duration.value).sum(),
+                distance_meters: route.legs.iter().map(|leg| leg.distance.value).sum(),
+                polyline: route.overview_polyline.points.clone(),
+            });
         }
+        Ok(CommuteReport {
+            address: house.address.clone(),
+            location,
+            commutes,
+        })
+    }
 
+    // Writes a PNG of every commute in the report, each route in its own colour
+    pub async fn render_static_map<W: Write + Send>(
+        &self,
+        report: &CommuteReport,
+        sink: &mut W,
+    ) -> Result<()> {
+        let paths: Vec<String> = report
+            .commutes
+            .iter()
+            .map(|commute| commute.polyline.clone())
+            .collect();
+        let colors: Vec<String> = ROUTE_COLORS
+            .iter()
+            .cycle()
+            .take(paths.len())
+            .map(|color| color.to_string())
+            .collect();
         let map_data = self
             .maps_client
             .fetch_combined_static_map_data(&paths, &colors)
             .await?;
-        let file_name = format!(
-            "{}.png",
-            house
-                .address
-                .replace(" ", "_")
-                .replace(",", "")
-                .replace("/", "_")
+        sink.write_all(&map_data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maps::fixture_maps_client::FixtureMapsClient;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_add_target() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("../maps/fixtures");
+        let mut planner = Planner::new(
+            Box::new(FixtureMapsClient::replay(fixtures)),
+            Geocoder::default(),
         );
-        self.maps_client.save_map_to_file(&map_data, &file_name)?;
+        planner
+            .add_target(
+                "Adelaide Central Market, Adelaide SA, Australia",
+                TravelMode::Driving,
+            )
+            .await
+            .unwrap();
+        let location = planner.targets[0].location;
+        assert_eq!((location.lat, location.lng), (-34.9298, 138.5966));
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn test_stored_coordinates_are_not_geocoded() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("../maps/fixtures");
+        let planner = Planner::new(
+            Box::new(FixtureMapsClient::replay(fixtures)),
+            Geocoder::default(),
+        );
+        let house = House {
+            id: 1,
+            address: "Somewhere not recorded".to_string(),
+            url: "".to_string(),
+            lat: Some(-34.92),
+            lon: Some(138.6),
+        };
+        let location = planner.locate_house(&house).await.unwrap();
+        assert_eq!((location.lat, location.lng), (-34.92, 138.6));
+        assert_eq!(planner.geocode_stats(), CacheStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_plan() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("../maps/fixtures");
+        let mut planner = Planner::new(
+            Box::new(FixtureMapsClient::replay(fixtures)),
+            Geocoder::default(),
+        );
+        planner.targets.push(TargetLocation {
+            address: "Adelaide Airport".to_string(),
+            travel_mode: TravelMode::Driving,
+            location: Location {
+                lat: -34.9455,
+                lng: 138.5306,
+            },
+        });
+        let house = House {
+            id: 1,
+            address: "Adelaide Central Market".to_string(),
+            url: "".to_string(),
+            lat: Some(-34.9298),
+            lon: Some(138.5966),
+        };
+        let report = planner.plan(&house).await.unwrap();
+        let commute = &report.commutes[0];
+        assert_eq!(
+            (commute.duration_seconds, commute.distance_meters),
+            (846, 7212)
+        );
+        assert_eq!(commute.polyline, "~fxtEkfkcYbAvMnBpYbCl_@");
     }
 }
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Records each finished pair as a "source,destination" line, so a rerun can skip pairs
+// that were written even if they aren't visible when listing the table yet
+pub struct Checkpoint {
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open checkpoint {}", path.display()))?;
+        Ok(Checkpoint {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<HashSet<(String, String)>> {
+        if !path.exists() {
+            return Ok(HashSet::new());
+        }
+        let contents = fs::read_to_string(path)?;
+        let pairs = contents
+            .lines()
+            .filter_map(|line| line.split_once(','))
+            .map(|(source, destination)| (source.to_string(), destination.to_string()))
+            .collect();
+        Ok(pairs)
+    }
+
+    pub fn record(&self, source_index: &str, destination_index: &str) -> Result<()> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Checkpoint lock poisoned"))?;
+        writeln!(file, "{},{}", source_index, destination_index)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn checkpoint_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "populate_distances_{}_{}.checkpoint",
+            name,
+            std::process::id()
+        ));
+        fs::remove_file(&path).ok();
+        path
+    }
+
+    #[test]
+    fn test_missing_checkpoint_is_empty() {
+        let path = checkpoint_path("missing");
+        assert!(Checkpoint::load(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_records_are_loaded() {
+        let path = checkpoint_path("records");
+        let checkpoint = Checkpoint::open(&path).unwrap();
+        checkpoint
+            .record("87b916800ffffff", "87b916804ffffff")
+            .unwrap();
+        checkpoint
+            .record("87b916804ffffff", "87b916800ffffff")
+            .unwrap();
+        // Reopening appends rather than truncating
+        drop(checkpoint);
+        let checkpoint = Checkpoint::open(&path).unwrap();
+        checkpoint
+            .record("87b916801ffffff", "87b916800ffffff")
+            .unwrap();
+        let pairs = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let expected = [
+            ("87b916800ffffff", "87b916804ffffff"),
+            ("87b916804ffffff", "87b916800ffffff"),
+            ("87b916801ffffff", "87b916800ffffff"),
+        ];
+        let expected: HashSet<(String, String)> = expected
+            .into_iter()
+            .map(|(source, destination)| (source.to_string(), destination.to_string()))
+            .collect();
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn test_partial_lines_are_skipped() {
+        let path = checkpoint_path("partial");
+        fs::write(&path, "87b916800ffffff,87b916804ffffff\n87b9168").unwrap();
+        let pairs = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(pairs.len(), 1);
+    }
+}
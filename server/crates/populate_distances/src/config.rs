@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use chrono_tz::Tz;
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub enum RoutingBackend {
+    Google,
+    // Routes over a local OpenStreetMap extract, without public transport
+    Osm { extract_path: PathBuf },
+}
+
+pub struct Config {
+    pub city_code: String,
+    // Departure times for each time window are local to the city
+    pub timezone: Tz,
+    pub requests_per_second: u32,
+    pub max_concurrent_pairs: usize,
+    pub max_retries: u32,
+    pub checkpoint_path: PathBuf,
+    pub include_time_windows: bool,
+    pub routing_backend: RoutingBackend,
+    // Public transport is routed over this GTFS feed instead of the routing backend
+    pub gtfs_feed_dir: Option<PathBuf>,
+    pub dry_run: bool,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self> {
+        let timezone = env_or("CITY_TIMEZONE", "Australia/Adelaide".to_string())?;
+        let routing_backend = match env_or("ROUTING_BACKEND", "google".to_string())?.as_str() {
+            "google" => RoutingBackend::Google,
+            "osm" => RoutingBackend::Osm {
+                extract_path: env::var("OSM_EXTRACT_PATH")
+                    .context("OSM_EXTRACT_PATH must be set for the osm backend")?
+                    .into(),
+            },
+            backend => return Err(anyhow::anyhow!("Unknown routing backend {}", backend)),
+        };
+        let gtfs_feed_dir = env::var("GTFS_FEED_DIR").ok().map(PathBuf::from);
+        let config = Config {
+            city_code: env_or("CITY_CODE", "Adelaide".to_string())?,
+            timezone: Tz::from_str(&timezone)
+                .map_err(|_| anyhow::anyhow!("Unknown timezone {}", timezone))?,
+            requests_per_second: env_or("REQUESTS_PER_SECOND", 10)?,
+            max_concurrent_pairs: env_or("MAX_CONCURRENT_PAIRS", 4)?,
+            max_retries: env_or("MAX_RETRIES", 5)?,
+            checkpoint_path: env_or(
+                "CHECKPOINT_PATH",
+                PathBuf::from("populate_distances.checkpoint"),
+            )?,
+            // Offline driving doesn't depend on the time of day, but timetables do
+            include_time_windows: env_or("INCLUDE_TIME_WINDOWS", true)?
+                && (matches!(routing_backend, RoutingBackend::Google) || gtfs_feed_dir.is_some()),
+            routing_backend,
+            gtfs_feed_dir,
+            dry_run: env::args().any(|arg| arg == "--dry-run"),
+        };
+        if config.requests_per_second == 0 || config.max_concurrent_pairs == 0 {
+            return Err(anyhow::anyhow!(
+                "REQUESTS_PER_SECOND and MAX_CONCURRENT_PAIRS must be positive"
+            ));
+        }
+        Ok(config)
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> Result<T> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse::<T>()
+            .ok()
+            .with_context(|| format!("Invalid value for {}: {}", name, value)),
+        Err(_) => Ok(default),
+    }
+}
@@ -1,121 +1,427 @@
-use anyhow::Result;
-// use anyhow::{Context, Result};
-// use database::dynamodb_client_cloud::DynamoDbClient;
-// use database::dynamodb_client_trait::IDynamoDbClient;
-// use database::spatial_distance_item::SpatialDistanceItem;
-// use dotenv::from_path;
-// use h3_mapper::h3_client::H3Client;
-// use h3o::LatLng;
-// use maps::{maps_client::GoogleMapsClient, models::TravelMode};
-// use std::{collections::HashSet, env};
+mod checkpoint;
+mod config;
+mod rate_limiter;
+
+use anyhow::{Context, Result};
+use checkpoint::Checkpoint;
+use chrono::Utc;
+use config::{Config, RoutingBackend};
+#[cfg(feature = "cloud")]
+use database::dynamodb_client_cloud::DynamoDbClient;
+#[cfg(feature = "local")]
+use database::dynamodb_client_local::DynamoDbClient;
+use database::dynamodb_client_trait::IDynamoDbClient;
+use database::spatial_distance_item::{SpatialDistanceItem, TimeWindow, WindowDurations};
+use dotenv::from_path;
+use futures::stream::{self, StreamExt};
+use h3_mapper::h3_client::H3Client;
+use h3o::{CellIndex, LatLng};
+use maps::errors::is_retryable;
+use maps::gtfs::raptor::GtfsRouter;
+use maps::maps_provider::maps_provider_from_env;
+use maps::models::{Location, TravelMode};
+use maps::osm_router::OsmRouter;
+use maps::travel_time::TravelTimeProvider;
+use rate_limiter::RateLimiter;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::time::Duration;
+
+// USD per request at Google's list price. Driving with a departure time uses live traffic,
+// which is billed at the advanced rate.
+const COST_PER_REQUEST: f64 = 0.005;
+const COST_PER_TRAFFIC_REQUEST: f64 = 0.01;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const NO_ROUTE: i32 = -1;
+
+struct Job {
+    config: Config,
+    travel_times: Box<dyn TravelTimeProvider>,
+    db_client: DynamoDbClient,
+    // Only requests to paid APIs are rate limited
+    rate_limiter: Option<RateLimiter>,
+    transit_times: Option<GtfsRouter>,
+    // Timetables need a departure time even when no time window is asked for
+    transit_departure_time: Option<i64>,
+    checkpoint: Checkpoint,
+    departure_times: Vec<(TimeWindow, i64)>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // let city_code = "Adelaide";
-    // let env_path = env::current_dir()?.join("server/.env");
-    // from_path(env_path).ok();
-    // let maps_api_key =
-    //     env::var("GOOGLE_MAPS_API_KEY").context("GOOGLE_MAPS_API_KEY must be set")?;
-    // let maps_client = GoogleMapsClient::new(maps_api_key);
-    // let db_client = DynamoDbClient::new().await?;
-    // let h3_client = H3Client::new();
-    // let existing_items = SpatialDistanceItem::list_from_db(city_code, &db_client)
-    //     .await
-    //     .context("Failed to list items")?;
-    // let existing_pairs: HashSet<(String, String)> = existing_items
-    //     .into_iter()
-    //     .map(|item| (item.source_index, item.destination_index))
-    //     .collect();
-    // let cells = h3_client.get_indices_for_city(&city_code)?;
-    // println!("Num cells: {}", cells.len());
-    // let num_cells = cells.len();
-    // for i in 0..num_cells - 1 {
-    //     println!("Processing cell {}", i);
-    //     let source_index = &cells[i].to_string();
-    //     let source_lat_lng: LatLng = cells[i].into();
-    //     for j in i + 1..num_cells {
-    //         let destination_index = &cells[j].to_string();
-    //         let destination_lat_lng: LatLng = cells[j].into();
-    //         if existing_pairs.contains(&(source_index.clone(), destination_index.clone())) {
-    //             continue;
-    //         }
-    //         let duration_drive = get_duration(
-    //             maps_client
-    //                 .get_directions(
-    //                     source_lat_lng.lat(),
-    //                     source_lat_lng.lng(),
-    //                     destination_lat_lng.lat(),
-    //                     destination_lat_lng.lng(),
-    //                     &TravelMode::Driving,
-    //                 )
-    //                 .await?,
-    //         );
-    //         let duration_walk = get_duration(
-    //             maps_client
-    //                 .get_directions(
-    //                     source_lat_lng.lat(),
-    //                     source_lat_lng.lng(),
-    //                     destination_lat_lng.lat(),
-    //                     destination_lat_lng.lng(),
-    //                     &TravelMode::Walking,
-    //                 )
-    //                 .await?,
-    //         );
-    //         let duration_cycle = get_duration(
-    //             maps_client
-    //                 .get_directions(
-    //                     source_lat_lng.lat(),
-    //                     source_lat_lng.lng(),
-    //                     destination_lat_lng.lat(),
-    //                     destination_lat_lng.lng(),
-    //                     &TravelMode::Bicycling,
-    //                 )
-    //                 .await?,
-    //         );
-    //         let duration_transit = get_duration(
-    //             maps_client
-    //                 .get_directions(
-    //                     source_lat_lng.lat(),
-    //                     source_lat_lng.lng(),
-    //                     destination_lat_lng.lat(),
-    //                     destination_lat_lng.lng(),
-    //                     &TravelMode::Transit,
-    //                 )
-    //                 .await?,
-    //         );
-    //         let item_forward = SpatialDistanceItem {
-    //             city_code: city_code.to_string(),
-    //             source_index: source_index.to_string(),
-    //             destination_index: destination_index.to_string(),
-    //             duration_cycle: duration_cycle,
-    //             duration_drive: duration_drive,
-    //             duration_transit: duration_transit,
-    //             duration_walk: duration_walk,
-    //         };
-    //         let item_reverse = SpatialDistanceItem {
-    //             city_code: city_code.to_string(),
-    //             source_index: destination_index.to_string(),
-    //             destination_index: source_index.to_string(),
-    //             duration_cycle: duration_cycle,
-    //             duration_drive: duration_drive,
-    //             duration_transit: duration_transit,
-    //             duration_walk: duration_walk,
-    //         };
-    //         db_client
-    //             .write(vec![item_forward.save()?, item_reverse.save()?])
-    //             .await?;
-    //     }
-    // }
+    let env_path = env::current_dir()?.join("server/.env");
+    from_path(env_path).ok();
+    let config = Config::from_env()?;
+    let db_client = DynamoDbClient::new().await?;
+    let h3_client = H3Client::new()?;
+
+    let mut existing_pairs = Checkpoint::load(&config.checkpoint_path)?;
+    let existing_items = SpatialDistanceItem::list_by_city_from_db(&config.city_code, &db_client)
+        .await
+        .context("Failed to list items")?;
+    existing_pairs.extend(
+        existing_items
+            .into_iter()
+            .map(|item| (item.source_index, item.destination_index)),
+    );
+    let pairs = get_missing_pairs(
+        &h3_client.get_cell_indices_for_city(&config.city_code)?,
+        &existing_pairs,
+    );
+    print_estimate(&config, pairs.len());
+    if config.dry_run {
+        return Ok(());
+    }
+
+    let (travel_times, rate_limiter): (Box<dyn TravelTimeProvider>, _) =
+        match &config.routing_backend {
+            RoutingBackend::Google => (
+                Box::new(maps_provider_from_env()?),
+                Some(RateLimiter::new(config.requests_per_second)),
+            ),
+            RoutingBackend::Osm { extract_path } => {
+                println!("Loading {}", extract_path.display());
+                (Box::new(OsmRouter::from_file(extract_path)?), None)
+            }
+        };
+    let transit_times = match &config.gtfs_feed_dir {
+        Some(gtfs_feed_dir) => {
+            println!("Loading {}", gtfs_feed_dir.display());
+            Some(GtfsRouter::from_directory(gtfs_feed_dir)?)
+        }
+        None => None,
+    };
+    let now = Utc::now().with_timezone(&config.timezone);
+    let all_departure_times: BTreeMap<TimeWindow, i64> = TimeWindow::ALL
+        .into_iter()
+        .filter_map(|window| Some((window, window.next_departure(&now)?.timestamp())))
+        .collect();
+    let transit_departure_time = match transit_times {
+        Some(_) => all_departure_times.get(&TimeWindow::OffPeak).copied(),
+        None => None,
+    };
+    let departure_times = match config.include_time_windows {
+        true => all_departure_times.into_iter().collect(),
+        false => vec![],
+    };
+    let job = Job {
+        travel_times,
+        db_client,
+        rate_limiter,
+        transit_times,
+        transit_departure_time,
+        checkpoint: Checkpoint::open(&config.checkpoint_path)?,
+        departure_times,
+        config,
+    };
+
+    let num_pairs = pairs.len();
+    let mut num_done = 0;
+    let mut num_failed = 0;
+    let mut results = stream::iter(pairs)
+        .map(|(source, destination)| job.populate_pair(source, destination))
+        .buffer_unordered(job.config.max_concurrent_pairs);
+    while let Some(result) = results.next().await {
+        num_done += 1;
+        if let Err(error) = result {
+            num_failed += 1;
+            eprintln!("{:#}", error);
+        }
+        if num_done % 100 == 0 || num_done == num_pairs {
+            println!("Processed {}/{} pairs", num_done, num_pairs);
+        }
+    }
+    if num_failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} pairs failed, rerun to retry them",
+            num_failed
+        ));
+    }
     Ok(())
 }
 
-// fn get_duration(response: maps::models::DirectionsResponse) -> i32 {
-//     let mut total_duration: i32 = 0;
-//     if response.routes.is_empty() {
-//         return i32::MAX;
-//     }
-//     for leg in &response.routes[0].legs {
-//         total_duration += leg.duration.value;
-//     }
-//     total_duration
-// }
+// Each unordered pair is routed once, and is missing if either direction hasn't been saved
+fn get_missing_pairs(
+    cells: &[CellIndex],
+    existing_pairs: &HashSet<(String, String)>,
+) -> Vec<(CellIndex, CellIndex)> {
+    let mut pairs = vec![];
+    for (i, source) in cells.iter().enumerate() {
+        for destination in &cells[i + 1..] {
+            let forward = (source.to_string(), destination.to_string());
+            let reverse = (destination.to_string(), source.to_string());
+            if !existing_pairs.contains(&forward) || !existing_pairs.contains(&reverse) {
+                pairs.push((*source, *destination));
+            }
+        }
+    }
+    pairs
+}
+
+fn to_location(cell: CellIndex) -> Location {
+    let lat_lng: LatLng = cell.into();
+    Location {
+        lat: lat_lng.lat(),
+        lng: lat_lng.lng(),
+    }
+}
+
+struct Estimate {
+    num_routes: usize,
+    // Requests to paid APIs, none of which are made offline
+    num_requests: usize,
+    num_traffic_requests: usize,
+    cost: f64,
+}
+
+fn estimate(config: &Config, num_pairs: usize) -> Estimate {
+    let num_windows = match config.include_time_windows {
+        true => TimeWindow::ALL.len(),
+        false => 0,
+    };
+    // Walking and cycling once, then driving and transit both ways, undated and for each window
+    let num_directed_routes = num_pairs * 2 * (1 + num_windows);
+    let num_routes = num_pairs * 2 + num_directed_routes * 2;
+    let (num_requests, num_traffic_requests) = match config.routing_backend {
+        RoutingBackend::Osm { .. } => (0, 0),
+        RoutingBackend::Google => {
+            let num_transit_requests = match config.gtfs_feed_dir {
+                Some(_) => 0,
+                None => num_directed_routes,
+            };
+            (
+                num_pairs * 2 + num_directed_routes + num_transit_requests,
+                num_pairs * 2 * num_windows,
+            )
+        }
+    };
+    let cost = (num_requests - num_traffic_requests) as f64 * COST_PER_REQUEST
+        + num_traffic_requests as f64 * COST_PER_TRAFFIC_REQUEST;
+    Estimate {
+        num_routes,
+        num_requests,
+        num_traffic_requests,
+        cost,
+    }
+}
+
+fn print_estimate(config: &Config, num_pairs: usize) {
+    let estimate = estimate(config, num_pairs);
+    println!("City: {}", config.city_code);
+    println!("Pairs to populate: {}", num_pairs);
+    println!("Routes: {}", estimate.num_routes);
+    if let RoutingBackend::Osm { .. } = config.routing_backend {
+        println!("Estimated cost: $0.00 USD (offline)");
+        return;
+    }
+    let hours = estimate.num_requests as f64 / config.requests_per_second as f64 / 3600.0;
+    println!(
+        "Directions requests: {} ({} with traffic)",
+        estimate.num_requests, estimate.num_traffic_requests
+    );
+    println!("Estimated cost: ${:.2} USD", estimate.cost);
+    println!("Estimated time: {:.1} hours", hours);
+}
+
+// Durations that differ by direction, with one-way streets and timetables
+struct DirectedDurations {
+    duration_drive: i32,
+    duration_transit: i32,
+    window_durations: BTreeMap<TimeWindow, WindowDurations>,
+}
+
+impl Job {
+    async fn populate_pair(&self, source: CellIndex, destination: CellIndex) -> Result<()> {
+        let origin = to_location(source);
+        let target = to_location(destination);
+        // Walking and cycling are taken to be the same both ways
+        let duration_walk = self
+            .get_duration(origin, target, &TravelMode::Walking, None)
+            .await?;
+        let duration_cycle = self
+            .get_duration(origin, target, &TravelMode::Bicycling, None)
+            .await?;
+        let forward = self.get_directed_durations(origin, target).await?;
+        let reverse = self.get_directed_durations(target, origin).await?;
+        let item_forward = SpatialDistanceItem {
+            city_code: self.config.city_code.clone(),
+            source_index: source.to_string(),
+            destination_index: destination.to_string(),
+            duration_walk,
+            duration_cycle,
+            duration_drive: forward.duration_drive,
+            duration_transit: forward.duration_transit,
+            window_durations: forward.window_durations,
+        };
+        let item_reverse = SpatialDistanceItem {
+            source_index: destination.to_string(),
+            destination_index: source.to_string(),
+            duration_drive: reverse.duration_drive,
+            duration_transit: reverse.duration_transit,
+            window_durations: reverse.window_durations,
+            ..item_forward.clone()
+        };
+        self.db_client
+            .write(vec![item_forward.save()?, item_reverse.save()?])
+            .await
+            .with_context(|| format!("Failed to save {} -> {}", source, destination))?;
+        self.checkpoint
+            .record(&item_forward.source_index, &item_forward.destination_index)?;
+        self.checkpoint
+            .record(&item_reverse.source_index, &item_reverse.destination_index)?;
+        Ok(())
+    }
+
+    async fn get_directed_durations(
+        &self,
+        origin: Location,
+        target: Location,
+    ) -> Result<DirectedDurations> {
+        let duration_drive = self
+            .get_duration(origin, target, &TravelMode::Driving, None)
+            .await?;
+        let duration_transit = self
+            .get_duration(
+                origin,
+                target,
+                &TravelMode::Transit,
+                self.transit_departure_time,
+            )
+            .await?;
+        let mut window_durations = BTreeMap::new();
+        for (window, departure_time) in &self.departure_times {
+            let durations = WindowDurations {
+                duration_drive: self
+                    .get_duration(origin, target, &TravelMode::Driving, Some(*departure_time))
+                    .await?,
+                duration_transit: self
+                    .get_duration(origin, target, &TravelMode::Transit, Some(*departure_time))
+                    .await?,
+            };
+            window_durations.insert(*window, durations);
+        }
+        Ok(DirectedDurations {
+            duration_drive,
+            duration_transit,
+            window_durations,
+        })
+    }
+
+    async fn get_duration(
+        &self,
+        origin: Location,
+        destination: Location,
+        travel_mode: &TravelMode,
+        departure_time: Option<i64>,
+    ) -> Result<i32> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        let (travel_times, rate_limiter): (&dyn TravelTimeProvider, _) =
+            match (travel_mode, &self.transit_times) {
+                (TravelMode::Transit, Some(transit_times)) => (transit_times, None),
+                _ => (self.travel_times.as_ref(), self.rate_limiter.as_ref()),
+            };
+        loop {
+            if let Some(rate_limiter) = rate_limiter {
+                rate_limiter.wait().await;
+            }
+            let response = travel_times
+                .travel_time(origin, destination, travel_mode, departure_time)
+                .await;
+            match response {
+                // Stored when there is no route, which the map endpoint treats as unknown
+                Ok(duration) => return Ok(duration.unwrap_or(NO_ROUTE)),
+                // Requests the API rejected, like INVALID_REQUEST, would fail again
+                Err(error) if attempt < self.config.max_retries && is_retryable(&error) => {
+                    eprintln!(
+                        "Retrying {} directions in {:?}: {:#}",
+                        travel_mode.as_str(),
+                        backoff,
+                        error
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(error) => {
+                    return Err(error.context(format!(
+                        "Failed to get {} directions after {} attempts",
+                        travel_mode.as_str(),
+                        attempt + 1
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn config(routing_backend: RoutingBackend, gtfs_feed_dir: Option<&str>) -> Config {
+        Config {
+            city_code: "Adelaide".to_string(),
+            timezone: chrono_tz::Australia::Adelaide,
+            requests_per_second: 10,
+            max_concurrent_pairs: 4,
+            max_retries: 5,
+            checkpoint_path: PathBuf::from("populate_distances.checkpoint"),
+            include_time_windows: true,
+            routing_backend,
+            gtfs_feed_dir: gtfs_feed_dir.map(PathBuf::from),
+            dry_run: true,
+        }
+    }
+
+    #[test]
+    fn test_missing_pairs() {
+        let h3_client = H3Client::new().unwrap();
+        let cells = &h3_client.get_cell_indices_for_city("Adelaide").unwrap()[..3];
+        let pair = |source: usize, destination: usize| {
+            (cells[source].to_string(), cells[destination].to_string())
+        };
+        // 0 and 1 are done, and only one direction of 0 and 2 was recorded
+        let existing_pairs = HashSet::from([pair(0, 1), pair(1, 0), pair(2, 0)]);
+        let pairs = get_missing_pairs(cells, &existing_pairs);
+        assert_eq!(pairs, vec![(cells[0], cells[2]), (cells[1], cells[2])]);
+    }
+
+    #[test]
+    fn test_google_estimate() {
+        let num_windows = TimeWindow::ALL.len();
+        let estimate = estimate(&config(RoutingBackend::Google, None), 10);
+        // Walking and cycling, then driving and transit both ways for no window and each window
+        assert_eq!(estimate.num_routes, 10 * (2 + 4 * (1 + num_windows)));
+        assert_eq!(estimate.num_requests, estimate.num_routes);
+        assert_eq!(estimate.num_traffic_requests, 10 * 2 * num_windows);
+        let num_standard_requests = estimate.num_requests - estimate.num_traffic_requests;
+        let cost = num_standard_requests as f64 * COST_PER_REQUEST
+            + estimate.num_traffic_requests as f64 * COST_PER_TRAFFIC_REQUEST;
+        assert!((estimate.cost - cost).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_gtfs_transit_is_free() {
+        let num_windows = TimeWindow::ALL.len();
+        let estimate = estimate(&config(RoutingBackend::Google, Some("gtfs")), 10);
+        assert_eq!(estimate.num_requests, 10 * (2 + 2 * (1 + num_windows)));
+        assert_eq!(estimate.num_traffic_requests, 10 * 2 * num_windows);
+    }
+
+    #[test]
+    fn test_osm_estimate() {
+        let mut config = config(
+            RoutingBackend::Osm {
+                extract_path: PathBuf::from("extract.osm"),
+            },
+            None,
+        );
+        config.include_time_windows = false;
+        let estimate = estimate(&config, 10);
+        assert_eq!(estimate.num_routes, 10 * 6);
+        assert_eq!(estimate.num_requests, 0);
+        assert_eq!(estimate.cost, 0.0);
+    }
+}
@@ -0,0 +1,46 @@
+use tokio::sync::Mutex;
+use tokio::time::{interval, Duration, Interval, MissedTickBehavior};
+
+// Spaces requests evenly, however many tasks are waiting
+pub struct RateLimiter {
+    interval: Mutex<Interval>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32) -> Self {
+        let mut interval = interval(Duration::from_secs(1) / requests_per_second);
+        // Don't burst to catch up after a slow period
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        RateLimiter {
+            interval: Mutex::new(interval),
+        }
+    }
+
+    pub async fn wait(&self) {
+        self.interval.lock().await.tick().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::time::Instant;
+
+    #[tokio::test]
+    async fn test_spaces_requests() {
+        let rate_limiter = Arc::new(RateLimiter::new(20));
+        let start = Instant::now();
+        // The first request goes straight away, then one every 50ms across all the tasks
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let rate_limiter = rate_limiter.clone();
+                tokio::spawn(async move { rate_limiter.wait().await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
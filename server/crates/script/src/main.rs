@@ -1,20 +1,32 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
+#[cfg(feature = "cloud")]
+use database::dynamodb_client_cloud::DynamoDbClient;
+#[cfg(feature = "local")]
+use database::dynamodb_client_local::DynamoDbClient;
 use dotenv::from_path;
 use houses::house_client::HouseClient;
-use maps::maps_client::GoogleMapsClient;
+use maps::geocode_cache::{DynamoDbGeocodeCache, GeocodeCache, Geocoder, InMemoryGeocodeCache};
+use maps::maps_provider::maps_provider_from_env;
 use maps::models::TravelMode;
 use planner::planner::Planner;
 use std::env;
+use std::fs::File;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let env_path = env::current_dir()?.join("server/.env");
     from_path(env_path).ok();
-    let maps_api_key =
-        env::var("GOOGLE_MAPS_API_KEY").context("GOOGLE_MAPS_API_KEY must be set")?;
     let house_client = HouseClient::new();
-    let maps_client = GoogleMapsClient::new(maps_api_key);
-    let mut planner = Planner::new(maps_client);
+    let maps_client = maps_provider_from_env()?;
+    // Shares geocoded addresses with ingest_houses when the cache table is configured
+    let cache: Box<dyn GeocodeCache> = match env::var("GEOCODE_CACHE_TABLE_NAME") {
+        Ok(_) => Box::new(DynamoDbGeocodeCache::new(Arc::new(
+            DynamoDbClient::new().await?,
+        ))),
+        Err(_) => Box::new(InMemoryGeocodeCache::default()),
+    };
+    let mut planner = Planner::new(maps_client, Geocoder::new(cache));
 
     let houses = house_client.get_houses(1, 10)?;
     planner
@@ -30,7 +42,24 @@ async fn main() -> Result<()> {
         )
         .await?;
 
-    planner.plan(&houses[0]).await?;
+    let report = planner.plan(&houses[0]).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    let file_name = format!(
+        "{}.png",
+        report
+            .address
+            .replace(" ", "_")
+            .replace(",", "")
+            .replace("/", "_")
+    );
+    let mut file = File::create(&file_name)?;
+    planner.render_static_map(&report, &mut file).await?;
+    println!("Map image saved as {}", file_name);
+    let stats = planner.geocode_stats();
+    println!(
+        "Geocode cache: {} hits, {} misses",
+        stats.hits, stats.misses
+    );
 
     Ok(())
 }
@@ -75,3 +75,17 @@ resource "aws_dynamodb_table" "houses" {
     enabled        = true
   }
 }
+
+resource "aws_dynamodb_table" "geocode_cache" {
+  name         = "${local.prefix}-GeocodeCache"
+  billing_mode = "PAY_PER_REQUEST"
+  hash_key     = "AddressKey"
+  attribute {
+    name = "AddressKey"
+    type = "S"
+  }
+  ttl {
+    attribute_name = "TimeToLive"
+    enabled        = true
+  }
+}
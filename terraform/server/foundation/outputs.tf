@@ -34,3 +34,15 @@ resource "aws_ssm_parameter" "houses_table_arn" {
   type  = "String"
   value = aws_dynamodb_table.houses.arn
 }
+
+resource "aws_ssm_parameter" "geocode_cache_table_name" {
+  name  = "${local.prefix_parameter}/DynamoDB/GeocodeCacheTable/Name"
+  type  = "String"
+  value = aws_dynamodb_table.geocode_cache.name
+}
+
+resource "aws_ssm_parameter" "geocode_cache_table_arn" {
+  name  = "${local.prefix_parameter}/DynamoDB/GeocodeCacheTable/Arn"
+  type  = "String"
+  value = aws_dynamodb_table.geocode_cache.arn
+}
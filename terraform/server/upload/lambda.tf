@@ -53,6 +53,7 @@ data "aws_iam_policy_document" "access_dynamodb" {
   statement {
     actions = [
       # "dynamodb:ConditionCheckItem",
+      "dynamodb:BatchGetItem",
       "dynamodb:DeleteItem",
       "dynamodb:GetItem",
       "dynamodb:PutItem",